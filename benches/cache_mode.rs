@@ -0,0 +1,61 @@
+//! Compares the two cache modes on large values. `CacheMode::Values` copies the
+//! matched values into the cache, so a hit is a single clone of the cached result.
+//! `CacheMode::Patterns` only keeps the tokens of the matched groups, so the cache
+//! stays small, but a hit walks down to every group again before cloning.
+//! Memory is the growth of live heap bytes while the cache is filled.
+//!
+//! `cargo bench --bench cache_mode`
+
+mod common;
+
+use common::{best_of, live_bytes, Counting, ROUNDS};
+use std::time::{Duration, Instant};
+use trie::token::{CommonTokenParser, TokenParser};
+use trie::{CacheMode, Trie};
+
+const CACHE: usize = 1024;
+const VALUE_SIZE: usize = 1024;
+
+#[global_allocator]
+static ALLOCATOR: Counting = Counting;
+
+// Fills the cache and returns the heap it took, the time of the misses and the best time of all rounds of hits.
+// Changing the mode clears the cache, so every mode starts from an empty cache
+fn measure<'a>(trie: &mut Trie<'a, String, CACHE>, subjects: &[Vec<&'a str>], mode: CacheMode) -> (usize, Duration, Duration) {
+    trie.set_cache_mode(mode);
+    let before = live_bytes();
+    let start = Instant::now();
+    for keys in subjects.iter() {
+        trie.find(keys);
+    }
+    let miss = start.elapsed();
+    let memory = live_bytes() - before;
+    assert_eq!(trie.cache_len(), CACHE);
+    let hit = best_of(|| {
+        let start = Instant::now();
+        for keys in subjects.iter() {
+            trie.find(keys);
+        }
+        start.elapsed()
+    });
+    (memory, miss, hit)
+}
+
+fn main() {
+    let parser = CommonTokenParser::nats();
+    let subjects: Vec<String> = (0..CACHE).map(|i| format!("orders.{}.created", i)).collect();
+    let mut trie = Trie::<_, CACHE>::new();
+    let value = |name: &str| name.repeat(VALUE_SIZE / name.len());
+    for subject in subjects.iter() {
+        trie.insert(&parser.parse_tokens(subject).unwrap(), value(subject));
+    }
+    trie.insert(&parser.parse_tokens("orders.*.created").unwrap(), value("created"));
+    trie.insert(&parser.parse_tokens("orders.>").unwrap(), value("orders"));
+    let subjects: Vec<Vec<&str>> = subjects.iter().map(|s| s.split('.').collect()).collect();
+
+    println!("{} cached results of 3 values of {} bytes, best of {} rounds of hits", CACHE, VALUE_SIZE, ROUNDS);
+    for mode in [CacheMode::Values, CacheMode::Patterns].iter() {
+        let (memory, miss, hit) = measure(&mut trie, &subjects, *mode);
+        println!("{:?}: cache {} KiB, misses {:?}, hits {:?}", mode, memory / 1024, miss, hit);
+    }
+}
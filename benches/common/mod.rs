@@ -0,0 +1,52 @@
+//! Helpers shared by the benches, included with `mod common;`.
+//! Not every bench uses every helper.
+
+#![allow(dead_code)]
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+pub const ROUNDS: usize = 10;
+
+// Runs round ROUNDS times and returns the best of the times it measured.
+// Each round times only its own measured part, so it can prepare untimed first
+pub fn best_of(mut round: impl FnMut() -> Duration) -> Duration {
+    (0..ROUNDS).map(|_| round()).min().unwrap_or(Duration::MAX)
+}
+
+// Runs query on every subject and returns the best time of all rounds
+pub fn time<'s>(subjects: &[Vec<&'s str>], mut query: impl FnMut(&[&'s str]) -> usize) -> Duration {
+    best_of(|| {
+        let start = Instant::now();
+        let mut matched = 0;
+        for keys in subjects.iter() {
+            matched += query(keys);
+        }
+        assert!(matched > 0);
+        start.elapsed()
+    })
+}
+
+// Counts the live heap bytes, install it in a bench with
+// `#[global_allocator] static ALLOCATOR: common::Counting = common::Counting;`
+pub struct Counting;
+
+static LIVE: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        LIVE.fetch_add(layout.size(), Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        LIVE.fetch_sub(layout.size(), Ordering::Relaxed);
+        System.dealloc(ptr, layout)
+    }
+}
+
+// The live heap bytes, only counted when `Counting` is the global allocator
+pub fn live_bytes() -> usize {
+    LIVE.load(Ordering::Relaxed)
+}
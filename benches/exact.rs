@@ -0,0 +1,41 @@
+//! Times `find` on a trie of literal patterns only, which takes the fast path
+//! that only descends normal children, against the same trie with one unrelated
+//! wildcard pattern added, which forces the general wildcard-aware path.
+//!
+//! `cargo bench --bench exact`
+
+mod common;
+
+use common::{time, ROUNDS};
+use trie::token::{CommonTokenParser, TokenParser};
+use trie::Trie;
+
+const REGIONS: usize = 10;
+const SERVICES: usize = 100;
+const INSTANCES: usize = 10;
+
+fn main() {
+    let parser = CommonTokenParser::nats();
+    let patterns: Vec<String> = (0..REGIONS)
+        .flat_map(|r| (0..SERVICES).flat_map(move |s| {
+            (0..INSTANCES).map(move |i| format!("region-{}.service-{}.instance-{}", r, s, i))
+        }))
+        .collect();
+
+    let mut exact = Trie::<_, 0>::new();
+    let mut mixed = Trie::<_, 0>::new();
+    for (v, pattern) in patterns.iter().enumerate() {
+        let tokens = parser.parse_tokens(pattern).unwrap();
+        exact.insert(&tokens, v);
+        mixed.insert(&tokens, v);
+    }
+    // never matches the subjects below, but the trie is no longer wildcard-free
+    mixed.insert(&parser.parse_tokens("other.*").unwrap(), patterns.len());
+
+    let subjects: Vec<Vec<&str>> = patterns.iter().map(|s| s.split('.').collect()).collect();
+    println!("{} subjects, best of {} rounds", subjects.len(), ROUNDS);
+    println!("find,  wildcard-free trie: {:?}", time(&subjects, |keys| exact.find(keys).len()));
+    println!("find,  one wildcard:       {:?}", time(&subjects, |keys| mixed.find(keys).len()));
+    println!("exist, wildcard-free trie: {:?}", time(&subjects, |keys| exact.exist(keys) as usize));
+    println!("exist, one wildcard:       {:?}", time(&subjects, |keys| mixed.exist(keys) as usize));
+}
@@ -0,0 +1,52 @@
+//! Times `find` and `exist` on subjects of three tokens, where each level of a
+//! query matches only a few nodes. Compare the `Vec` frontier with the `SmallVec`
+//! one by running it with and without the feature:
+//!
+//! `cargo bench --bench frontier` and `cargo bench --bench frontier --features smallvec`.
+
+mod common;
+
+use common::{time, ROUNDS};
+use trie::token::{CommonTokenParser, TokenParser};
+use trie::Trie;
+
+const REGIONS: usize = 10;
+const SERVICES: usize = 100;
+const INSTANCES: usize = 10;
+
+fn main() {
+    let parser = CommonTokenParser::nats();
+    let mut patterns = Vec::new();
+    for r in 0..REGIONS {
+        for s in 0..SERVICES {
+            for i in 0..INSTANCES {
+                patterns.push(format!("region-{}.service-{}.instance-{}", r, s, i));
+            }
+            patterns.push(format!("region-{}.service-{}.*", r, s));
+        }
+        patterns.push(format!("region-{}.>", r));
+    }
+    patterns.push(String::from("*.service-0.*"));
+
+    // Literal subjects follow a single path, wildcard subjects also follow `*` and `>`
+    let mut exact = Trie::<_, 0>::new();
+    let mut wildcard = Trie::<_, 0>::new();
+    for (v, pattern) in patterns.iter().enumerate() {
+        let tokens = parser.parse_tokens(pattern).unwrap();
+        if tokens.has_no_wildcard() {
+            exact.insert(&tokens, v);
+        }
+        wildcard.insert(&tokens, v);
+    }
+    let subjects: Vec<String> = (0..REGIONS)
+        .flat_map(|r| (0..SERVICES).map(move |s| format!("region-{}.service-{}.instance-{}", r, s, s % INSTANCES)))
+        .collect();
+    let subjects: Vec<Vec<&str>> = subjects.iter().map(|s| s.split('.').collect()).collect();
+
+    let frontier = if cfg!(feature = "smallvec") { "SmallVec" } else { "Vec" };
+    println!("{} subjects, {} frontier, best of {} rounds", subjects.len(), frontier, ROUNDS);
+    println!("find,  literal patterns:  {:?}", time(&subjects, |keys| exact.find_owned(keys).len()));
+    println!("find,  wildcard patterns: {:?}", time(&subjects, |keys| wildcard.find_owned(keys).len()));
+    println!("exist, literal patterns:  {:?}", time(&subjects, |keys| exact.exist(keys) as usize));
+    println!("exist, wildcard patterns: {:?}", time(&subjects, |keys| wildcard.exist(keys) as usize));
+}
@@ -0,0 +1,55 @@
+//! Compares the heap memory of a trie with and without interning on a
+//! repetitive keyspace: 100 segment names combined into 3-level patterns,
+//! whose tokens own their strings as if they were read from a file.
+//!
+//! Run with `cargo bench --bench interning`.
+
+mod common;
+
+use common::{live_bytes, Counting};
+use std::borrow::Cow;
+use trie::{Token, Tokens, Trie};
+
+#[global_allocator]
+static GLOBAL: Counting = Counting;
+
+const SEGMENTS: usize = 100;
+
+fn patterns() -> Vec<Tokens<'static>> {
+    let names: Vec<String> = (0..SEGMENTS).map(|i| format!("segment-name-{:03}", i)).collect();
+    let mut patterns = Vec::new();
+    for a in names.iter().take(10) {
+        for b in names.iter() {
+            for c in names.iter() {
+                patterns.push(
+                    vec![a, b, c]
+                        .into_iter()
+                        .map(|s| Token::Normal(Cow::Owned(s.clone())))
+                        .collect::<Vec<_>>()
+                        .into(),
+                );
+            }
+        }
+    }
+    patterns
+}
+
+fn measure(mut trie: Trie<'static, usize, 10>, patterns: &[Tokens<'static>]) -> usize {
+    let before = live_bytes();
+    for (i, tokens) in patterns.iter().enumerate() {
+        trie.insert(tokens, i);
+    }
+    let used = live_bytes() - before;
+    assert_eq!(trie.find(["segment-name-001", "segment-name-002", "segment-name-003"]).len(), 1);
+    used
+}
+
+fn main() {
+    let patterns = patterns();
+    let plain = measure(Trie::new(), &patterns);
+    let interned = measure(Trie::with_interner(), &patterns);
+    println!("{} patterns over {} distinct segments", patterns.len(), SEGMENTS);
+    println!("without interner: {:>10} bytes", plain);
+    println!("with interner:    {:>10} bytes", interned);
+    println!("reduction:        {:>9.1}%", 100.0 * (plain as f64 - interned as f64) / plain as f64);
+}
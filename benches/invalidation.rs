@@ -0,0 +1,52 @@
+//! Times inserting and removing a pattern while the cache is full. Cached results
+//! are bucketed by their first key, so a pattern starting with a normal token only
+//! scans the results sharing that first key, while a pattern starting with a
+//! wildcard still scans every cached result.
+//!
+//! `cargo bench --bench invalidation`
+
+mod common;
+
+use common::{best_of, ROUNDS};
+use std::time::{Duration, Instant};
+use trie::token::{CommonTokenParser, TokenParser, Tokens};
+use trie::Trie;
+
+const CACHE: usize = 4096;
+const FIRSTS: usize = 256;
+const MUTATIONS: usize = 1000;
+
+// Inserts and removes the pattern repeatedly and returns the best time of all rounds.
+// The cache is refilled before each round, which is not timed
+fn time<'a>(trie: &mut Trie<'a, usize, CACHE>, subjects: &[Vec<&'a str>], pattern: &Tokens<'a>) -> Duration {
+    best_of(|| {
+        for keys in subjects.iter() {
+            trie.find(keys);
+        }
+        assert_eq!(trie.cache_len(), CACHE);
+        let start = Instant::now();
+        for _ in 0..MUTATIONS {
+            trie.insert(pattern, usize::MAX);
+            trie.remove(pattern, &usize::MAX);
+        }
+        start.elapsed()
+    })
+}
+
+fn main() {
+    let parser = CommonTokenParser::nats();
+    let subjects: Vec<String> = (0..CACHE)
+        .map(|i| format!("first-{}.second-{}", i % FIRSTS, i / FIRSTS))
+        .collect();
+    let mut trie = Trie::<_, CACHE>::new();
+    for (v, subject) in subjects.iter().enumerate() {
+        trie.insert(&parser.parse_tokens(subject).unwrap(), v);
+    }
+    let subjects: Vec<Vec<&str>> = subjects.iter().map(|s| s.split('.').collect()).collect();
+
+    let normal = parser.parse_tokens("first-0.other").unwrap();
+    let wildcard = parser.parse_tokens("*.other").unwrap();
+    println!("{} cached results in {} buckets, {} mutations, best of {} rounds", CACHE, FIRSTS, MUTATIONS, ROUNDS);
+    println!("normal first token:   {:?}", time(&mut trie, &subjects, &normal));
+    println!("wildcard first token: {:?}", time(&mut trie, &subjects, &wildcard));
+}
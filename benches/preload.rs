@@ -0,0 +1,35 @@
+//! Compares the time to preload many patterns whose first tokens are all
+//! distinct, with and without pre-sizing the root node.
+//!
+//! Run with `cargo bench --bench preload`.
+
+mod common;
+
+use common::{best_of, ROUNDS};
+use std::time::{Duration, Instant};
+use trie::{Token, Tokens, Trie};
+
+const PATTERNS: usize = 100_000;
+
+fn preload(names: &[String], trie: impl Fn() -> Trie<'static, usize, 10>) -> Duration {
+    let patterns: Vec<Tokens> = names.iter()
+        .map(|name| vec![Token::Normal(name.clone().into()), Token::OneWildcard(None)].into())
+        .collect();
+    best_of(|| {
+        let mut trie = trie();
+        let start = Instant::now();
+        for (i, tokens) in patterns.iter().enumerate() {
+            trie.insert(tokens, i);
+        }
+        start.elapsed()
+    })
+}
+
+fn main() {
+    let names: Vec<String> = (0..PATTERNS).map(|i| format!("device-{}", i)).collect();
+    let plain = preload(&names, Trie::new);
+    let sized = preload(&names, || Trie::with_root_capacity(PATTERNS));
+    println!("{} patterns, best of {} rounds", PATTERNS, ROUNDS);
+    println!("Trie::new:                {:?}", plain);
+    println!("Trie::with_root_capacity: {:?}", sized);
+}
@@ -0,0 +1,230 @@
+use crate::token::{TokenAtom, Tokens};
+use std::borrow::{Borrow, Cow};
+use std::collections::{BTreeMap, HashMap};
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+
+/// 一次查询是否命中了缓存
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CacheOutcome {
+    /// 结果来自缓存
+    Hit,
+    /// 缓存中没有结果，查询了trie树
+    Miss,
+}
+
+/// 查询缓存中保存的结果的形式
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum CacheMode {
+    /// 保存匹配的值的复制，命中时直接复制缓存的结果
+    #[default]
+    Values,
+    /// 只保存匹配的组的tokens，命中时再从这些组中读取当前的值。
+    /// 缓存中不复制值，适用于值很大的场景，代价是命中时需要沿着每个tokens重新找到组
+    Patterns,
+}
+
+/// 缓存的一次查询的结果
+#[derive(Debug)]
+pub(crate) enum Cached<'a, V, K = Cow<'a, str>> {
+    /// 匹配的所有值的复制
+    Values(Vec<V>),
+    /// 匹配的所有非空的组的tokens，多层wildcard组以`MultiWildcard`结尾
+    Patterns(Vec<Tokens<'a, K>>),
+}
+
+impl<'a, V: PartialEq, K> Cached<'a, V, K> {
+    /// 结果中是否有value。只保存了组的tokens时命中会重新读取组中的值，
+    /// 组中移除的值不会被返回，因此总是返回false
+    pub(crate) fn contains(&self, value: &V) -> bool {
+        match self {
+            Cached::Values(values) => values.contains(value),
+            Cached::Patterns(_) => false,
+        }
+    }
+}
+
+/// trie树的查询缓存，键是查询的keys
+pub(crate) type QueryCache<'a, V, const N: usize, K = Cow<'a, str>> =
+    LruCache<Vec<&'a <K as TokenAtom>::Key>, Cached<'a, V, K>, N>;
+
+/// 最多保存N项的LRU缓存，已满时淘汰最久没有使用的项。
+/// 项按照键的第一项分桶保存，使缓存失效时可以只检查可能受影响的桶
+#[derive(Debug)]
+pub(crate) struct LruCache<K, T, const N: usize> {
+    // 按照桶分组的缓存的项，以及其最后一次被使用的时刻
+    buckets: HashMap<u64, HashMap<K, (T, u64)>>,
+    // 每一项最后一次被使用的时刻到其所在的桶和键，第一项就是最久没有使用的
+    order: BTreeMap<u64, (u64, K)>,
+    // 所有桶中的项数之和
+    len: usize,
+    // 单调递增的时刻，每次使用一项时增加
+    tick: u64,
+}
+
+/// 缓存的键，第一项相同的键在同一个桶中
+pub(crate) trait CacheKey {
+    /// 键所在的桶
+    fn bucket(&self) -> u64;
+}
+
+impl<Q: Hash + ?Sized> CacheKey for [&Q] {
+    fn bucket(&self) -> u64 {
+        bucket_of(self.first().copied())
+    }
+}
+
+impl<Q: Hash + ?Sized> CacheKey for Vec<&Q> {
+    fn bucket(&self) -> u64 {
+        self.as_slice().bucket()
+    }
+}
+
+/// 第一项为first的键所在的桶，None表示空的键。
+/// 使用固定的hasher，同一个first总是对应同一个桶，不同的first偶尔在同一个桶中也不影响正确性
+pub(crate) fn bucket_of<Q: Hash + ?Sized>(first: Option<&Q>) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    first.hash(&mut hasher);
+    hasher.finish()
+}
+
+impl<K, T, const N: usize> Default for LruCache<K, T, N> {
+    fn default() -> Self {
+        LruCache {
+            buckets: HashMap::new(),
+            order: BTreeMap::new(),
+            len: 0,
+            tick: 0,
+        }
+    }
+}
+
+impl<K, T, const N: usize> LruCache<K, T, N>
+where
+    K: Hash + Eq + Clone + CacheKey,
+{
+    /// 返回key对应的值，并标记为最近使用
+    pub(crate) fn get<Q>(&mut self, key: &Q) -> Option<&T>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + CacheKey + ?Sized,
+    {
+        let bucket = key.bucket();
+        let (value, used) = self.buckets.get_mut(&bucket)?.get_mut(key)?;
+        self.tick += 1;
+        let last = std::mem::replace(used, self.tick);
+        if let Some(entry) = self.order.remove(&last) {
+            self.order.insert(self.tick, entry);
+        }
+        Some(&*value)
+    }
+
+    /// 放入一项。已满时先淘汰最久没有使用的项，容量为0时什么也不做
+    pub(crate) fn put(&mut self, key: K, value: T) {
+        if N == 0 {
+            return;
+        }
+        let bucket = key.bucket();
+        let exists = self.buckets.get(&bucket).is_some_and(|entries| entries.contains_key(&key));
+        if self.len >= N && !exists {
+            if let Some((_, (b, oldest))) = self.order.pop_first() {
+                if let Some(entries) = self.buckets.get_mut(&b) {
+                    entries.remove(&oldest);
+                    self.len -= 1;
+                    if entries.is_empty() {
+                        self.buckets.remove(&b);
+                    }
+                }
+            }
+        }
+        self.tick += 1;
+        self.order.insert(self.tick, (bucket, key.clone()));
+        match self.buckets.entry(bucket).or_default().insert(key, (value, self.tick)) {
+            None => self.len += 1,
+            Some((_, last)) => {
+                self.order.remove(&last);
+            },
+        }
+    }
+
+    /// 移除所有满足pred的项
+    pub(crate) fn remove<F>(&mut self, mut pred: F)
+    where
+        F: FnMut(&K) -> bool,
+    {
+        self.remove_entries(|k, _| pred(k));
+    }
+
+    /// 只在bucket这个桶中移除满足pred的项，其它桶中的项不会被检查。
+    /// 调用者需要保证其它桶中没有满足pred的项
+    pub(crate) fn remove_in<F>(&mut self, bucket: u64, mut pred: F)
+    where
+        F: FnMut(&K) -> bool,
+    {
+        if let Some(entries) = self.buckets.get_mut(&bucket) {
+            let before = entries.len();
+            let order = &mut self.order;
+            entries.retain(|k, (_, used)| {
+                let remove = pred(k);
+                if remove {
+                    order.remove(used);
+                }
+                !remove
+            });
+            self.len -= before - entries.len();
+            if entries.is_empty() {
+                self.buckets.remove(&bucket);
+            }
+        }
+    }
+
+    /// 移除所有满足pred的项，pred可以检查项的值
+    pub(crate) fn remove_entries<F>(&mut self, mut pred: F)
+    where
+        F: FnMut(&K, &T) -> bool,
+    {
+        let order = &mut self.order;
+        for entries in self.buckets.values_mut() {
+            entries.retain(|k, (v, used)| {
+                let remove = pred(k, v);
+                if remove {
+                    order.remove(used);
+                }
+                !remove
+            });
+        }
+        self.buckets.retain(|_, entries| !entries.is_empty());
+        self.len = self.buckets.values().map(|entries| entries.len()).sum();
+    }
+
+    /// 清空缓存
+    pub(crate) fn clear(&mut self) {
+        self.buckets.clear();
+        self.order.clear();
+        self.len = 0;
+    }
+
+    /// 释放桶中以及桶的表中多余的容量，淘汰和失效之后桶中的空间不会自动释放
+    pub(crate) fn shrink_to_fit(&mut self) {
+        for entries in self.buckets.values_mut() {
+            entries.shrink_to_fit();
+        }
+        self.buckets.shrink_to_fit();
+    }
+
+    /// 桶以及桶的表占用的容量，用于检查`shrink_to_fit`
+    #[cfg(test)]
+    pub(crate) fn allocated(&self) -> usize {
+        self.buckets.capacity() + self.buckets.values().map(|entries| entries.capacity()).sum::<usize>()
+    }
+
+    /// 当前缓存的项数
+    pub(crate) fn len(&self) -> usize {
+        self.len
+    }
+
+    /// 最多能缓存的项数
+    pub(crate) fn capacity(&self) -> usize {
+        N
+    }
+}
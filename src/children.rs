@@ -0,0 +1,261 @@
+use std::borrow::Borrow;
+use std::collections::hash_map::RandomState;
+use std::collections::{BTreeMap, HashMap};
+use std::hash::{BuildHasher, Hash};
+use std::marker::PhantomData;
+
+/// 普通token对应的子结点的容器的种类，决定子结点如何保存以及遍历子结点的顺序。
+/// 默认的`HashedChildren`查找最快，`OrderedChildren`按照键的顺序遍历子结点
+pub trait ChildMap {
+    /// 以Q为键、N为子结点的容器
+    type Map<Q: Eq + Hash + Ord, N>: ChildContainer<Q, N>;
+}
+
+/// 子结点的容器需要支持的操作，与`HashMap`的同名方法相同
+pub trait ChildContainer<Q, N>: Default {
+    /// 生成一个空的容器，并为capacity个子结点预留空间
+    fn with_capacity(capacity: usize) -> Self;
+
+    /// 子结点的数量
+    fn len(&self) -> usize;
+
+    /// 是否没有子结点
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// 再预留additional个子结点的空间
+    fn reserve(&mut self, additional: usize);
+
+    /// 释放多余的空间
+    fn shrink_to_fit(&mut self);
+
+    /// 添加子结点，返回原来的子结点
+    fn insert(&mut self, key: Q, node: N) -> Option<N>;
+
+    /// 返回key对应的子结点
+    fn get<B>(&self, key: &B) -> Option<&N>
+    where
+        Q: Borrow<B>,
+        B: ?Sized + Eq + Hash + Ord;
+
+    /// 返回key对应的子结点的可变引用
+    fn get_mut<B>(&mut self, key: &B) -> Option<&mut N>
+    where
+        Q: Borrow<B>,
+        B: ?Sized + Eq + Hash + Ord;
+
+    /// 返回容器中保存的key以及其对应的子结点
+    fn get_key_value<B>(&self, key: &B) -> Option<(&Q, &N)>
+    where
+        Q: Borrow<B>,
+        B: ?Sized + Eq + Hash + Ord;
+
+    /// 是否有key对应的子结点
+    fn contains_key<B>(&self, key: &B) -> bool
+    where
+        Q: Borrow<B>,
+        B: ?Sized + Eq + Hash + Ord,
+    {
+        self.get(key).is_some()
+    }
+
+    /// 移除并返回key对应的子结点
+    fn remove<B>(&mut self, key: &B) -> Option<N>
+    where
+        Q: Borrow<B>,
+        B: ?Sized + Eq + Hash + Ord;
+
+    /// 遍历所有的键和子结点
+    fn iter<'m>(&'m self) -> impl Iterator<Item = (&'m Q, &'m N)>
+    where
+        Q: 'm,
+        N: 'm;
+
+    /// 遍历所有的键和子结点的可变引用
+    fn iter_mut<'m>(&'m mut self) -> impl Iterator<Item = (&'m Q, &'m mut N)>
+    where
+        Q: 'm,
+        N: 'm;
+
+    /// 移除并返回所有的键和子结点
+    fn drain(&mut self) -> impl Iterator<Item = (Q, N)>;
+
+    /// 遍历所有的子结点
+    fn values<'m>(&'m self) -> impl Iterator<Item = &'m N>
+    where
+        Q: 'm,
+        N: 'm,
+    {
+        self.iter().map(|(_, n)| n)
+    }
+
+    /// 遍历所有的子结点的可变引用
+    fn values_mut<'m>(&'m mut self) -> impl Iterator<Item = &'m mut N>
+    where
+        Q: 'm,
+        N: 'm,
+    {
+        self.iter_mut().map(|(_, n)| n)
+    }
+}
+
+/// 用`HashMap`保存子结点，S是其hasher，默认与组使用相同的hasher。遍历子结点的顺序不确定
+pub struct HashedChildren<S = RandomState>(PhantomData<S>);
+
+impl<S: BuildHasher + Default> ChildMap for HashedChildren<S> {
+    type Map<Q: Eq + Hash + Ord, N> = HashMap<Q, N, S>;
+}
+
+/// 用`BTreeMap`保存子结点，遍历子结点时按照键的顺序，因此`visit`、`into_iter`等遍历的输出是确定的。
+/// 查找子结点比`HashedChildren`慢
+pub struct OrderedChildren;
+
+impl ChildMap for OrderedChildren {
+    type Map<Q: Eq + Hash + Ord, N> = BTreeMap<Q, N>;
+}
+
+impl<Q: Eq + Hash, N, S: BuildHasher + Default> ChildContainer<Q, N> for HashMap<Q, N, S> {
+    fn with_capacity(capacity: usize) -> Self {
+        HashMap::with_capacity_and_hasher(capacity, S::default())
+    }
+
+    fn len(&self) -> usize {
+        HashMap::len(self)
+    }
+
+    fn reserve(&mut self, additional: usize) {
+        HashMap::reserve(self, additional)
+    }
+
+    fn shrink_to_fit(&mut self) {
+        HashMap::shrink_to_fit(self)
+    }
+
+    fn insert(&mut self, key: Q, node: N) -> Option<N> {
+        HashMap::insert(self, key, node)
+    }
+
+    fn get<B>(&self, key: &B) -> Option<&N>
+    where
+        Q: Borrow<B>,
+        B: ?Sized + Eq + Hash + Ord,
+    {
+        HashMap::get(self, key)
+    }
+
+    fn get_mut<B>(&mut self, key: &B) -> Option<&mut N>
+    where
+        Q: Borrow<B>,
+        B: ?Sized + Eq + Hash + Ord,
+    {
+        HashMap::get_mut(self, key)
+    }
+
+    fn get_key_value<B>(&self, key: &B) -> Option<(&Q, &N)>
+    where
+        Q: Borrow<B>,
+        B: ?Sized + Eq + Hash + Ord,
+    {
+        HashMap::get_key_value(self, key)
+    }
+
+    fn remove<B>(&mut self, key: &B) -> Option<N>
+    where
+        Q: Borrow<B>,
+        B: ?Sized + Eq + Hash + Ord,
+    {
+        HashMap::remove(self, key)
+    }
+
+    fn iter<'m>(&'m self) -> impl Iterator<Item = (&'m Q, &'m N)>
+    where
+        Q: 'm,
+        N: 'm,
+    {
+        HashMap::iter(self)
+    }
+
+    fn iter_mut<'m>(&'m mut self) -> impl Iterator<Item = (&'m Q, &'m mut N)>
+    where
+        Q: 'm,
+        N: 'm,
+    {
+        HashMap::iter_mut(self)
+    }
+
+    fn drain(&mut self) -> impl Iterator<Item = (Q, N)> {
+        HashMap::drain(self)
+    }
+}
+
+impl<Q: Ord, N> ChildContainer<Q, N> for BTreeMap<Q, N> {
+    // BTreeMap没有预留空间的概念
+    fn with_capacity(_capacity: usize) -> Self {
+        BTreeMap::new()
+    }
+
+    fn len(&self) -> usize {
+        BTreeMap::len(self)
+    }
+
+    fn reserve(&mut self, _additional: usize) {}
+
+    fn shrink_to_fit(&mut self) {}
+
+    fn insert(&mut self, key: Q, node: N) -> Option<N> {
+        BTreeMap::insert(self, key, node)
+    }
+
+    fn get<B>(&self, key: &B) -> Option<&N>
+    where
+        Q: Borrow<B>,
+        B: ?Sized + Eq + Hash + Ord,
+    {
+        BTreeMap::get(self, key)
+    }
+
+    fn get_mut<B>(&mut self, key: &B) -> Option<&mut N>
+    where
+        Q: Borrow<B>,
+        B: ?Sized + Eq + Hash + Ord,
+    {
+        BTreeMap::get_mut(self, key)
+    }
+
+    fn get_key_value<B>(&self, key: &B) -> Option<(&Q, &N)>
+    where
+        Q: Borrow<B>,
+        B: ?Sized + Eq + Hash + Ord,
+    {
+        BTreeMap::get_key_value(self, key)
+    }
+
+    fn remove<B>(&mut self, key: &B) -> Option<N>
+    where
+        Q: Borrow<B>,
+        B: ?Sized + Eq + Hash + Ord,
+    {
+        BTreeMap::remove(self, key)
+    }
+
+    fn iter<'m>(&'m self) -> impl Iterator<Item = (&'m Q, &'m N)>
+    where
+        Q: 'm,
+        N: 'm,
+    {
+        BTreeMap::iter(self)
+    }
+
+    fn iter_mut<'m>(&'m mut self) -> impl Iterator<Item = (&'m Q, &'m mut N)>
+    where
+        Q: 'm,
+        N: 'm,
+    {
+        BTreeMap::iter_mut(self)
+    }
+
+    fn drain(&mut self) -> impl Iterator<Item = (Q, N)> {
+        std::mem::take(self).into_iter()
+    }
+}
@@ -0,0 +1,370 @@
+use crate::children::{ChildContainer, ChildMap};
+use crate::intern::{Atom, ChildKey};
+use crate::token::{normalize_alternatives, Token, TokenAtom, Tokens};
+use crate::node::{Node, NodeParts, ValueSet};
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hash};
+use std::marker::PhantomData;
+
+// 压缩之后的子结点在父结点中的位置
+enum Slot<K: TokenAtom> {
+    Root,
+    Child(ChildKey<K>),
+    OneWildcard,
+    OneOf(Vec<K>),
+    NumRange(u64, u64),
+}
+
+// 已经压缩的结点，以及其父结点在列表中的位置和其在父结点中的位置
+type BuiltNodes<V, S, K> = Vec<(Option<usize>, Slot<K>, CompactNode<V, S, K>)>;
+
+// 多选一token对应的子结点的集合
+type AltNodes<V, S, K> = Vec<(Vec<K>, Box<CompactNode<V, S, K>>)>;
+
+// 数字范围token对应的子结点的集合
+type RangeNodes<V, S, K> = Vec<((u64, u64), Box<CompactNode<V, S, K>>)>;
+
+// 查询中匹配到的结点
+type Nodes<'t, V, S, K> = Vec<&'t CompactNode<V, S, K>>;
+
+// 查询过程中待处理的结点，以及已经匹配了该结点的边上的几个token
+type Cursors<'t, V, S, K> = Vec<(&'t CompactNode<V, S, K>, usize)>;
+
+/// 压缩的trie树结点。到达该结点的边除了第一个token之外，还需要依次匹配tail中的token
+struct CompactNode<V, S, K: TokenAtom> {
+    // 边上除了第一个token之外的普通token
+    tail: Vec<ChildKey<K>>,
+    // 子结点，键为边上的第一个token
+    children: HashMap<ChildKey<K>, Box<CompactNode<V, S, K>>, S>,
+    // 单层wildcard对应的子结点
+    o_node: Option<Box<CompactNode<V, S, K>>>,
+    // 多选一token对应的子结点，备选token已经排序去重
+    alt_nodes: AltNodes<V, S, K>,
+    // 数字范围token对应的子结点
+    range_nodes: RangeNodes<V, S, K>,
+    // 多层wildcard组
+    m_value_set: ValueSet<V, S>,
+    // 当前结点对应的组
+    value_set: ValueSet<V, S>,
+}
+
+impl<V, S, K> CompactNode<V, S, K>
+where
+    K: TokenAtom,
+    V: Eq + Hash + Clone,
+    S: BuildHasher + Default,
+{
+    /// 由原来的结点的各个部分生成压缩的结点，子结点为空，之后再装上
+    fn new<M: ChildMap>(tail: Vec<ChildKey<K>>, parts: &mut NodeParts<'_, V, S, K, M>) -> Self {
+        CompactNode {
+            tail,
+            children: HashMap::with_hasher(S::default()),
+            o_node: None,
+            alt_nodes: Vec::new(),
+            range_nodes: Vec::new(),
+            m_value_set: std::mem::take(&mut parts.m_value_set),
+            value_set: std::mem::take(&mut parts.value_set),
+        }
+    }
+
+    /// 生成一个没有值也没有子结点的结点，到达它的边上还有tail
+    fn empty(tail: Vec<ChildKey<K>>) -> Self {
+        CompactNode {
+            tail,
+            children: HashMap::with_hasher(S::default()),
+            o_node: None,
+            alt_nodes: Vec::new(),
+            range_nodes: Vec::new(),
+            m_value_set: ValueSet::with_hasher(S::default()),
+            value_set: ValueSet::with_hasher(S::default()),
+        }
+    }
+
+    /// 返回path的第一个token对应的子结点，以及从path中消耗的token的数量。
+    /// 子结点不存在时创建，其后紧跟的普通token都放到新的边上；边上的token与path只有一部分相同时，
+    /// 在不同的地方拆分边。created记录新生成的结点的数量
+    fn child_or_insert(&mut self, path: &[&Token<'_, K>], created: &mut usize) -> (&mut Self, usize) {
+        let normals: Vec<&K> = path[1..].iter()
+            .map_while(|token| match token {
+                Token::Normal(k) => Some(k),
+                _ => None,
+            })
+            .collect();
+        let (child, is_new) = self.slot_or_insert(path[0]);
+        if is_new {
+            *created += 1;
+            child.tail = normals.iter().map(|k| Atom::Plain((*k).clone())).collect();
+            return (child, 1 + normals.len());
+        }
+        let matched = child.tail.iter().zip(normals.iter())
+            .take_while(|&(t, k)| &**t == k.key())
+            .count();
+        if matched < child.tail.len() {
+            *created += 1;
+            child.split(matched);
+        }
+        (child, 1 + matched)
+    }
+
+    /// 返回token对应的位置上的子结点，以及是否是新创建的
+    fn slot_or_insert(&mut self, token: &Token<'_, K>) -> (&mut Self, bool) {
+        match token {
+            Token::Normal(k) => {
+                let is_new = !self.children.contains_key(k.key());
+                if is_new {
+                    self.children.insert(Atom::Plain(k.clone()), Box::new(CompactNode::empty(Vec::new())));
+                }
+                (self.children.get_mut(k.key()).unwrap(), is_new)
+            },
+            Token::OneWildcard(_) => {
+                let is_new = self.o_node.is_none();
+                (self.o_node.get_or_insert_with(|| Box::new(CompactNode::empty(Vec::new()))), is_new)
+            },
+            Token::OneOf(alternatives) => {
+                let alternatives = normalize_alternatives(alternatives);
+                let idx = self.alt_nodes.iter().position(|(a, _)| *a == alternatives);
+                let is_new = idx.is_none();
+                let idx = idx.unwrap_or_else(|| {
+                    self.alt_nodes.push((alternatives, Box::new(CompactNode::empty(Vec::new()))));
+                    self.alt_nodes.len() - 1
+                });
+                (&mut self.alt_nodes[idx].1, is_new)
+            },
+            Token::NumRange(lo, hi) => {
+                let idx = self.range_nodes.iter().position(|(range, _)| *range == (*lo, *hi));
+                let is_new = idx.is_none();
+                let idx = idx.unwrap_or_else(|| {
+                    self.range_nodes.push(((*lo, *hi), Box::new(CompactNode::empty(Vec::new()))));
+                    self.range_nodes.len() - 1
+                });
+                (&mut self.range_nodes[idx].1, is_new)
+            },
+            Token::MultiWildcard => unreachable!("multi wildcards are removed from the path"),
+        }
+    }
+
+    /// 在边上的第at个token之前拆分边：当前结点只保留边上的前at个token，
+    /// 原来的值和子结点都移到新的子结点上，到达它的边为剩下的token
+    fn split(&mut self, at: usize) {
+        let mut rest = self.tail.split_off(at).into_iter();
+        let key = rest.next().expect("the edge is split before one of its tokens");
+        let head = std::mem::take(&mut self.tail);
+        let mut lower = std::mem::replace(self, CompactNode::empty(head));
+        lower.tail = rest.collect();
+        self.children.insert(key, Box::new(lower));
+    }
+
+    /// 将子结点装到slot的位置
+    fn attach(&mut self, slot: Slot<K>, node: CompactNode<V, S, K>) {
+        let node = Box::new(node);
+        match slot {
+            Slot::Root => {},
+            Slot::Child(k) => {
+                self.children.insert(k, node);
+            },
+            Slot::OneWildcard => self.o_node = Some(node),
+            Slot::OneOf(a) => self.alt_nodes.push((a, node)),
+            Slot::NumRange(lo, hi) => self.range_nodes.push(((lo, hi), node)),
+        }
+    }
+
+    /// 所有子结点的不可变引用
+    fn child_nodes(&self) -> impl Iterator<Item = &CompactNode<V, S, K>> {
+        self.children.values()
+            .chain(self.o_node.as_ref())
+            .chain(self.alt_nodes.iter().map(|(_, n)| n))
+            .chain(self.range_nodes.iter().map(|(_, n)| n))
+            .map(|n| n.as_ref())
+    }
+}
+
+// 默认的析构会随着树的深度递归，这里先把子结点拆下来放到栈中，再逐个释放
+impl<V, S, K: TokenAtom> Drop for CompactNode<V, S, K> {
+    fn drop(&mut self) {
+        let mut stack: Vec<Box<CompactNode<V, S, K>>> = Vec::new();
+        stack.extend(self.children.drain().map(|(_, n)| n));
+        stack.extend(self.o_node.take());
+        stack.extend(self.alt_nodes.drain(..).map(|(_, n)| n));
+        stack.extend(self.range_nodes.drain(..).map(|(_, n)| n));
+        while let Some(mut node) = stack.pop() {
+            stack.extend(node.children.drain().map(|(_, n)| n));
+            stack.extend(node.o_node.take());
+            stack.extend(node.alt_nodes.drain(..).map(|(_, n)| n));
+            stack.extend(node.range_nodes.drain(..).map(|(_, n)| n));
+        }
+    }
+}
+
+/// 压缩的trie树，由`Trie::compact`生成。只有一个普通子结点、自身没有值的结点会被合并到
+/// 到达它的边上，一条边可以包含多个token，适用于很深但是很少分叉的键空间，例如
+/// `com.example.service.v1.api.users`。查询的结果与压缩之前的trie树相同，包括`set_mwc_includes_prefix`的设置。
+/// 之后仍然可以用`insert`添加值，需要时拆分边，但是不支持移除
+pub struct CompactTrie<'a, V, S = RandomState, K: TokenAtom = Cow<'a, str>> {
+    // 根结点，其tail总是空的
+    root: CompactNode<V, S, K>,
+    // 多层wildcard组是否也匹配其前缀本身
+    mwc_includes_prefix: bool,
+    // 压缩之后结点的数量，包括根结点
+    node_count: usize,
+    // 原来的trie树的生命周期。结点中不再保存捕获名，借用的数据都在原子K中
+    marker: PhantomData<&'a ()>,
+}
+
+impl<'a, V, S, K> CompactTrie<'a, V, S, K>
+where
+    K: TokenAtom,
+    V: Eq + Hash + Clone,
+    S: BuildHasher + Default,
+{
+    /// 压缩以root为根的trie树。使用显式的栈，不会因为树太深而栈溢出
+    pub(crate) fn new<M: ChildMap>(root: Box<Node<'a, V, S, K, M>>, mwc_includes_prefix: bool) -> Self {
+        let mut pending = vec![(None, Slot::Root, root)];
+        let mut built: BuiltNodes<V, S, K> = Vec::new();
+        while let Some((parent, slot, node)) = pending.pop() {
+            let mut parts = node.into_parts();
+            // 沿着只有一个普通子结点的链合并，根结点不合并
+            let mut tail = Vec::new();
+            while parent.is_some() && parts.value_set.is_empty() && parts.m_value_set.is_empty()
+                && parts.o_node.is_none() && parts.alt_nodes.is_empty() && parts.range_nodes.is_empty()
+                && parts.children.len() == 1 {
+                let (key, child) = parts.children.drain().next().unwrap();
+                tail.push(key);
+                parts = child.into_parts();
+            }
+            let idx = built.len();
+            built.push((parent, slot, CompactNode::new(tail, &mut parts)));
+            for (key, child) in parts.children.drain() {
+                pending.push((Some(idx), Slot::Child(key), child));
+            }
+            if let Some(child) = parts.o_node.take() {
+                pending.push((Some(idx), Slot::OneWildcard, child));
+            }
+            for (alternatives, child) in parts.alt_nodes.drain(..).rev() {
+                pending.push((Some(idx), Slot::OneOf(alternatives), child));
+            }
+            for ((lo, hi), child) in parts.range_nodes.drain(..).rev() {
+                pending.push((Some(idx), Slot::NumRange(lo, hi), child));
+            }
+        }
+        // 子结点总是排在父结点之后，从后往前依次装到父结点上
+        let node_count = built.len();
+        let mut root = None;
+        while let Some((parent, slot, node)) = built.pop() {
+            match parent {
+                None => root = Some(node),
+                Some(p) => built[p].2.attach(slot, node),
+            }
+        }
+        CompactTrie { root: root.expect("the root is always built"), mwc_includes_prefix, node_count, marker: PhantomData }
+    }
+
+    /// 添加键值对，与`Trie::insert`相同，tokens中的多层wildcard表示添加到多层wildcard组。
+    /// 新的普通token都放到同一条边上，与已有的边只有一部分相同时在分叉的地方拆分边，
+    /// 因此添加之后的结构与压缩添加之后的`Trie`相同
+    pub fn insert(&mut self, tokens: &Tokens<'a, K>, value: V) {
+        let path: Vec<&Token<'a, K>> = tokens.0.iter().filter(|t| !matches!(t, Token::MultiWildcard)).collect();
+        let mwc = path.len() < tokens.0.len();
+        let mut created = 0;
+        let mut node = &mut self.root;
+        let mut rest = &path[..];
+        while !rest.is_empty() {
+            let (child, consumed) = node.child_or_insert(rest, &mut created);
+            node = child;
+            rest = &rest[consumed..];
+        }
+        if mwc {
+            node.m_value_set.insert(value);
+        } else {
+            node.value_set.insert(value);
+        }
+        self.node_count += created;
+    }
+
+    /// 压缩之后结点的数量，包括根结点
+    pub fn node_count(&self) -> usize {
+        self.node_count
+    }
+
+    /// 边上的token的总数，即压缩之前除了根结点之外的结点的数量
+    pub fn edge_token_count(&self) -> usize {
+        let mut stack = vec![&self.root];
+        let mut count = 0;
+        while let Some(node) = stack.pop() {
+            for child in node.child_nodes() {
+                count += 1 + child.tail.len();
+                stack.push(child);
+            }
+        }
+        count
+    }
+
+    /// 返回能与keys匹配的所有值，与压缩之前的`find`结果相同
+    pub fn find<'k>(&self, keys: impl AsRef<[&'k K::Key]>) -> Vec<V> {
+        let (mwc_nodes, nodes) = self.matched_nodes(keys.as_ref());
+        mwc_nodes.into_iter().flat_map(|n| n.m_value_set.iter().cloned())
+            .chain(nodes.into_iter().flat_map(|n| n.value_set.iter().cloned()))
+            .collect()
+    }
+
+    /// 返回能与keys匹配的值的数量
+    pub fn count<'k>(&self, keys: impl AsRef<[&'k K::Key]>) -> usize {
+        let (mwc_nodes, nodes) = self.matched_nodes(keys.as_ref());
+        mwc_nodes.into_iter().map(|n| n.m_value_set.len()).sum::<usize>()
+            + nodes.into_iter().map(|n| n.value_set.len()).sum::<usize>()
+    }
+
+    /// 是否有与keys匹配的值存在
+    pub fn exist<'k>(&self, keys: impl AsRef<[&'k K::Key]>) -> bool {
+        self.count(keys) > 0
+    }
+
+    /// 返回与keys匹配的结点。第一个是多层wildcard组匹配的结点，第二个是自身的组匹配的结点
+    fn matched_nodes<'t>(&'t self, keys: &[&K::Key]) -> (Nodes<'t, V, S, K>, Nodes<'t, V, S, K>) {
+        let mut mwc_nodes = Vec::new();
+        let mut cursors: Cursors<'t, V, S, K> = vec![(&self.root, 0)];
+        for key in keys.iter() {
+            let mut next_cursors = Vec::new();
+            for (node, matched) in cursors.into_iter() {
+                // 还在边上，key需要与边上的下一个token相同
+                if matched < node.tail.len() {
+                    if &*node.tail[matched] == *key {
+                        next_cursors.push((node, matched + 1));
+                    }
+                    continue;
+                }
+                // 已经到达结点，多层wildcard必然满足tokens的需求
+                if !node.m_value_set.is_empty() {
+                    mwc_nodes.push(node);
+                }
+                next_cursors.extend(node.o_node.as_deref().map(|n| (n, 0)));
+                next_cursors.extend(node.alt_nodes.iter()
+                    .filter(|(a, _)| a.binary_search_by(|alt| alt.key().cmp(key)).is_ok())
+                    .map(|(_, n)| (n.as_ref(), 0)));
+                if let Some(num) = K::key_to_num(key) {
+                    next_cursors.extend(node.range_nodes.iter()
+                        .filter(|((lo, hi), _)| *lo <= num && num <= *hi)
+                        .map(|(_, n)| (n.as_ref(), 0)));
+                }
+                if let Some(n) = node.children.get(*key) {
+                    next_cursors.push((n, 0));
+                }
+            }
+            if next_cursors.is_empty() {
+                return (mwc_nodes, vec![]);
+            }
+            cursors = next_cursors;
+        }
+        // 停在边上的不算到达结点
+        let nodes: Nodes<'t, V, S, K> = cursors.into_iter()
+            .filter(|(node, matched)| *matched == node.tail.len())
+            .map(|(node, _)| node)
+            .collect();
+        // 多层wildcard组也匹配其前缀本身时，最后到达的结点的多层wildcard组也匹配
+        if self.mwc_includes_prefix {
+            mwc_nodes.extend(nodes.iter().filter(|n| !n.m_value_set.is_empty()));
+        }
+        (mwc_nodes, nodes)
+    }
+}
@@ -0,0 +1,34 @@
+use crate::token::CommonTokenError;
+use thiserror::Error;
+
+/// Errors of this crate
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum Error {
+    /// Failed to parse a source into tokens
+    #[error("failed to parse tokens: {0}")]
+    Parse(#[from] CommonTokenError),
+    /// A `OneOf` token without any alternative or a `NumRange` token whose lower bound
+    /// is greater than its upper bound, which can never match, at the index
+    #[error("empty token at index {0}")]
+    EmptyToken(usize),
+    /// A token following the multi wildcard at the index
+    #[error("token after multi wildcard at index {0}")]
+    TokenAfterMwc(usize),
+    /// The group already holds as many values as the limit set by `Trie::set_group_limit`
+    #[error("group is full")]
+    GroupFull,
+}
+
+/// Errors of loading `pattern<TAB>value` lines, each with the 1-based line number
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum LoadError<E> {
+    /// The line has no tab seperating the pattern and the value
+    #[error("missing value at line {line}")]
+    MissingValue { line: usize },
+    /// The parser failed to parse the pattern of the line
+    #[error("failed to parse pattern at line {line}: {source}")]
+    Parse { line: usize, source: E },
+    /// The pattern of the line is parsed but is not valid
+    #[error("invalid pattern at line {line}: {source}")]
+    Invalid { line: usize, source: Error },
+}
@@ -0,0 +1,16 @@
+use crate::token::Tokens;
+use std::borrow::Cow;
+
+/// trie树中的值发生变化时通知观察者的事件
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TrieEvent<'a, V, K = Cow<'a, str>> {
+    /// 向pattern对应的组中添加了value
+    Inserted { pattern: Tokens<'a, K>, value: V },
+    /// 从pattern对应的组中移除了value
+    Removed { pattern: Tokens<'a, K>, value: V },
+    /// 清空了整个trie树
+    Cleared,
+}
+
+/// 观察者，在trie树中的值发生变化时被调用
+pub type Observer<'a, V, K = Cow<'a, str>> = Box<dyn FnMut(TrieEvent<'a, V, K>) + Send + Sync + 'a>;
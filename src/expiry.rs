@@ -0,0 +1,110 @@
+use crate::token::{normalize_alternatives, Token, TokenAtom, Tokens};
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::time::Instant;
+
+/// `insert_with_ttl`添加的值的过期时间，按照组分开保存。
+/// 组的tokens会先转换为统一的形式，带有不同捕获名或者备选token顺序不同的tokens对应同一个组
+pub(crate) struct Expiries<'a, V, K> {
+    groups: HashMap<Tokens<'a, K>, HashMap<V, Instant>>,
+    // 所有过期时间中最早的，移除过期时间时不更新，因此可能比实际的更早
+    next: Option<Instant>,
+}
+
+impl<'a, V: Eq + Hash, K: TokenAtom> Expiries<'a, V, K> {
+    /// 初始化
+    pub(crate) fn new() -> Self {
+        Expiries { groups: HashMap::new(), next: None }
+    }
+
+    /// 记录tokens对应的组中value的过期时间，已经有过期时间时更新
+    pub(crate) fn set(&mut self, tokens: &Tokens<'a, K>, value: V, expires_at: Instant) {
+        self.groups.entry(group_key(tokens)).or_default().insert(value, expires_at);
+        self.next = Some(self.next.map_or(expires_at, |next| next.min(expires_at)));
+    }
+
+    /// 删除tokens对应的组中value的过期时间，值被移除时调用
+    pub(crate) fn forget(&mut self, tokens: &Tokens<'a, K>, value: &V) {
+        self.take(tokens, value);
+    }
+
+    /// 取出tokens对应的组中value的过期时间以及保存过期时间的值
+    pub(crate) fn take(&mut self, tokens: &Tokens<'a, K>, value: &V) -> Option<(V, Instant)> {
+        if self.groups.is_empty() {
+            return None;
+        }
+        let key = group_key(tokens);
+        let values = self.groups.get_mut(&key)?;
+        let taken = values.remove_entry(value);
+        if values.is_empty() {
+            self.groups.remove(&key);
+        }
+        taken
+    }
+
+    /// 删除tokens对应的组中所有值的过期时间
+    pub(crate) fn forget_group(&mut self, tokens: &Tokens<'a, K>) {
+        if !self.groups.is_empty() {
+            self.groups.remove(&group_key(tokens));
+        }
+    }
+
+    /// 删除以path为前缀的所有组中的过期时间，path为空时删除所有的
+    pub(crate) fn forget_prefix(&mut self, path: &[Token<'a, K>]) {
+        let path = group_key(&Tokens(path.to_vec()));
+        self.groups.retain(|tokens, _| !tokens.0.starts_with(&path.0));
+    }
+
+    /// 将第depth个token为from的所有组改为to。to对应的组中已经有的值保留原来的过期时间，
+    /// contains判断改名之前to对应的组中是否已经有该值
+    pub(crate) fn rename(&mut self, depth: usize, from: &K::Key, to: &K, mut contains: impl FnMut(&Tokens<'a, K>, &V) -> bool) {
+        let renamed: Vec<_> = self.groups.keys()
+            .filter(|tokens| matches!(tokens.0.get(depth), Some(Token::Normal(k)) if k.key() == from))
+            .cloned()
+            .collect();
+        for tokens in renamed {
+            let values = self.groups.remove(&tokens).unwrap_or_default();
+            let mut target = tokens;
+            target.0[depth] = Token::Normal(to.clone());
+            for (value, expires_at) in values {
+                if !contains(&target, &value) {
+                    self.groups.entry(target.clone()).or_default().insert(value, expires_at);
+                }
+            }
+        }
+    }
+
+    /// 是否有过期时间不晚于现在的值
+    pub(crate) fn is_due(&self) -> bool {
+        self.next.is_some_and(|next| next <= Instant::now())
+    }
+
+    /// 取出所有过期时间不晚于now的值以及其所在的组
+    pub(crate) fn take_expired(&mut self, now: Instant) -> Vec<(Tokens<'a, K>, V)> {
+        let mut expired = Vec::new();
+        for (tokens, values) in self.groups.iter_mut() {
+            expired.extend(values.extract_if(|_, expires_at| *expires_at <= now).map(|(v, _)| (tokens.clone(), v)));
+        }
+        self.groups.retain(|_, values| !values.is_empty());
+        self.next = self.groups.values().flat_map(|values| values.values()).min().copied();
+        expired
+    }
+
+    /// 清空所有的过期时间
+    pub(crate) fn clear(&mut self) {
+        self.groups.clear();
+        self.next = None;
+    }
+}
+
+// 组的tokens的统一形式：单层wildcard不带捕获名，多选一token的备选token排序去重，与结点中保存的相同
+fn group_key<'a, K: TokenAtom>(tokens: &Tokens<'a, K>) -> Tokens<'a, K> {
+    tokens.0.iter()
+        .map(|token| match token {
+            Token::OneWildcard(_) => Token::OneWildcard(None),
+            Token::OneOf(alternatives) => Token::OneOf(normalize_alternatives(alternatives)),
+            token => token.clone(),
+        })
+        .collect::<Vec<_>>()
+        .into()
+}
@@ -0,0 +1,65 @@
+use crate::children::{ChildMap, HashedChildren};
+use crate::node::Node;
+use crate::token::TokenAtom;
+use crate::view::TrieView;
+use std::borrow::Cow;
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hash};
+use std::sync::Arc;
+
+/// trie树某一时刻的只读快照，由`Trie::freeze`生成。结点保存在`Arc`中，
+/// 复制快照只增加引用计数，在`V`和`S`满足条件时可以在线程之间共享。
+/// 之后对原来的trie树的修改不会影响已经生成的快照，写入方可以在修改之后重新生成快照，
+/// 再整体替换读取方持有的快照
+pub struct FrozenTrie<'a, V, S = RandomState, K: TokenAtom = Cow<'a, str>, M: ChildMap = HashedChildren<S>> {
+    // 复制出来的结点
+    root: Arc<Node<'a, V, S, K, M>>,
+    // 多层wildcard组是否也匹配与前缀完全相同的key
+    mwc_includes_prefix: bool,
+    // 生成快照时是否有wildcard组
+    has_wildcard: bool,
+}
+
+impl<'a, V, S, K, M> FrozenTrie<'a, V, S, K, M>
+where
+    K: TokenAtom,
+    M: ChildMap,
+    V: Eq + Hash + Clone,
+    S: BuildHasher + Default,
+{
+    /// 由复制出来的根结点生成快照
+    pub(crate) fn new(root: Node<'a, V, S, K, M>, mwc_includes_prefix: bool, has_wildcard: bool) -> Self {
+        FrozenTrie { root: Arc::new(root), mwc_includes_prefix, has_wildcard }
+    }
+
+    /// 返回快照上的只读视图，用于其它的查询
+    pub fn view(&self) -> TrieView<'_, 'a, V, S, K, M> {
+        TrieView::new(&self.root, self.mwc_includes_prefix, self.has_wildcard)
+    }
+
+    /// 返回能与keys匹配的所有值，不经过缓存
+    pub fn find<'k>(&self, keys: impl AsRef<[&'k K::Key]>) -> Vec<V> {
+        self.view().find(keys)
+    }
+
+    /// 是否有与keys匹配的值存在，包含带有wildcard的
+    pub fn exist<'k>(&self, keys: impl AsRef<[&'k K::Key]>) -> bool {
+        self.view().exist(keys)
+    }
+
+    /// 返回能与keys匹配的值的数量，与`find`返回的结果数量相同
+    pub fn count<'k>(&self, keys: impl AsRef<[&'k K::Key]>) -> usize {
+        self.view().count(keys)
+    }
+}
+
+// 手动实现，只复制`Arc`，不需要`V: Clone`和`S: Clone`
+impl<'a, V, S, K: TokenAtom, M: ChildMap> Clone for FrozenTrie<'a, V, S, K, M> {
+    fn clone(&self) -> Self {
+        FrozenTrie {
+            root: Arc::clone(&self.root),
+            mwc_includes_prefix: self.mwc_includes_prefix,
+            has_wildcard: self.has_wildcard,
+        }
+    }
+}
@@ -0,0 +1,143 @@
+use crate::children::{ChildMap, HashedChildren};
+use crate::error::Error;
+use crate::node::ValueSet;
+use crate::token::{TokenAtom, Tokens};
+use crate::Trie;
+use std::borrow::Cow;
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hash};
+use std::ops::{Deref, DerefMut};
+
+/// `Trie::group_mut`返回的组的可变引用，可以直接读取组中的值，修改则通过`insert`、`remove`等方法。
+/// 这些方法与trie树的同名方法相同：受`set_group_limit`的限制、删除与组的tokens匹配的缓存结果、
+/// 通知观察者，移除的值也不再过期，不会因为绕过trie树而破坏这些状态
+pub struct GroupGuard<'t, 'a, V, const N: usize, S = RandomState, K = Cow<'a, str>, M = HashedChildren<S>>
+where
+    K: TokenAtom,
+    M: ChildMap,
+    V: Eq + Hash,
+    S: BuildHasher + Default,
+{
+    // 组所在的trie树
+    trie: &'t mut Trie<'a, V, N, S, K, M>,
+    // 组对应的tokens
+    tokens: Tokens<'a, K>,
+}
+
+impl<'t, 'a, V, const N: usize, S, K, M> GroupGuard<'t, 'a, V, N, S, K, M>
+where
+    K: TokenAtom,
+    M: ChildMap,
+    V: Eq + Hash,
+    S: BuildHasher + Default,
+{
+    /// 初始化，tokens对应的组已经存在
+    pub(crate) fn new(trie: &'t mut Trie<'a, V, N, S, K, M>, tokens: Tokens<'a, K>) -> Self {
+        GroupGuard { trie, tokens }
+    }
+
+    /// 向组中添加值，见`Trie::try_insert`
+    pub fn insert(&mut self, value: V) -> Result<(), Error> {
+        self.trie.try_insert(&self.tokens, value)
+    }
+
+    /// 与`HashSet::remove`一样从组中移除值，`DuplicateMode::Count`下不论值被添加了几次。返回值是否存在
+    pub fn remove(&mut self, value: &V) -> bool {
+        self.take(value).is_some()
+    }
+
+    /// 取出组中与value相等的值，见`Trie::take`
+    pub fn take(&mut self, value: &V) -> Option<V> {
+        self.trie.take(&self.tokens, value)
+    }
+
+    /// 只保留组中f返回true的值，返回移除的值的数量
+    pub fn retain(&mut self, f: impl FnMut(&V) -> bool) -> usize {
+        self.trie.retain_group(&self.tokens, f)
+    }
+}
+
+impl<'t, 'a, V, const N: usize, S, K, M> Deref for GroupGuard<'t, 'a, V, N, S, K, M>
+where
+    K: TokenAtom,
+    M: ChildMap,
+    V: Eq + Hash,
+    S: BuildHasher + Default,
+{
+    type Target = ValueSet<V, S>;
+
+    fn deref(&self) -> &Self::Target {
+        // 组在`group_mut`中创建，之后的修改都不会删除结点
+        self.trie.group(&self.tokens).expect("group created by group_mut")
+    }
+}
+
+/// `Trie::insert_default`返回的组中唯一的值的可变引用。值在此期间从组中取出，
+/// 释放时再放回组中，因此可以修改值的内容，包括影响hash的部分。
+/// 与`Trie::update`一样，有观察者时会依次收到`Removed`和`Inserted`事件
+pub struct ValueGuard<'t, 'a, V, const N: usize, S = RandomState, K = Cow<'a, str>, M = HashedChildren<S>>
+where
+    K: TokenAtom,
+    M: ChildMap,
+    V: Eq + Hash,
+    S: BuildHasher + Default,
+{
+    // 取出的值，只在释放时为None
+    value: Option<V>,
+    // 值所在的组
+    group: GroupGuard<'t, 'a, V, N, S, K, M>,
+}
+
+impl<'t, 'a, V, const N: usize, S, K, M> ValueGuard<'t, 'a, V, N, S, K, M>
+where
+    K: TokenAtom,
+    M: ChildMap,
+    V: Eq + Hash,
+    S: BuildHasher + Default,
+{
+    /// 初始化，value已经从group中取出
+    pub(crate) fn new(value: V, group: GroupGuard<'t, 'a, V, N, S, K, M>) -> Self {
+        ValueGuard { value: Some(value), group }
+    }
+}
+
+impl<'t, 'a, V, const N: usize, S, K, M> Deref for ValueGuard<'t, 'a, V, N, S, K, M>
+where
+    K: TokenAtom,
+    M: ChildMap,
+    V: Eq + Hash,
+    S: BuildHasher + Default,
+{
+    type Target = V;
+
+    fn deref(&self) -> &V {
+        self.value.as_ref().unwrap()
+    }
+}
+
+impl<'t, 'a, V, const N: usize, S, K, M> DerefMut for ValueGuard<'t, 'a, V, N, S, K, M>
+where
+    K: TokenAtom,
+    M: ChildMap,
+    V: Eq + Hash,
+    S: BuildHasher + Default,
+{
+    fn deref_mut(&mut self) -> &mut V {
+        self.value.as_mut().unwrap()
+    }
+}
+
+// 把值放回组中，组已满时放不回去，值被丢弃
+impl<'t, 'a, V, const N: usize, S, K, M> Drop for ValueGuard<'t, 'a, V, N, S, K, M>
+where
+    K: TokenAtom,
+    M: ChildMap,
+    V: Eq + Hash,
+    S: BuildHasher + Default,
+{
+    fn drop(&mut self) {
+        if let Some(value) = self.value.take() {
+            let _ = self.group.insert(value);
+        }
+    }
+}
@@ -0,0 +1,142 @@
+use crate::token::TokenAtom;
+use std::borrow::{Borrow, Cow};
+use std::cmp::Ordering;
+use std::collections::HashSet;
+use std::hash::{Hash, Hasher};
+use std::ops::Deref;
+use std::sync::Arc;
+
+/// 子结点的键，Q是原子的借用形式。对外的API仍然使用`&Q`，只在内部区分原子的存储方式
+#[derive(Debug)]
+pub(crate) enum Atom<K, Q: ?Sized> {
+    // 直接保存token中的原子
+    Plain(K),
+    // 由Interner共享存储的键
+    Shared(Arc<Q>),
+}
+
+/// 以K为原子的子结点的键
+pub(crate) type ChildKey<K> = Atom<K, <K as TokenAtom>::Key>;
+
+impl<K: TokenAtom> ChildKey<K> {
+    /// 转换为token中使用的原子
+    pub(crate) fn to_atom(&self) -> K {
+        match self {
+            Atom::Plain(k) => k.clone(),
+            Atom::Shared(k) => K::from_key(k),
+        }
+    }
+
+    /// 转换为token中使用的原子，不需要复制Plain中的原子
+    pub(crate) fn into_atom(self) -> K {
+        match self {
+            Atom::Plain(k) => k,
+            Atom::Shared(k) => K::from_key(&k),
+        }
+    }
+}
+
+// 手动实现，避免derive带来的`Q: Clone`约束，str没有实现Clone
+impl<K: Clone, Q: ?Sized> Clone for Atom<K, Q> {
+    fn clone(&self) -> Self {
+        match self {
+            Atom::Plain(k) => Atom::Plain(k.clone()),
+            Atom::Shared(k) => Atom::Shared(k.clone()),
+        }
+    }
+}
+
+impl<K: Borrow<Q>, Q: ?Sized> Deref for Atom<K, Q> {
+    type Target = Q;
+
+    fn deref(&self) -> &Q {
+        match self {
+            Atom::Plain(k) => k.borrow(),
+            Atom::Shared(k) => k,
+        }
+    }
+}
+
+// 以下的比较和hash都与Q一致，这样才能用&Q在HashMap中查找
+impl<K: Borrow<Q>, Q: ?Sized> Borrow<Q> for Atom<K, Q> {
+    fn borrow(&self) -> &Q {
+        self
+    }
+}
+
+impl<K: Borrow<Q>, Q: ?Sized + Hash> Hash for Atom<K, Q> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        (**self).hash(state)
+    }
+}
+
+impl<K: Borrow<Q>, Q: ?Sized + PartialEq> PartialEq for Atom<K, Q> {
+    fn eq(&self, other: &Self) -> bool {
+        **self == **other
+    }
+}
+
+impl<K: Borrow<Q>, Q: ?Sized + Eq> Eq for Atom<K, Q> {}
+
+impl<K: Borrow<Q>, Q: ?Sized + Ord> PartialOrd for Atom<K, Q> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<K: Borrow<Q>, Q: ?Sized + Ord> Ord for Atom<K, Q> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        (**self).cmp(&**other)
+    }
+}
+
+/// 原子的驻留池，使相同的原子共享同一份存储。目前只驻留字符串：
+/// 借用自源字符串的token本身就不占用额外的存储，因此只驻留需要自己持有的字符串
+pub(crate) struct Interner<K: TokenAtom> {
+    keys: HashSet<Arc<K::Key>>,
+    // 原子需要共享存储时返回其键，否则返回None
+    owned: fn(&K) -> Option<&K::Key>,
+    // 生成键的共享存储
+    share: fn(&K::Key) -> Arc<K::Key>,
+}
+
+impl<'a> Interner<Cow<'a, str>> {
+    /// 驻留自己持有的字符串的驻留池
+    pub(crate) fn strings() -> Self {
+        Interner {
+            keys: HashSet::new(),
+            owned: |s| match s {
+                Cow::Borrowed(_) => None,
+                Cow::Owned(owned) => Some(owned.as_str()),
+            },
+            share: |s| Arc::from(s),
+        }
+    }
+}
+
+impl<K: TokenAtom> Interner<K> {
+    /// 返回k对应的键，需要自己持有的原子会与池中相同的原子共享存储
+    pub(crate) fn intern(&mut self, k: &K) -> ChildKey<K> {
+        let owned = match (self.owned)(k) {
+            None => return Atom::Plain(k.clone()),
+            Some(owned) => owned,
+        };
+        if let Some(shared) = self.keys.get(owned) {
+            return Atom::Shared(shared.clone());
+        }
+        let shared = (self.share)(owned);
+        self.keys.insert(shared.clone());
+        Atom::Shared(shared)
+    }
+
+    /// 释放已经没有被任何结点使用的原子
+    pub(crate) fn shrink_to_fit(&mut self) {
+        self.keys.retain(|k| Arc::strong_count(k) > 1);
+        self.keys.shrink_to_fit();
+    }
+
+    /// 清空驻留池
+    pub(crate) fn clear(&mut self) {
+        self.keys.clear();
+    }
+}
@@ -0,0 +1,112 @@
+use crate::children::{ChildContainer, ChildMap, HashedChildren};
+use crate::node::{Node, ValueIntoIter};
+use std::collections::HashMap;
+use crate::token::{Token, TokenAtom, Tokens};
+use std::borrow::Cow;
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hash};
+
+// 待拆解的结点，其父结点的深度，以及从父结点到达该结点的token，根结点没有token
+type PendingNode<'a, V, S, K, M> = (usize, Option<Token<'a, K>>, Box<Node<'a, V, S, K, M>>);
+
+// 待输出的组对应的tokens、组中的值，以及值的额外次数
+type PendingGroup<'a, V, S, K> = (Vec<Token<'a, K>>, ValueIntoIter<V>, Option<HashMap<V, usize, S>>);
+
+// 值对应的tokens、值本身，以及额外次数和保存额外次数的值
+pub(crate) type CountedItem<'a, V, K> = (Tokens<'a, K>, V, Option<(V, usize)>);
+
+/// 消耗trie树，依次输出每一个值以及其对应的tokens，顺序不固定
+pub struct IntoIter<'a, V, S = RandomState, K: TokenAtom = Cow<'a, str>, M: ChildMap = HashedChildren<S>> {
+    // 待拆解的结点
+    nodes: Vec<PendingNode<'a, V, S, K, M>>,
+    // 到达当前拆解的结点的路径，所有结点共用，避免每个结点都复制一份路径
+    path: Vec<Token<'a, K>>,
+    // 待输出的组
+    groups: Vec<PendingGroup<'a, V, S, K>>,
+}
+
+impl<'a, V, S, K: TokenAtom, M: ChildMap> IntoIter<'a, V, S, K, M> {
+    /// 从根结点开始拆解
+    pub(crate) fn new(root: Box<Node<'a, V, S, K, M>>) -> Self {
+        IntoIter {
+            nodes: vec![(0, None, root)],
+            path: vec![],
+            groups: vec![],
+        }
+    }
+}
+
+impl<'a, V, S, K, M> Iterator for IntoIter<'a, V, S, K, M>
+where
+    K: TokenAtom,
+    M: ChildMap,
+    V: Eq + Hash,
+    S: BuildHasher + Default,
+{
+    type Item = (Tokens<'a, K>, V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_counted().map(|(tokens, value, _)| (tokens, value))
+    }
+}
+
+impl<'a, V, S, K, M> IntoIter<'a, V, S, K, M>
+where
+    K: TokenAtom,
+    M: ChildMap,
+    V: Eq + Hash,
+    S: BuildHasher + Default,
+{
+    /// 与`next`相同，同时输出`DuplicateMode::Count`下值的额外次数以及保存额外次数的值，没有重复添加过时为None
+    pub(crate) fn next_counted(&mut self) -> Option<CountedItem<'a, V, K>> {
+        loop {
+            // 先输出已经拆解出来的组中的值
+            if let Some((path, values, extras)) = self.groups.last_mut() {
+                match values.next() {
+                    Some(v) => {
+                        let extra = extras.as_mut().and_then(|extras| extras.remove_entry(&v));
+                        return Some((path.clone().into(), v, extra));
+                    },
+                    None => {
+                        self.groups.pop();
+                        continue;
+                    }
+                }
+            }
+
+            // 所有组都输出完了，再拆解下一个结点，没有结点时迭代结束
+            let (depth, token, node) = self.nodes.pop()?;
+            self.path.truncate(depth);
+            self.path.extend(token);
+            let depth = self.path.len();
+            let mut parts = node.into_parts();
+            let start = self.nodes.len();
+            for (s, child) in parts.children.drain() {
+                self.nodes.push((depth, Some(Token::Normal(s.into_atom())), child));
+            }
+            if let Some(child) = parts.o_node {
+                self.nodes.push((depth, Some(Token::OneWildcard(None)), child));
+            }
+            for (alternatives, child) in parts.alt_nodes.into_iter() {
+                self.nodes.push((depth, Some(Token::OneOf(alternatives)), child));
+            }
+            for ((lo, hi), child) in parts.range_nodes.into_iter() {
+                self.nodes.push((depth, Some(Token::NumRange(lo, hi)), child));
+            }
+            // 倒序入栈，子结点按照普通子结点、单层wildcard、多选一token、数字范围token的顺序出栈
+            self.nodes[start..].reverse();
+            let (extras, mwc_extras) = match parts.duplicates {
+                None => (None, None),
+                Some(duplicates) => (Some(duplicates.exact), Some(duplicates.mwc)),
+            };
+            if !parts.m_value_set.is_empty() {
+                let mut mwc_path = self.path.clone();
+                mwc_path.push(Token::MultiWildcard);
+                self.groups.push((mwc_path, parts.m_value_set.into_iter(), mwc_extras));
+            }
+            if !parts.value_set.is_empty() {
+                self.groups.push((self.path.clone(), parts.value_set.into_iter(), extras));
+            }
+        }
+    }
+}
@@ -1,89 +1,369 @@
+mod cache;
+mod children;
+mod compact;
+pub mod error;
+mod event;
+mod expiry;
+mod frozen;
+mod guard;
+mod intern;
+mod iter;
+mod matcher;
 mod node;
+mod oplog;
+mod reverse;
+mod schema;
 pub mod token;
+mod view;
 
-pub use token::{Token, Tokens};
-use node::Node;
-use std::hash::Hash;
-use lru_map::LRUMap;
+pub use cache::{CacheMode, CacheOutcome};
+pub use children::{ChildContainer, ChildMap, HashedChildren, OrderedChildren};
+pub use compact::CompactTrie;
+pub use error::{Error, LoadError};
+pub use event::{Observer, TrieEvent};
+pub use frozen::FrozenTrie;
+pub use guard::{GroupGuard, ValueGuard};
+pub use iter::IntoIter;
+pub use matcher::Matcher;
+pub use node::DuplicateMode;
+pub use oplog::{Op, OpLog};
+pub use reverse::ReverseTrie;
+pub use schema::SchemaTrie;
+pub use token::{Token, TokenAtom, Tokens, TokensBuilder};
+pub use view::{FindResult, MatchBreakdown, MatchPriority, NodeInfo, PriorityFn, SubStats, TrieView};
+use cache::{bucket_of, Cached, LruCache, QueryCache};
+use expiry::Expiries;
+use intern::Interner;
+use node::{Node, ValueSet};
+use token::TokenParser;
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
+use std::collections::hash_map::RandomState;
+use std::fmt::Debug;
+use std::hash::{BuildHasher, Hash};
+use std::time::Instant;
 
-#[derive(Default)]
-pub struct Trie<'a, V, const N: usize> {
+/// trie树。K是普通token的原子，默认为字符串，也可以是`u32`等整数ID，此时key为`&u32`，见[`TokenAtom`]
+/// M决定普通token对应的子结点的容器，默认为`HashMap`，见[`ChildMap`]
+pub struct Trie<'a, V, const N: usize, S = RandomState, K: TokenAtom = Cow<'a, str>, M: ChildMap = HashedChildren<S>> {
     // 查询结果的缓存
-    cache: LRUMap<Vec<&'a str>, Vec<V>, N>,
+    cache: QueryCache<'a, V, N, K>,
+    // 缓存中保存的结果的形式
+    cache_mode: CacheMode,
     // 根结点
-    root: Box<Node<'a, V>>,
+    root: Box<Node<'a, V, S, K, M>>,
+    // 值发生变化时的观察者
+    observer: Option<Observer<'a, V, K>>,
+    // 复制值的函数，只有生成事件时需要。设置观察者或者操作日志时V一定实现了Clone，
+    // 这样其它的操作就不需要`V: Clone`
+    clone_value: Option<fn(&V) -> V>,
+    // 子结点键的字符串驻留池，None表示不驻留
+    interner: Option<Interner<K>>,
+    // find_ranked中不同组的排列顺序
+    priority: MatchPriority<K>,
+    // 操作日志，None表示不记录
+    op_log: Option<OpLog<V, K::Static>>,
+    // 多层wildcard组是否也匹配其前缀本身，例如`a.>`匹配`a`
+    mwc_includes_prefix: bool,
+    // 每个组最多可以有的值的数量，None表示不限制
+    group_limit: Option<usize>,
+    // 向组中重复添加已经存在的值时的行为
+    duplicate_mode: DuplicateMode,
+    // 是否可能存在wildcard。插入带有wildcard的tokens时置为true，移除时不会立即重新计算，
+    // 只在清空和`shrink_to_fit`时更新。为false时查询只需要沿着普通token对应的子结点向下
+    has_any_wildcard: bool,
+    // 经过缓存的查询中每个keys被查询的次数，None表示不统计
+    key_counts: Option<HashMap<Vec<&'a K::Key>, u32>>,
+    // 由`insert_with_ttl`添加的值的过期时间
+    expiries: Expiries<'a, V, K>,
 }
 
-impl<'a, V, const N: usize> Trie<'a, V, N>
+/// 用`BTreeMap`保存普通子结点的trie树，`visit`、`into_iter`和`debug_tree`等遍历都按照子结点的键的顺序，
+/// 输出是确定的，不需要每次遍历时排序。代价是查找子结点比`Trie`慢
+pub type OrderedTrie<'a, V, const N: usize, S = RandomState, K = Cow<'a, str>> = Trie<'a, V, N, S, K, OrderedChildren>;
+
+impl<'a, V, const N: usize, S, K, M> Trie<'a, V, N, S, K, M>
 where
-    V: Eq + Hash + Clone
+    K: TokenAtom,
+    M: ChildMap,
+    V: Eq + Hash,
+    S: BuildHasher + Default,
 {
     /// 初始化
-    pub fn new() -> Trie<'a, V, N> {
+    pub fn new() -> Trie<'a, V, N, S, K, M> {
         Trie {
-            cache: LRUMap::default(),
+            cache: LruCache::default(),
+            cache_mode: CacheMode::Values,
             root: Box::new(Node::new()),
+            observer: None,
+            clone_value: None,
+            interner: None,
+            priority: MatchPriority::ExactFirst,
+            op_log: None,
+            mwc_includes_prefix: false,
+            group_limit: None,
+            duplicate_mode: DuplicateMode::Ignore,
+            has_any_wildcard: false,
+            key_counts: None,
+            expiries: Expiries::new(),
+        }
+    }
+
+    /// 初始化，并为根结点预留children个子结点的空间。
+    /// 适用于事先知道第一层会有大量不同token的场景，避免预加载时反复扩容
+    pub fn with_root_capacity(children: usize) -> Trie<'a, V, N, S, K, M> {
+        Trie {
+            root: Box::new(Node::with_capacity(children)),
+            ..Self::new()
         }
     }
 
-    /// 添加键值对
-    pub fn insert(&mut self, tokens: &Tokens<'a>, value: V) {
+    /// 添加键值对。组中的值已经达到`set_group_limit`设置的上限时不会添加，
+    /// 需要知道是否添加成功时使用`try_insert`
+    pub fn insert(&mut self, tokens: &Tokens<'a, K>, value: V) {
+        // 组已满时不添加，这里忽略错误
+        let mode = self.duplicate_mode;
+        let _ = self.insert_by(tokens, value, |node, value, is_mwc| node.add_in_mode(value, is_mwc, mode));
+    }
+
+    /// 与`insert`相同，但是使用调用者事先计算的value的hash，不再对value计算hash。
+    /// 适用于hash代价很高、又要插入到很多模式下的值。每个组的hasher都是`S::default()`，
+    /// 因此S的所有实例必须计算出相同的hash，例如`BuildHasherDefault`，而不能是`RandomState`。
+    /// hash必须等于`S::default().hash_one(&value)`，否则组会被破坏，debug构建中会检查
+    #[cfg(feature = "hashbrown")]
+    pub fn insert_prehashed(&mut self, tokens: &Tokens<'a, K>, value: V, hash: u64) {
+        let mode = self.duplicate_mode;
+        let _ = self.insert_by(tokens, value, |node, value, is_mwc| node.add_prehashed_in_mode(value, hash, is_mwc, mode));
+    }
+
+    // 找到tokens对应的结点，由add把value放到结点的组中，add返回是否新添加了值。
+    // 计数模式下没有新添加值时增加了次数，也会生成事件。组已满并且value不在组中时返回错误，不做任何修改
+    fn insert_by(&mut self, tokens: &Tokens<'a, K>, value: V, add: impl FnOnce(&mut Node<'a, V, S, K, M>, V, bool) -> bool) -> Result<(), Error> {
+        let limit = self.group_limit;
+        // 上限为0时任何值都不能添加，也不需要创建结点
+        if limit == Some(0) {
+            return Err(Error::GroupFull);
+        }
+        // 只有观察者和操作日志需要value的复制
+        let event_value = self.cloner().map(|clone| clone(&value));
         // 查找对应的节点
         let (node, is_mwc) = self.must_find_node_mut(tokens);
-        // 找到之后就把value给放进去，如果存在mwc则放在mwc里面去
-        if is_mwc {
-            node.mwc_add(value.clone());
-        } else {
-            node.add(value.clone());
+        if limit.is_some_and(|max| node.is_group_full(&value, is_mwc, max)) {
+            return Err(Error::GroupFull);
         }
+        node.set_capture_names(tokens.capture_names(), is_mwc);
+        // 找到之后就把value给放进去
+        let added = add(node, value, is_mwc);
 
         // 删除与当前tokens匹配的缓存结果，因为已经过期
-        self.cache.remove(|keys| tokens.match_keys(keys));
-    }
-
-    /// 返回能与keys匹配的所有值的迭代器，如果不存在键，返回空迭代器
-    pub fn find(&mut self, keys: impl AsRef<[&'a str]>) -> Vec<V> {
-        let keys = keys.as_ref().to_vec();
-        // 先查找cache，如果命中就返回
-        if let Some(res) = self.cache.get(&keys) {
-            return (*res).clone();
-        }
-
-        // 保存结果
-        let mut values: Vec<V> = Vec::new();
-        // 迭代key来获得最终node
-        let nodes = keys.iter()
-            // 待处理的nodes
-            .try_fold(vec![self.root.as_ref(), ],
-                |nodes, token| {
-                    // 如果是空node，那就不用查找了
-                    if nodes.len() == 0 {
-                        return Err(());
-                    }
-                    
-                    let mut next_nodes: Vec<&Node<V>> = Vec::new();
-                    for node in nodes.into_iter() {
-                        // 多层wildcard必然满足tokens的需求，所以直接添加到values中
-                        values.extend(node.mwc_values_owned());
-                        // 符合当前token的node可以是token对应的，也可以是owc对应的
-                        next_nodes.extend(node.owc_node());
-                        if let Some(n) = node.get_child_node(token) {
-                            next_nodes.push(n);
-                        }
-                    }
-                    Ok(next_nodes)
-                }).unwrap_or(vec![]);
-        // 先迭代mwc中的结果
-        values.extend(nodes.into_iter().flat_map(|n| n.values_owned()));
-        self.cache.put(keys, values.clone());
-        values
+        self.invalidate(tokens);
+        let changed = added || self.duplicate_mode == DuplicateMode::Count;
+        if let (true, Some(value)) = (changed, event_value) {
+            self.notify(|_| TrieEvent::Inserted { pattern: tokens.clone(), value });
+        }
+        Ok(())
+    }
+
+    /// 设置向组中重复添加已经存在的值时的行为，默认为`DuplicateMode::Ignore`。
+    /// `DuplicateMode::Count`记录每个值被添加的次数，`remove`每次减少一次，次数为0时才移除值，
+    /// 其它的移除方法仍然直接移除值。只影响之后的添加和移除，已经存在的值的次数为1。
+    /// 计数模式下次数的每次增减也生成`Inserted`和`Removed`事件，其它的移除方法一次移除值的所有次数时，
+    /// 每一次都生成一个`Removed`事件，因此在同样是计数模式的trie树上重放操作日志时可以还原次数
+    pub fn set_duplicate_mode(&mut self, mode: DuplicateMode) {
+        self.duplicate_mode = mode;
+    }
+
+    /// 返回value在tokens对应的组中被添加的次数，不进行wildcard匹配，值不存在时为0。
+    /// 只有`DuplicateMode::Count`下才会大于1
+    pub fn count_of(&self, tokens: &Tokens<'a, K>, value: &V) -> usize {
+        match self.snapshot().find_node(tokens) {
+            (None, _) => 0,
+            (Some(node), mwc) => node.count_in_mode(value, mwc, self.duplicate_mode),
+        }
+    }
+
+    /// 设置每个组最多可以有的不同的值的数量，防止一个热门的模式无限制地积累值。
+    /// 只影响之后的添加，已经超过上限的组不会被截断。默认不限制
+    pub fn set_group_limit(&mut self, max: usize) {
+        self.group_limit = Some(max);
+    }
+
+    /// 向tokens对应的组中添加多个值，只查找一次结点，也只删除一次过期的缓存结果。
+    /// 返回新添加的值的数量，组中已经存在的值不计算在内，组已满之后的值会被跳过
+    pub fn insert_values(&mut self, tokens: &Tokens<'a, K>, values: impl IntoIterator<Item = V>) -> usize {
+        let cloner = self.cloner();
+        let limit = self.group_limit;
+        let mode = self.duplicate_mode;
+        // 新添加的值，只有观察者和操作日志需要
+        let mut added_values = Vec::new();
+        let (node, is_mwc) = self.must_find_node_mut(tokens);
+        node.set_capture_names(tokens.capture_names(), is_mwc);
+        let mut added = 0;
+        for value in values.into_iter() {
+            if limit.is_some_and(|max| node.is_group_full(&value, is_mwc, max)) {
+                continue;
+            }
+            let event_value = cloner.map(|clone| clone(&value));
+            let is_new = node.add_in_mode(value, is_mwc, mode);
+            if is_new {
+                added += 1;
+            }
+            // 计数模式下增加次数也生成事件
+            if is_new || mode == DuplicateMode::Count {
+                added_values.extend(event_value);
+            }
+        }
+
+        self.invalidate(tokens);
+        for value in added_values.into_iter() {
+            self.notify(|_| TrieEvent::Inserted { pattern: tokens.clone(), value });
+        }
+        added
+    }
+
+    /// 将other中所有的值移动到当前trie树中，other会被清空，类似`Vec::append`。
+    /// 值不会被复制，两者的缓存都会被清空。`DuplicateMode::Count`下值的次数以及`insert_with_ttl`的过期时间随值一起移动。
+    /// 组达到`set_group_limit`设置的上限时放不下的值不会丢失，而是与其所在的组一起返回
+    pub fn append(&mut self, other: &mut Trie<'a, V, N, S, K, M>) -> Vec<(Tokens<'a, K>, V)> {
+        let root = std::mem::replace(&mut other.root, Box::new(Node::new()));
+        let mut expiries = std::mem::replace(&mut other.expiries, Expiries::new());
+        other.clear();
+        self.cache.clear();
+        let mode = self.duplicate_mode;
+        let mut rejected = Vec::new();
+        let mut values = IntoIter::new(root);
+        while let Some((tokens, value, extra)) = values.next_counted() {
+            let expiry = expiries.take(&tokens, &value);
+            // 先检查组是否已满，insert_by在组已满时会丢弃值
+            let full = match (self.group_limit, self.find_node_mut(&tokens)) {
+                (Some(max), Some((node, hasmwc))) => node.is_group_full(&value, hasmwc, max),
+                (Some(max), None) => max == 0,
+                (None, _) => false,
+            };
+            if full {
+                rejected.push((tokens, value));
+                continue;
+            }
+            let _ = self.insert_by(&tokens, value, |node, value, is_mwc| node.add_moved(value, is_mwc, mode, extra));
+            if let Some((value, expires_at)) = expiry {
+                self.expiries.set(&tokens, value, expires_at);
+            }
+        }
+        rejected
+    }
+
+    /// 移除观察者并返回
+    pub fn take_observer(&mut self) -> Option<Observer<'a, V, K>> {
+        self.observer.take()
+    }
+
+    /// 返回当前记录的操作日志，没有开始记录时返回None
+    pub fn op_log(&self) -> Option<&OpLog<V, K::Static>> {
+        self.op_log.as_ref()
+    }
+
+    /// 停止记录操作日志并返回
+    pub fn take_op_log(&mut self) -> Option<OpLog<V, K::Static>> {
+        self.op_log.take()
+    }
+
+    /// 清空trie树中所有的值以及缓存
+    pub fn clear(&mut self) {
+        *self.root = Node::new();
+        self.has_any_wildcard = false;
+        self.cache.clear();
+        self.expiries.clear();
+        if let Some(interner) = self.interner.as_mut() {
+            interner.clear();
+        }
+        self.notify(|_| TrieEvent::Cleared);
+    }
+
+    /// 检查tokens的结构之后再添加键值对，tokens结构错误时返回错误并且不做任何修改。
+    /// 组中的值已经达到`set_group_limit`设置的上限并且不包含value时返回`Error::GroupFull`
+    pub fn try_insert(&mut self, tokens: &Tokens<'a, K>, value: V) -> Result<(), Error> {
+        tokens.validate()?;
+        let mode = self.duplicate_mode;
+        self.insert_by(tokens, value, |node, value, is_mwc| node.add_in_mode(value, is_mwc, mode))
+    }
+
+    /// 返回tokens对应的组的可变引用，组不存在时创建，适用于对组中的值进行读取之后再修改。
+    /// 通过它进行的修改与直接调用trie树的方法相同，见[`GroupGuard`]
+    pub fn group_mut(&mut self, tokens: &Tokens<'a, K>) -> GroupGuard<'_, 'a, V, N, S, K, M> {
+        self.must_find_node_mut(tokens);
+        GroupGuard::new(self, tokens.clone())
+    }
+
+    /// 为tokens对应的节点的子节点预留空间，如果节点不存在则创建。
+    /// 适用于事先知道某一层会有大量子节点的场景，避免反复扩容
+    pub fn reserve(&mut self, tokens: &Tokens<'a, K>, additional: usize) {
+        let (node, _) = self.must_find_node_mut(tokens);
+        node.reserve(additional);
+    }
+
+    /// 删除没有值的结点，并释放所有结点以及查询缓存中多余的容量。
+    /// 适用于大量移除之后回收内存，缓存中的结果不会被删除
+    pub fn shrink_to_fit(&mut self) {
+        self.root.prune();
+        self.root.shrink_to_fit();
+        self.cache.shrink_to_fit();
+        self.has_any_wildcard = self.root.has_wildcard();
+        if let Some(interner) = self.interner.as_mut() {
+            interner.shrink_to_fit();
+        }
+    }
+
+    /// 查询缓存最多能保存的结果数量，即`N`
+    pub fn cache_capacity(&self) -> usize {
+        self.cache.capacity()
+    }
+
+    /// 查询缓存中当前保存的结果数量
+    pub fn cache_len(&self) -> usize {
+        self.cache.len()
+    }
+
+    /// 设置查询缓存中保存的结果的形式，默认为`CacheMode::Values`，改变时清空缓存。
+    /// `CacheMode::Patterns`只保存匹配的组的tokens，命中时再从组中读取值，缓存不会复制值
+    pub fn set_cache_mode(&mut self, mode: CacheMode) {
+        if self.cache_mode != mode {
+            self.cache_mode = mode;
+            self.cache.clear();
+        }
+    }
+
+    /// 开始统计经过缓存的查询（`find`、`find_traced`等）中每个keys被查询的次数，
+    /// 用于找出最常查询的keys来预热缓存或者决定缓存的大小。默认不统计，不统计时没有额外的开销
+    pub fn enable_hot_keys(&mut self) {
+        self.key_counts.get_or_insert_with(HashMap::new);
+    }
+
+    /// 返回被查询次数最多的top_n个keys以及其次数，按照次数从多到少排列，次数相同时按照keys排列。
+    /// 没有开始统计时返回空的列表，见`enable_hot_keys`
+    pub fn hot_keys(&self, top_n: usize) -> Vec<(Vec<&'a K::Key>, u32)> {
+        let mut counts: Vec<_> = match self.key_counts.as_ref() {
+            None => return vec![],
+            Some(counts) => counts.iter().map(|(keys, count)| (keys.clone(), *count)).collect(),
+        };
+        counts.sort_by(|(k1, c1), (k2, c2)| c2.cmp(c1).then_with(|| k1.cmp(k2)));
+        counts.truncate(top_n);
+        counts
     }
 
     /// 移除tokens对应的组中的value值。如果存在tokens组并且其中有value值，返回true。
-    /// 如果不存在tokens组或者tokens组中没有value值，返回false
-    pub fn remove(&mut self, tokens: &Tokens<'a>, value: &V) -> bool {
-        self.cache.remove(|keys| tokens.match_keys(keys));
-        match self.find_node_mut(tokens) {
+    /// 如果不存在tokens组或者tokens组中没有value值，返回false。
+    /// `DuplicateMode::Count`下value被添加了多次时只减少一次次数，值仍然留在组中，也返回true
+    pub fn remove(&mut self, tokens: &Tokens<'a, K>, value: &V) -> bool {
+        let counted = self.duplicate_mode == DuplicateMode::Count;
+        if let (true, Some((node, hasmwc))) = (counted, self.find_node_mut(tokens)) {
+            // 组中的值没有变化，缓存也不需要过期，但是次数的减少需要记录
+            if node.release(value, hasmwc) {
+                self.notify(|clone| TrieEvent::Removed { pattern: tokens.clone(), value: clone(value) });
+                return true;
+            }
+        }
+        self.invalidate(tokens);
+        let removed = match self.find_node_mut(tokens) {
             None => false,
             Some((node, hasmwc)) => {
                 if hasmwc {
@@ -92,110 +372,456 @@ where
                     node.remove(value)
                 }
             }
+        };
+        if removed {
+            self.after_remove(tokens, value, 1);
         }
+        removed
     }
 
-    /// 移除key对应的组中的所有value。如果存在keys则返回true，如果不存在则返回false
-    pub fn remove_all(&mut self, tokens: &Tokens<'a>) -> bool {
-        self.cache.remove(|keys| tokens.match_keys(keys));
-        match self.find_node_mut(tokens) {
-            None => false,
-            Some((node, hasmwc)) => 
+    /// 只有当pred对组中保存的与value相等的值返回true时，才移除该值。
+    /// 返回是否确实移除了值，值不存在或者pred返回false时不做任何修改。
+    /// 适用于例如带有引用计数的值，只在计数满足条件时才移除的场景
+    pub fn remove_if<F: FnOnce(&V) -> bool>(&mut self, tokens: &Tokens<'a, K>, value: &V, pred: F) -> bool {
+        let mode = self.duplicate_mode;
+        let removed = match self.find_node_mut(tokens) {
+            None => None,
+            Some((node, hasmwc)) => {
+                let existing = if hasmwc { node.mwc_get(value) } else { node.get(value) };
+                match existing {
+                    Some(existing) if pred(existing) => {
+                        let count = node.count_in_mode(value, hasmwc, mode);
+                        let taken = if hasmwc { node.mwc_take(value) } else { node.take(value) };
+                        taken.map(|v| (v, count))
+                    },
+                    _ => None,
+                }
+            }
+        };
+        // 只有确实移除了值，缓存才会过期
+        if let Some((value, count)) = removed {
+            self.invalidate(tokens);
+            self.after_remove(tokens, &value, count);
+            true
+        } else {
+            false
+        }
+    }
+
+    /// 从能与keys匹配的每一个组中移除value，包括路径上的单层wildcard组、多选一token组以及多层wildcard组，
+    /// 返回移除了value的组的数量。适用于只知道某个订阅者对应的key，需要将其从所有覆盖该key的模式中移除的场景。
+    /// 因此变空的结点会被一并删除
+    pub fn remove_matching(&mut self, keys: &[&K::Key], value: &V) -> usize {
+        // 先找到所有包含value的组，再逐个移除
+        let patterns = self.snapshot().patterns_containing(keys, value);
+        let mode = self.duplicate_mode;
+        let mut counts = Vec::with_capacity(patterns.len());
+        for pattern in patterns.iter() {
+            if let Some((node, hasmwc)) = self.find_node_mut(pattern) {
+                counts.push(node.count_in_mode(value, hasmwc, mode));
                 if hasmwc {
-                    node.mwc_remove_all()
+                    node.mwc_remove(value);
                 } else {
-                    node.remove_all()
+                    node.remove(value);
                 }
+            }
+            self.root.prune_path(pattern.strip_mwc());
+        }
+        if !patterns.is_empty() {
+            // 被移除的组能匹配哪些key不容易知道，所以移除所有包含value的缓存结果
+            self.cache.remove_entries(|_, cached| cached.contains(value));
+        }
+        for (pattern, count) in patterns.iter().zip(counts) {
+            self.after_remove(pattern, value, count);
         }
+        patterns.len()
     }
 
-    /// 找到key对应的node，返回其引用，如果没有，则返回None
-    #[allow(dead_code)]
-    fn find_node(&self, tokens: &Tokens<'a>) -> (Option<&Node<V>>, bool) {
-        let mut hasmwc = false;
-        let value = tokens.0.iter()
-            // 查找token对应的node，如果没有token就返回None
-            .fold(Some(& *self.root),
-                |node, token| {
-                    node.and_then(|n| {
-                        match token {
-                            Token::MultiWildcard => {
-                                hasmwc = true;
-                                Some(n)
-                            },
-                            Token::OneWildcard => {
-                                n.owc_node()
-                            },
-                            Token::Normal(s) => {
-                                n.get_child_node(s)
-                            }
-                        }
-                    })
-                });
-        (value, hasmwc)
+    /// 取出tokens对应的组中的value值。如果存在则返回组中保存的值，否则返回None
+    pub fn take(&mut self, tokens: &Tokens<'a, K>, value: &V) -> Option<V> {
+        let mode = self.duplicate_mode;
+        let taken = match self.find_node_mut(tokens) {
+            None => None,
+            Some((node, hasmwc)) => {
+                let count = node.count_in_mode(value, hasmwc, mode);
+                let taken = if hasmwc {
+                    node.mwc_take(value)
+                } else {
+                    node.take(value)
+                };
+                taken.map(|v| (v, count))
+            }
+        };
+        // 只有确实取出了值，缓存才会过期
+        let (value, count) = taken?;
+        self.invalidate(tokens);
+        self.after_remove(tokens, &value, count);
+        Some(value)
     }
 
-    // 是否有与keys匹配的值存在，包含带有wildcard的
-    pub fn exist(&self, keys: impl AsRef<[&'a str]>) -> bool {
-        // 迭代key来获得最终node
-        // 其中try_fold里面的Result没有错误的含义，只是用来使用Err来短路迭代
-        let nodes = keys.as_ref().iter()
-            // 待处理的nodes
-            .try_fold(vec![self.root.as_ref(), ],
-                |nodes, token| {
-                    // 如果是空node，那就不用查找了
-                    if nodes.len() == 0 {
-                        return Err(false);
+    /// 移除key对应的组中的所有value。如果存在keys则返回true，如果不存在则返回false
+    pub fn remove_all(&mut self, tokens: &Tokens<'a, K>) -> bool {
+        self.invalidate(tokens);
+        self.expiries.forget_group(tokens);
+        if !self.is_observed() {
+            return match self.find_node_mut(tokens) {
+                None => false,
+                Some((node, hasmwc)) =>
+                    if hasmwc {
+                        node.mwc_remove_all()
+                    } else {
+                        node.remove_all()
                     }
-                    let mut next_nodes: Vec<&Node<V>> = Vec::new();
-                    for node in nodes.into_iter() {
-                        // 存在mwc的结果则肯定有匹配值
-                        if !node.is_mwc_empty() { return Err(true); }
-                        // 符合当前token的node可以是token对应的，也可以是owc对应的
-                        next_nodes.extend(node.owc_node());
-                        if let Some(n) = node.get_child_node(token) {
-                            next_nodes.push(n);
+            };
+        }
+        // 有观察者时需要取出移除的值来通知，值被添加了几次就通知几次
+        let mode = self.duplicate_mode;
+        let removed = match self.find_node_mut(tokens) {
+            None => vec![],
+            Some((node, hasmwc)) => node.drain_counted(hasmwc),
+        };
+        let any_removed = !removed.is_empty();
+        for (value, times) in removed.into_iter() {
+            self.notify_removed(tokens, &value, removed_times(times, mode));
+        }
+        any_removed
+    }
+
+    /// 只保留f返回true的值，f的参数为值所在的组对应的tokens以及值本身，多层wildcard组的tokens以
+    /// `MultiWildcard`结尾，tokens中的单层wildcard不带捕获名。返回移除的值的数量。
+    /// 因此变空的结点会被删除，缓存会被清空
+    pub fn retain_with_pattern<F: FnMut(&Tokens<'a, K>, &V) -> bool>(&mut self, mut f: F) -> usize {
+        let cloner = self.cloner();
+        let expiries = &mut self.expiries;
+        // 被移除的值以及其所在的组，只有需要通知时才记录
+        let mut removed = Vec::new();
+        let mut count = 0;
+        // 到达当前结点的路径，所有结点共用
+        let mut path: Vec<Token<'a, K>> = Vec::new();
+        let mut stack = vec![(0, None, &mut *self.root)];
+        while let Some((depth, token, node)) = stack.pop() {
+            path.truncate(depth);
+            path.extend(token);
+            let (has_values, has_mwc_values) = (!node.is_empty(), !node.is_mwc_empty());
+            let mut retain_group = |pattern: Tokens<'a, K>, mwc: bool| {
+                node.retain_counted(mwc, |v, times| {
+                    let keep = f(&pattern, v);
+                    if !keep {
+                        count += 1;
+                        expiries.forget(&pattern, v);
+                        if let Some(clone) = cloner {
+                            removed.push((pattern.clone(), clone(v), times));
                         }
                     }
-                    Ok(next_nodes)
-                }
-            );
-        match nodes {
-            // 短路，直接输出内部包含值
-            Err(v) => { return v; },
-            // 没有短路，查找匹配的nodes中是否有值
-            Ok(ns) => {
-                for n in ns.into_iter() {
-                    if !n.is_empty() { return true; }
-                }
-                return false;
+                    keep
+                })
+            };
+            if has_values {
+                retain_group(Tokens(path.clone()), false);
+            }
+            if has_mwc_values {
+                let mut mwc_path = path.clone();
+                mwc_path.push(Token::MultiWildcard);
+                retain_group(Tokens(mwc_path), true);
             }
+            let depth = path.len();
+            stack.extend(node.token_child_nodes_mut().map(|(t, n)| (depth, Some(t), n)));
+        }
+        if count > 0 {
+            self.root.prune();
+            self.cache.clear();
+        }
+        let mode = self.duplicate_mode;
+        for (pattern, value, times) in removed.into_iter() {
+            self.notify_removed(&pattern, &value, removed_times(times, mode));
+        }
+        count
+    }
+
+    /// 移除prefix对应的整个子树，包括该结点本身以及其下所有的值，末尾的多层wildcard会被忽略。
+    /// 返回移除的值的数量
+    pub fn remove_subtree(&mut self, prefix: &Tokens<'a, K>) -> usize {
+        remove_cached(&mut self.cache, prefix, |keys| prefix.match_keys_prefix(keys));
+        let path = prefix.strip_mwc();
+        let detached = if path.is_empty() {
+            // 前缀为空时整棵树都要移除
+            self.has_any_wildcard = false;
+            Some(std::mem::replace(&mut self.root, Box::new(Node::new())))
+        } else {
+            self.root.detach(path)
+        };
+        let detached = match detached {
+            None => return 0,
+            Some(node) => node,
+        };
+        self.expiries.forget_prefix(path);
+        if !self.is_observed() {
+            return detached.subtree_len();
+        }
+        // 有观察者时逐个通知移除的值，tokens是相对于前缀的
+        let mode = self.duplicate_mode;
+        let mut removed = 0;
+        let mut values = IntoIter::new(detached);
+        while let Some((relative, value, extra)) = values.next_counted() {
+            let mut pattern = path.to_vec();
+            pattern.extend(relative.0);
+            let times = 1 + extra.map_or(0, |(_, extra)| extra);
+            self.notify_removed(&pattern.into(), &value, removed_times(times, mode));
+            removed += 1;
+        }
+        removed
+    }
+
+    // 是否有与keys匹配的值存在，包含带有wildcard的
+    pub fn exist<'k>(&self, keys: impl AsRef<[&'k K::Key]>) -> bool {
+        self.snapshot().exist(keys)
+    }
+
+    /// 返回任意一个与keys匹配的值，找到第一个值之后立即返回，不存在时返回None。
+    /// 不经过缓存，也不复制值
+    pub fn any_match<'k>(&self, keys: impl AsRef<[&'k K::Key]>) -> Option<&V> {
+        self.snapshot().any_match(keys)
+    }
+
+    /// 对一批subject分别判断是否有与其匹配的值存在，结果与逐个调用`exist`相同。
+    /// 首个key相同的subject共用从根结点出发的第一步匹配
+    pub fn exist_batch(&self, subjects: &[&[&K::Key]]) -> Vec<bool> {
+        self.snapshot().exist_batch(subjects)
+    }
+
+    /// 返回tokens对应的组中唯一的值，适用于每个组只有一个值的场景，例如特性开关。
+    /// 只查找tokens本身对应的组，不进行wildcard匹配。组不存在、为空或者有多个值时返回None
+    pub fn get_single(&self, tokens: &Tokens<'a, K>) -> Option<&V> {
+        self.snapshot().get_single(tokens)
+    }
+
+    /// 是否存在与keys完全相同、不含任何wildcard的组，并且组中有值。
+    /// 与`exist`不同，只由wildcard匹配到的值不算在内
+    pub fn exist_exact<'k>(&self, keys: impl AsRef<[&'k K::Key]>) -> bool {
+        self.snapshot().exist_exact(keys)
+    }
+
+    /// 返回能与keys匹配的值按照来源分类的数量：不含wildcard的组、含有单层wildcard的组以及多层wildcard组
+    pub fn match_breakdown<'k>(&self, keys: impl AsRef<[&'k K::Key]>) -> MatchBreakdown {
+        self.snapshot().match_breakdown(keys)
+    }
+
+    /// 设置`find_ranked`中来自不同组的值的排列顺序。`find`不受影响，仍然不排序
+    pub fn set_priority(&mut self, priority: MatchPriority<K>) {
+        self.priority = priority;
+    }
+
+    /// 遍历整棵树，对每个值调用f，参数是值所在的组的tokens，包括wildcard。
+    /// 与迭代器相比不需要保存遍历的状态，适用于一次性的统计，见[`TrieView::visit`]
+    pub fn visit<F: FnMut(&Tokens<'a, K>, &V)>(&self, f: F) {
+        self.snapshot().visit(f)
+    }
+
+    /// 统计整棵树中不含wildcard的组、含有单层wildcard的组和多层wildcard组中各有多少值，
+    /// 用于了解订阅的分布。与`match_breakdown`不同，这里不针对某个key
+    pub fn subscription_stats(&self) -> SubStats {
+        self.snapshot().subscription_stats()
+    }
+
+    /// 返回一个已经存在的、覆盖candidate的模式，即能与candidate匹配的key都能与该模式匹配，
+    /// 例如`a.>`覆盖`a.b.c`和`a.*`，`a.*`覆盖`a.(b|c)`。适用于判断新的订阅是否多余。不存在时返回None
+    pub fn covers<Q>(&self, candidate: &Tokens<Q>) -> Option<Tokens<'a, K>>
+    where
+        Q: TokenAtom<Key = K::Key>,
+    {
+        self.snapshot().covers(candidate)
+    }
+
+    /// prefix之下的所有subject是否都能匹配到值，即不存在以prefix开头、还有一个或者多个token却没有匹配的key。
+    /// 例如存在`a.>`或者同时存在`a.*`和`a.*.>`时`a`之下都被覆盖，适用于检查配置中的命名空间是否有兜底的订阅
+    pub fn has_catchall<Q>(&self, prefix: &Tokens<Q>) -> bool
+    where
+        Q: TokenAtom<Key = K::Key>,
+    {
+        self.snapshot().has_catchall(prefix)
+    }
+
+    /// 按照组返回与keys匹配的值：每个匹配的组对应的tokens以及组中所有值的引用。
+    /// 与`find`返回的值相同，只是按照来源的组分开，适用于按订阅组批量分发的场景。不经过缓存
+    pub fn find_grouped<'k>(&self, keys: impl AsRef<[&'k K::Key]>) -> Vec<(Tokens<'a, K>, Vec<&V>)> {
+        self.snapshot().find_grouped(keys)
+    }
+
+    /// 判断pattern是否与keys匹配，是判断单个模式的标准方法：值添加在pattern下时，
+    /// `find(keys)`包含该值当且仅当这里返回true。规则与`Tokens::match_keys`相同，
+    /// 另外遵循`set_mwc_includes_prefix`，例如开启时`a.>`也与`a`匹配
+    pub fn pattern_matches<'k, Q>(&self, pattern: &Tokens<Q>, keys: impl AsRef<[&'k K::Key]>) -> bool
+    where
+        Q: TokenAtom<Key = K::Key>,
+    {
+        pattern_matches(pattern, keys.as_ref(), self.mwc_includes_prefix)
+    }
+
+    /// 设置多层wildcard组是否也匹配其前缀本身。默认不匹配，`a.>`只匹配`a`之后至少还有一个token的key，
+    /// 设置为true之后`a.>`也匹配`a`，`>`也匹配空的key。所有的查询都遵循这个设置，修改设置会清空缓存
+    pub fn set_mwc_includes_prefix(&mut self, includes: bool) {
+        if self.mwc_includes_prefix != includes {
+            self.mwc_includes_prefix = includes;
+            self.cache.clear();
+        }
+    }
+
+    /// 返回与keys匹配的所有非空的组的tokens，不复制组中的值。用于调试某个key为什么被匹配
+    pub fn matching_patterns<'k>(&self, keys: impl AsRef<[&'k K::Key]>) -> Vec<Tokens<'a, K>> {
+        self.snapshot().matching_patterns(keys)
+    }
+
+    /// 返回逐个接收token进行匹配的匹配器，可以在key的token逐个到达时尽早发现不可能匹配的情况
+    pub fn matcher(&self) -> Matcher<'_, 'a, V, S, K, M> {
+        self.snapshot().matcher()
+    }
+
+    /// 返回不经过缓存的只读视图，视图上的查询只需要不可变引用
+    pub fn snapshot(&self) -> TrieView<'_, 'a, V, S, K, M> {
+        TrieView::new(&self.root, self.mwc_includes_prefix, self.has_any_wildcard)
+    }
+
+    /// 返回tokens对应的结点的直接子结点数量，路径不存在时返回None。
+    /// 只统计普通token对应的子结点，不包含单层wildcard和多选一token
+    pub fn child_count(&self, tokens: &Tokens<'a, K>) -> Option<usize> {
+        self.snapshot().child_count(tokens)
+    }
+
+    /// 返回tokens对应的结点的概要，包括两个组中的值的数量、子结点数量以及单层wildcard分支，
+    /// 路径不存在时返回None。见[`NodeInfo`]
+    pub fn node_info(&self, tokens: &Tokens<'a, K>) -> Option<NodeInfo> {
+        self.snapshot().node_info(tokens)
+    }
+
+    /// 返回整棵树中任意结点最大的直接子结点数量，可以用来发现某一层过于分散的键空间
+    pub fn max_fanout(&self) -> usize {
+        self.snapshot().max_fanout()
+    }
+
+    /// 返回所有注册在多层wildcard模式下的值，以及其以`MultiWildcard`结尾的模式，
+    /// 例如统计`a.>`这类订阅了整个子树的订阅者。只遍历结点，不需要从完整的`into_iter`中过滤
+    pub fn multi_wildcard_values(&self) -> Vec<(Tokens<'a, K>, &V)> {
+        self.snapshot().multi_wildcard_values()
+    }
+
+    /// 结点的数量，包括根结点
+    pub fn node_count(&self) -> usize {
+        self.root.descendants().count()
+    }
+
+    /// 将trie树的结构渲染为缩进的文本，用于调试wildcard匹配
+    pub fn debug_tree(&self) -> String
+    where
+        V: Debug,
+    {
+        self.snapshot().debug_tree()
+    }
+
+    // 如果有观察者，生成事件并通知观察者。event的参数是复制值的函数
+    fn notify(&mut self, event: impl FnOnce(fn(&V) -> V) -> TrieEvent<'a, V, K>) {
+        let clone = match self.cloner() {
+            Some(clone) => clone,
+            None => return,
+        };
+        let event = event(clone);
+        if let Some(log) = self.op_log.as_mut() {
+            log.record(&event, clone);
+        }
+        if let Some(observer) = self.observer.as_mut() {
+            observer(event);
+        }
+    }
+
+    /// 移除所有过期时间不晚于now的值，返回移除的值的数量，见`insert_with_ttl`。
+    /// 过期的值无论被添加了几次都会被移除。需要及时释放过期的值时定期调用，例如在定时器中传入`Instant::now()`
+    pub fn expire(&mut self, now: Instant) -> usize {
+        let expired = self.expiries.take_expired(now);
+        expired.iter().filter(|(tokens, value)| self.take(tokens, value).is_some()).count()
+    }
+
+    // 值从tokens对应的组中移除之后调用：删除其过期时间，并且通知观察者，count为值被移除之前的次数。
+    // 逐个移除值的方法都经过这里，成批移除值的方法直接删除整个组或者子树的过期时间
+    fn after_remove(&mut self, tokens: &Tokens<'a, K>, value: &V, count: usize) {
+        self.expiries.forget(tokens, value);
+        self.notify_removed(tokens, value, count);
+    }
+
+    // 通知观察者value从tokens对应的组中被移除了count次，每一次生成一个`Removed`事件，
+    // 这样重放操作日志时`remove`逐次减少次数，最终与移除之前一样
+    fn notify_removed(&mut self, tokens: &Tokens<'a, K>, value: &V, count: usize) {
+        for _ in 0..count {
+            self.notify(|clone| TrieEvent::Removed { pattern: tokens.clone(), value: clone(value) });
         }
     }
 
+    // 是否需要生成事件
+    fn is_observed(&self) -> bool {
+        self.observer.is_some() || self.op_log.is_some()
+    }
+
+    // 需要生成事件时返回复制值的函数，否则返回None
+    fn cloner(&self) -> Option<fn(&V) -> V> {
+        if self.is_observed() { self.clone_value } else { None }
+    }
+
+    /// 删除与tokens匹配的缓存结果，因为已经过期
+    fn invalidate(&mut self, tokens: &Tokens<'a, K>) {
+        invalidate(&mut self.cache, tokens, self.mwc_includes_prefix);
+    }
+
     // 找到key对应的node，返回其可变引用。如果没有对应node存在，则创建
-    fn must_find_node_mut(&mut self, tokens: &Tokens<'a>) -> (&mut Node<'a, V>, bool) {
-        // 是否遇到过了mwc
+    fn must_find_node_mut(&mut self, tokens: &Tokens<'a, K>) -> (&mut Node<'a, V, S, K, M>, bool) {
+        // 所有的结点都从这里创建，带有wildcard的tokens会生成wildcard结点或者组
+        if !tokens.has_no_wildcard() {
+            self.has_any_wildcard = true;
+        }
+        node_mut_or_insert(&mut self.root, &mut self.interner, tokens)
+    }
+
+    // tokens对应的组，不存在时返回None
+    pub(crate) fn group(&self, tokens: &Tokens<'a, K>) -> Option<&ValueSet<V, S>> {
         let mut hasmwc = false;
-        // 找到对应的node
-        let node = tokens.0.iter()
-            .fold(&mut *self.root,
-                |node, token| {
-                    match token {
-                        Token::MultiWildcard => {
-                            hasmwc = true;
-                            node
-                        },
-                        Token::OneWildcard => node.owc_node_mut(),
-                        Token::Normal(s) => node.get_child_node_mut_or_insert(s)
-                    }
+        let node = tokens.0.iter().try_fold(&*self.root, |node, token| match token {
+            Token::MultiWildcard => {
+                hasmwc = true;
+                Some(node)
+            },
+            _ => node.get_token_node(token),
+        })?;
+        Some(if hasmwc { node.mwc_value_set() } else { node.value_set() })
+    }
+
+    // 只保留tokens对应的组中f返回true的值，返回移除的值的数量。与`retain_with_pattern`相同，
+    // 移除的值不再过期并且会通知观察者，但是不删除变空的结点
+    pub(crate) fn retain_group(&mut self, tokens: &Tokens<'a, K>, mut f: impl FnMut(&V) -> bool) -> usize {
+        let cloner = self.cloner();
+        let expiries = &mut self.expiries;
+        let mut removed = Vec::new();
+        let mut count = 0;
+        let (node, mwc) = node_mut_or_insert(&mut self.root, &mut self.interner, tokens);
+        node.retain_counted(mwc, |v, times| {
+            let keep = f(v);
+            if !keep {
+                count += 1;
+                expiries.forget(tokens, v);
+                if let Some(clone) = cloner {
+                    removed.push((clone(v), times));
+                }
             }
-        );
-        (node, hasmwc)
+            keep
+        });
+        if count > 0 {
+            self.invalidate(tokens);
+        }
+        let mode = self.duplicate_mode;
+        for (value, times) in removed.into_iter() {
+            self.notify_removed(tokens, &value, removed_times(times, mode));
+        }
+        count
     }
 
     // 找到key对应的node，返回其可变引用。如果没有，则返回None
-    fn find_node_mut(&mut self, tokens: &Tokens<'a>) -> Option<(&mut Node<'a, V>, bool)> {
+    fn find_node_mut(&mut self, tokens: &Tokens<'a, K>) -> Option<GroupNode<'_, 'a, V, S, K, M>> {
         let mut hasmwc = false;
         tokens.0.iter()
             // 查找token对应的node，如果没有token就返回None
@@ -206,12 +832,7 @@ where
                             hasmwc = true;
                             Some(node)
                         },
-                        Token::OneWildcard => {
-                            Some(node.owc_node_mut())
-                        },
-                        Token::Normal(s) => {
-                            node.get_child_node_mut(s)
-                        }
+                        _ => node.get_token_node_mut(token),
                     }
                 }
             )
@@ -219,74 +840,3121 @@ where
     }
 }
 
-#[cfg(test)]
-mod tests
+// 需要复制值的方法：返回值的复制的查询、查询缓存，以及需要生成事件的设置
+impl<'a, V, const N: usize, S, K, M> Trie<'a, V, N, S, K, M>
+where
+    K: TokenAtom,
+    M: ChildMap,
+    V: Eq + Hash + Clone,
+    S: BuildHasher + Default,
 {
-    use super::*;
-    use crate::token::*;
-    use std::collections::HashSet;
+    /// 设置观察者，之后每次添加或者移除值、清空trie树时都会被调用。默认没有观察者
+    pub fn set_observer(&mut self, observer: Observer<'a, V, K>) {
+        self.observer = Some(observer);
+        self.clone_value = Some(V::clone);
+    }
 
-    // 两个迭代器中的元素在忽略顺序的情况下是否一一相等
-    fn vec_eq<V: Hash + Eq>(vec1: Vec<V>, vec2: Vec<V>) -> bool{
-        let set1: HashSet<V> = vec1.into_iter().collect();
-        let set2: HashSet<V> = vec2.into_iter().collect();
-        set1 == set2
+    /// 开始记录操作日志，之后每次确实改变了内容的添加、移除和清空都会被记录。默认不记录
+    pub fn enable_op_log(&mut self) {
+        self.op_log.get_or_insert_with(OpLog::new);
+        self.clone_value = Some(V::clone);
     }
 
-    #[test]
-    fn test_basic_trie() -> Result<(), CommonTokenError> {
-        let mut trie = Trie::<_, 10>::new();
-        let parser = CommonTokenParser::new('.', "*", ">");
-        trie.insert(&parser.parse_tokens("a")?, 1);
-        trie.insert(&parser.parse_tokens("a")?, 2);
-        trie.insert(&parser.parse_tokens("")?, 3);
-        trie.insert(&parser.parse_tokens("a.b")?, 5);
-        trie.insert(&parser.parse_tokens(".")?, 6);
-        trie.insert(&parser.parse_tokens("a")?, 8);
-        trie.insert(&parser.parse_tokens("a.b.c")?, 12);
-        assert!(vec_eq(trie.find(&["a"]), vec![1, 2, 8]));
-        assert!(vec_eq(trie.find(&[""]), vec![3, ]));
-        assert!(vec_eq(trie.find(&["a", "b"]), vec![5, ]));
-        assert!(vec_eq(trie.find(&["", ""]), vec![6, ]));
-        assert!(vec_eq(trie.find(&["a", "b", "c"]), vec![12,]));
-        assert_eq!(trie.find(vec!["b"]).len(), 0);
-        assert_eq!(trie.find(vec!["c"]).len(), 0);
-        assert_eq!(trie.remove(&parser.parse_tokens("a")?, &1), true);
-        assert_eq!(trie.remove(&parser.parse_tokens("a")?, &1), false);
-        assert_eq!(trie.remove(&parser.parse_tokens("a.b")?, &5), true);
-        assert_eq!(trie.remove(&parser.parse_tokens("a")?, &5), false);
-        assert!(vec_eq(trie.find(vec!["a"]), vec![2, 8, ]));
-        assert_eq!(trie.find(vec!["a", "b"]).len(), 0);
-        assert!(vec_eq(trie.find(vec!["a", "b", "c"]), vec![12, ]));
-        assert_eq!(trie.remove(&parser.parse_tokens("a.b")?, &5), false);
-        trie.insert(&parser.parse_tokens("a.b.c")?, 15);
-        trie.insert(&parser.parse_tokens("a.b.c")?, 17);
-        assert_eq!(trie.remove_all(&parser.parse_tokens("a.b.c")?), true);
-        assert_eq!(trie.find(vec!["a", "b", "c"]).len(), 0);
-        assert_eq!(trie.remove_all(&parser.parse_tokens("a")?), true);
-        assert_eq!(trie.remove_all(&parser.parse_tokens("a.b")?), false);
-        assert_eq!(trie.remove_all(&parser.parse_tokens("x.y.z")?), false);
-        Ok(())
+    /// 修改tokens对应的组中的old值。如果old存在则返回true，否则返回false并且不调用f。
+    ///
+    /// 组是以值本身的hash来存储的`HashSet`，原地修改会破坏集合，
+    /// 因此这里先取出old，用f修改之后再放回去。修改后的值如果与组中已有的值相等，
+    /// 两者会合并为一个。`DuplicateMode::Count`下修改后的值保留old被添加的次数，
+    /// 有观察者时old被添加了几次就依次收到几个`Removed`事件和几个`Inserted`事件。
+    /// old由`insert_with_ttl`添加时，修改后的值保留原来的过期时间
+    pub fn update<F: FnOnce(&mut V)>(&mut self, tokens: &Tokens<'a, K>, old: &V, f: F) -> bool {
+        let times = self.count_of(tokens, old);
+        let expiry = self.expiries.take(tokens, old);
+        let mut value = match self.take(tokens, old) {
+            None => return false,
+            Some(value) => value,
+        };
+        f(&mut value);
+        if let Some((_, expires_at)) = expiry {
+            self.expiries.set(tokens, value.clone(), expires_at);
+        }
+        // 逐次添加，计数模式下次数与事件都与old相同
+        for _ in 1..times {
+            self.insert(tokens, value.clone());
+        }
+        self.insert(tokens, value);
+        true
     }
 
-    #[test]
-    fn test_trie_with_wildcard() -> Result<(), CommonTokenError> {
-        let mut trie = Trie::<_, 10>::new();
-        let parser = CommonTokenParser::new('.', "*", ">");
-        trie.insert(&parser.parse_tokens("a")?, 1);
-        trie.insert(&parser.parse_tokens("a.b")?, 2);
-        trie.insert(&parser.parse_tokens("")?, 3);
-        trie.insert(&parser.parse_tokens("*")?, 4);
-        trie.insert(&parser.parse_tokens(">")?, 5);
-        trie.insert(&parser.parse_tokens("*.c")?, 6);
-        trie.insert(&parser.parse_tokens("a.*.c")?, 7);
-        trie.insert(&parser.parse_tokens("a.>")?, 8);
+    /// 按顺序在当前trie树上重放log中的所有操作
+    pub fn replay(&mut self, log: &OpLog<V, K::Static>) {
+        for op in log.ops().iter() {
+            match op {
+                Op::Insert { pattern, value } => self.insert(&Tokens::from_static(pattern), value.clone()),
+                Op::Remove { pattern, value } => {
+                    self.remove(&Tokens::from_static(pattern), value);
+                },
+                Op::Clear => self.clear(),
+            }
+        }
+    }
 
-        assert!(vec_eq(trie.find(vec!["a"]), vec![1, 4, 5]));
-        assert!(vec_eq(trie.find(vec!["b"]), vec![4, 5]));
-        assert!(vec_eq(trie.find(vec!["a", "b"]), vec![2, 5, 8]));
-        assert!(vec_eq(trie.find(vec!["a", "c"]), vec![5, 6, 8]));
-        assert!(vec_eq(trie.find(vec!["a", "b", "c"]), vec![5, 7, 8]));
-        Ok(())
+    /// 返回能与keys匹配的所有值的迭代器，如果不存在键，返回空迭代器。
+    /// keys中的每一项都是字面的key，即使是`*`或者`>`也不会被当作wildcard：key `*`只匹配
+    /// 字面上为`*`的普通token，但是与其它key一样，也被单层wildcard和多层wildcard的组匹配。
+    /// 只需要注册在字面`*`下的值时，用`exist_exact`或者`snapshot().values_at`，它们不进行wildcard匹配
+    pub fn find(&mut self, keys: impl AsRef<[&'a K::Key]>) -> Vec<V> {
+        self.find_traced(keys).0
     }
-}
\ No newline at end of file
+
+    /// 与`find`相同，同时返回这次查询是否命中了缓存，用于把单个查询与缓存的行为对应起来
+    pub fn find_traced(&mut self, keys: impl AsRef<[&'a K::Key]>) -> (Vec<V>, CacheOutcome) {
+        let keys = keys.as_ref();
+        // 先移除已经过期的值，同时会删除缓存中的相关结果
+        if self.expiries.is_due() {
+            self.expire(Instant::now());
+        }
+        if let Some(counts) = self.key_counts.as_mut() {
+            match counts.get_mut(keys) {
+                Some(count) => *count = count.saturating_add(1),
+                None => {
+                    counts.insert(keys.to_vec(), 1);
+                },
+            }
+        }
+        // 先用借用的slice查找cache，如果命中就返回，命中时不需要分配
+        match self.cache.get(keys) {
+            Some(Cached::Values(values)) => return (values.clone(), CacheOutcome::Hit),
+            Some(Cached::Patterns(patterns)) => {
+                // 从组中读取当前的值，不能借用整个self，因为cache还在被借用
+                let view = TrieView::new(&self.root, self.mwc_includes_prefix, self.has_any_wildcard);
+                let values = patterns.iter().flat_map(|p| view.values_at(p)).collect();
+                return (values, CacheOutcome::Hit);
+            }
+            None => {}
+        }
+
+        // 没有命中时才复制keys作为缓存的key
+        match self.cache_mode {
+            CacheMode::Values => {
+                let values = self.snapshot().find(keys);
+                self.cache.put(keys.to_vec(), Cached::Values(values.clone()));
+                (values, CacheOutcome::Miss)
+            }
+            CacheMode::Patterns => {
+                let groups = self.snapshot().find_grouped(keys);
+                let values = groups.iter().flat_map(|(_, vs)| vs.iter().map(|&v| v.clone())).collect();
+                let patterns = groups.into_iter().map(|(p, _)| p).collect();
+                self.cache.put(keys.to_vec(), Cached::Patterns(patterns));
+                (values, CacheOutcome::Miss)
+            }
+        }
+    }
+
+    /// 与`find`相同，但是没有匹配的值时区分没有与keys匹配的结点（`NoPath`）和匹配到的结点中没有值（`NoValues`），
+    /// 例如删除了所有的值但是结点仍然保留的情况，用于诊断。这里的查询不经过缓存
+    pub fn try_find<'k>(&self, keys: impl AsRef<[&'k K::Key]>) -> FindResult<V> {
+        self.snapshot().try_find(keys)
+    }
+
+    /// 返回能与keys匹配的所有值，按照从小到大的顺序排列，适用于需要确定的输出的场景，例如测试。
+    /// 不经过缓存，并且需要额外的O(n log n)的排序，见[`TrieView::find_sorted`]
+    pub fn find_sorted<'k>(&self, keys: impl AsRef<[&'k K::Key]>) -> Vec<V>
+    where
+        V: Ord,
+    {
+        self.snapshot().find_sorted(keys)
+    }
+
+    /// 返回能与keys匹配的所有值组成的集合，同一个值出现在多个匹配的组中时只出现一次。
+    /// 直接收集到集合中，不需要调用者再去重。这里的查询不经过缓存
+    pub fn find_set<'k>(&self, keys: impl AsRef<[&'k K::Key]>) -> HashSet<V> {
+        self.snapshot().find_set(keys)
+    }
+
+    /// 返回最多limit个能与keys匹配的值，收集到limit个之后立即停止匹配，
+    /// 适用于只需要投递给前几个订阅者、而匹配的订阅者可能非常多的key。
+    /// 返回哪些值是任意的，但是对于同一棵没有修改过的树，结果总是相同的。这里的查询不经过缓存
+    pub fn find_limited<'k>(&self, keys: impl AsRef<[&'k K::Key]>, limit: usize) -> Vec<V> {
+        self.snapshot().find_limited(keys, limit)
+    }
+
+    /// 返回能与keys匹配的所有值，以及每个值被收集时已经匹配的key的数量，用于统计匹配的深度。
+    /// 多层wildcard组中的值为多层wildcard所在的位置，例如`a.>`与`a.b.c`匹配时为1，
+    /// 其它组中的值为keys的长度。一个值在多个组中时出现多次。这里的查询不经过缓存
+    pub fn find_with_depth<'k>(&self, keys: impl AsRef<[&'k K::Key]>) -> Vec<(V, usize)> {
+        self.snapshot().find_with_depth(keys)
+    }
+
+    /// 与`find`相同，但是keys由迭代器逐个给出，例如`subject.split('.')`，
+    /// 匹配过程中按需消耗迭代器，不需要先收集为`Vec`。
+    /// 由于缓存需要完整的keys作为键，这里的查询不经过缓存
+    pub fn find_iter_keys<'k>(&self, keys: impl IntoIterator<Item = &'k K::Key>) -> Vec<V> {
+        self.snapshot().find_iter_keys(keys)
+    }
+
+    /// 返回能与keys匹配的所有值，以及每个值所在的组中带名字的单层wildcard匹配到的key，
+    /// 例如`a.:id.c`与`a.1.c`匹配时得到`{"id": "1"}`。捕获名属于组，同一组的tokens中的名字不同时，
+    /// 以最后插入的为准，同一个tokens中的重复名字以最后一个为准。这里的查询不经过缓存
+    pub fn find_captures<'k>(&self, keys: impl AsRef<[&'k K::Key]>) -> Vec<(V, HashMap<&str, &'k K::Key>)> {
+        self.snapshot().find_captures(keys)
+    }
+
+    /// 返回能与keys匹配的所有值以及其来源组的具体程度，按照具体程度从高到低排列：
+    /// 不含wildcard的组为2，含有单层wildcard或者多选一token的组为1，多层wildcard组为0
+    /// 来自不同组的值默认按照分数排列，可以通过`set_priority`修改
+    pub fn find_ranked<'k>(&self, keys: impl AsRef<[&'k K::Key]>) -> Vec<(u32, V)> {
+        self.snapshot().find_ranked_by(keys, &self.priority)
+    }
+
+    /// 在所有与keys匹配的组中，返回最具体的一个组的tokens和其中的值，不存在时返回None。
+    /// 具体程度的比较规则见[`TrieView::longest_match`]
+    pub fn longest_match<'k>(&self, keys: impl AsRef<[&'k K::Key]>) -> Option<(Tokens<'a, K>, Vec<V>)> {
+        self.snapshot().longest_match(keys)
+    }
+
+    /// 返回与pattern有交集的所有组中的值，pattern中也可以有wildcard，不经过缓存。
+    /// 例如`a.*`能找到`a.>`中的值，因为`a.>`包含了所有的`a.<任意token>`。具体规则见[`TrieView::find_pattern`]
+    pub fn find_pattern<Q>(&self, pattern: &Tokens<Q>) -> Vec<V>
+    where
+        Q: TokenAtom<Key = K::Key>,
+    {
+        self.snapshot().find_pattern(pattern)
+    }
+
+    /// 返回存储在keys这个字面前缀上及其之下的所有值，包括每一层的wildcard组。
+    /// 与`find`不同，这里不进行wildcard匹配，只是前缀扫描
+    pub fn subtree_values<'k>(&self, keys: impl AsRef<[&'k K::Key]>) -> Vec<V> {
+        self.snapshot().subtree_values(keys)
+    }
+
+    /// 返回tokens对应的组中唯一的值的可变引用，组为空时先添加`V::default()`，适用于计数器之类的聚合值。
+    /// 因为组是集合，这只对最多只有一个值的组有意义：组中有多个值，或者组为空但是`set_group_limit`的上限为0时返回None，
+    /// 不做任何修改。见[`ValueGuard`]
+    pub fn insert_default(&mut self, tokens: &Tokens<'a, K>) -> Option<ValueGuard<'_, 'a, V, N, S, K, M>>
+    where
+        V: Default,
+    {
+        if self.group_limit == Some(0) {
+            return None;
+        }
+        let mut group = self.group_mut(tokens);
+        let value = match group.len() {
+            0 => V::default(),
+            1 => {
+                let value = group.iter().next().cloned()?;
+                group.take(&value)?
+            },
+            _ => return None,
+        };
+        Some(ValueGuard::new(value, group))
+    }
+
+    /// 添加键值对，并且记录其过期时间。已经记录了过期时间的值再次添加时更新过期时间，
+    /// 以任何方式移除的值都不再过期。`find`等查询之前会先移除已经过期的值，过期的值不会出现在结果中；
+    /// `snapshot`等只借用trie树的查询不会移除，需要先调用`expire`
+    pub fn insert_with_ttl(&mut self, tokens: &Tokens<'a, K>, value: V, expires_at: Instant) {
+        let mode = self.duplicate_mode;
+        let inserted = self.insert_by(tokens, value.clone(), |node, value, is_mwc| node.add_in_mode(value, is_mwc, mode));
+        // 组已满时没有添加，也不需要记录过期时间
+        if inserted.is_ok() {
+            self.expiries.set(tokens, value, expires_at);
+        }
+    }
+
+    /// 将第depth个token为from的所有普通子结点改为to，to已经存在时合并两棵子树，
+    /// 组中的值都保留。depth从0开始，经过wildcard结点到达的同一层也会改名。
+    /// 返回改名的结点数量，之后清空缓存
+    pub fn rename_segment(&mut self, depth: usize, from: &K::Key, to: &K::Key) -> usize {
+        if from == to {
+            return 0;
+        }
+        let observed = self.is_observed();
+        let mode = self.duplicate_mode;
+        let to = K::from_key(to);
+        // 过期时间跟随值移动到新的组，目标组中原本就有的值保留原来的过期时间
+        let view = TrieView::new(&self.root, false, true);
+        self.expiries.rename(depth, from, &to, |tokens, value| view.values_at(tokens).contains(value));
+        let interner = &mut self.interner;
+        let mut events = Vec::new();
+        let mut renamed = 0;
+        let mut stack = vec![(&mut *self.root, Vec::new())];
+        while let Some((node, path)) = stack.pop() {
+            if path.len() < depth {
+                stack.extend(node.token_child_nodes_mut().map(|(token, child)| {
+                    let mut path = path.clone();
+                    path.push(token);
+                    (child, path)
+                }));
+                continue;
+            }
+            let moved = match node.remove_child_node(from) {
+                Some(moved) => moved,
+                None => continue,
+            };
+            let target = node.get_child_node_mut_or_insert(&to, interner.as_mut());
+            if observed {
+                // 移动的值都从原来的组中移除，目标组中原本没有的值才是新添加的。
+                // 计数模式下值的次数随值一起移动，每一次都生成事件
+                let existing = TrieView::new(target, false, true);
+                let mut values = IntoIter::new(Box::new(moved.deep_clone()));
+                while let Some((relative, value, extra)) = values.next_counted() {
+                    let pattern = |token: Token<'a, K>| {
+                        let mut pattern = path.clone();
+                        pattern.push(token);
+                        pattern.extend(relative.0.iter().cloned());
+                        Tokens::from(pattern)
+                    };
+                    let times = removed_times(1 + extra.map_or(0, |(_, extra)| extra), mode);
+                    let added = match mode {
+                        DuplicateMode::Count => times,
+                        DuplicateMode::Ignore => usize::from(!existing.values_at(&relative).contains(&value)),
+                    };
+                    let removed = TrieEvent::Removed { pattern: pattern(Token::Normal(K::from_key(from))), value: value.clone() };
+                    events.extend(std::iter::repeat_n(removed, times));
+                    let inserted = TrieEvent::Inserted { pattern: pattern(Token::Normal(to.clone())), value };
+                    events.extend(std::iter::repeat_n(inserted, added));
+                }
+            }
+            target.merge(moved);
+            renamed += 1;
+        }
+        if renamed > 0 {
+            self.cache.clear();
+        }
+        for event in events {
+            self.notify(|_| event);
+        }
+        renamed
+    }
+
+    /// 复制出当前trie树的只读快照，快照可以廉价地复制并在线程之间共享，
+    /// 之后对trie树的修改不会影响已经生成的快照
+    pub fn freeze(&self) -> FrozenTrie<'a, V, S, K, M> {
+        FrozenTrie::new(self.root.deep_clone(), self.mwc_includes_prefix, self.has_any_wildcard)
+    }
+
+    /// 将trie树压缩为`CompactTrie`，只有一个普通子结点、自身没有值的结点会被合并到边上。
+    /// 适用于很深但是很少分叉的键空间，查询结果不变，之后只能添加值，不能再移除
+    pub fn compact(self) -> CompactTrie<'a, V, S, K> {
+        CompactTrie::new(self.root, self.mwc_includes_prefix)
+    }
+}
+
+// 只适用于字符串token的方法：字符串的驻留、从文本加载和导出，以及由字符串分割或者借用得到keys的查询
+impl<'a, V, const N: usize, S, M> Trie<'a, V, N, S, Cow<'a, str>, M>
+where
+    M: ChildMap,
+    V: Eq + Hash,
+    S: BuildHasher + Default,
+{
+    /// 初始化一个驻留token字符串的trie树，相同的子结点键共享同一份存储。
+    /// 只有自己持有的token字符串（例如大小写折叠之后的，或者`Cow::Owned`构造的）会被驻留，
+    /// 借用自源字符串的token本身就不占用额外的存储。适用于大量重复的token字符串
+    pub fn with_interner() -> Self {
+        Trie {
+            interner: Some(Interner::strings()),
+            ..Self::new()
+        }
+    }
+
+    /// 加载多行`模式<TAB>值`的文本，模式由parser解析，值由parse_value解析，返回加载的数量。
+    /// 空白行会被跳过。所有行都解析成功之后才会添加，任意一行出错时返回带有行号的错误并且不做任何修改
+    pub fn load_lines<P: TokenParser>(
+        &mut self,
+        text: &'a str,
+        parser: &P,
+        parse_value: impl Fn(&str) -> V,
+    ) -> Result<usize, LoadError<P::Error>> {
+        let mut pairs = Vec::new();
+        for (i, line) in text.lines().enumerate() {
+            let line_no = i + 1;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let (pattern, value) = line.split_once('\t')
+                .ok_or(LoadError::MissingValue { line: line_no })?;
+            let tokens = parser.parse_tokens(pattern)
+                .map_err(|source| LoadError::Parse { line: line_no, source })?;
+            tokens.validate()
+                .map_err(|source| LoadError::Invalid { line: line_no, source })?;
+            pairs.push((tokens, parse_value(value)));
+        }
+        let loaded = pairs.len();
+        for (tokens, value) in pairs.into_iter() {
+            self.insert(&tokens, value);
+        }
+        Ok(loaded)
+    }
+
+    /// 与`exist`相同，但是keys可以是String等任意可以借用为str的类型
+    pub fn exist_owned(&self, keys: &[impl AsRef<str>]) -> bool {
+        let keys: Vec<&str> = keys.iter().map(|k| k.as_ref()).collect();
+        self.exist(keys)
+    }
+
+    /// 与`find`相同，但是keys由subject按照sep分割得到，例如`find_subject("a.b", '.')`等同于`find(["a", "b"])`，
+    /// 经过缓存。subject中的`*`和`>`等也只是字面的key，不会被当作wildcard。
+    /// 连续的、开头或者末尾的sep会分割出空的key，只与空的普通token匹配，例如`a.`分割为`a`和空的key；
+    /// 空的subject分割为一个空的key，而不是没有key
+    pub fn find_subject(&mut self, subject: &'a str, sep: char) -> Vec<V>
+    where
+        V: Clone,
+    {
+        let keys: Vec<&'a str> = subject.split(sep).collect();
+        self.find(keys)
+    }
+
+    /// 与`find`相同，但是keys可以是String等任意可以借用为str的类型。
+    /// 由于缓存中的key需要与trie有相同的生命周期，这里的查询不经过缓存
+    pub fn find_owned(&self, keys: &[impl AsRef<str>]) -> Vec<V>
+    where
+        V: Clone,
+    {
+        let keys: Vec<&str> = keys.iter().map(|k| k.as_ref()).collect();
+        self.snapshot().find(keys)
+    }
+
+    /// 将所有的值导出为`(模式, 值)`的列表，模式由`Tokens::render`以sep、owc和mwc渲染为文本，
+    /// 可以由相同配置的`CommonTokenParser`解析回来，例如用于`load_lines`
+    pub fn to_pairs(&self, sep: char, owc: &str, mwc: &str) -> Vec<(String, V)>
+    where
+        V: Clone,
+    {
+        let mut pairs = Vec::new();
+        self.visit(|tokens, value| pairs.push((tokens.render(sep, owc, mwc), value.clone())));
+        pairs
+    }
+}
+
+// 删除缓存中与tokens匹配的结果。多层wildcard组也匹配其前缀本身时，与前缀相同的key也会过期
+fn invalidate<'a, V, const N: usize, K: TokenAtom>(cache: &mut QueryCache<'a, V, N, K>, tokens: &Tokens<'a, K>, mwc_includes_prefix: bool) {
+    remove_cached(cache, tokens, |keys| pattern_matches(tokens, keys, mwc_includes_prefix));
+}
+
+// tokens是否与keys匹配，与查询的规则一致。多层wildcard组也匹配其前缀本身时，
+// 以多层wildcard结尾的tokens也与去掉多层wildcard之后的tokens对应的keys匹配
+fn pattern_matches<K: TokenAtom>(tokens: &Tokens<K>, keys: &[&K::Key], mwc_includes_prefix: bool) -> bool {
+    tokens.match_keys(keys)
+        || (mwc_includes_prefix
+            && tokens.ends_with_multi_wildcard()
+            && keys.len() == tokens.len() - 1
+            && tokens.match_keys_prefix(keys))
+}
+
+// 删除缓存中满足pred的结果，pred只对第一个key能与tokens的第一个token匹配的结果成立。
+// 第一个token是普通token或者多选一token时只需要检查对应的桶，否则检查所有的桶
+fn remove_cached<'a, V, const N: usize, K: TokenAtom>(
+    cache: &mut QueryCache<'a, V, N, K>,
+    tokens: &Tokens<'a, K>,
+    mut pred: impl FnMut(&Vec<&'a K::Key>) -> bool,
+) {
+    match tokens.0.first() {
+        Some(Token::Normal(first)) => cache.remove_in(bucket_of(Some(first.key())), pred),
+        Some(Token::OneOf(alternatives)) => {
+            for alternative in alternatives.iter() {
+                cache.remove_in(bucket_of(Some(alternative.key())), &mut pred);
+            }
+        },
+        // 空的前缀可以匹配任意的key
+        _ => cache.remove(pred),
+    }
+}
+
+// 值被移除时生成的`Removed`事件的数量，times为组中记录的次数。
+// 切换为`DuplicateMode::Ignore`之后组中可能还留有次数，但是此时值只算添加了一次
+fn removed_times(times: usize, mode: DuplicateMode) -> usize {
+    match mode {
+        DuplicateMode::Count => times,
+        DuplicateMode::Ignore => 1,
+    }
+}
+
+// tokens对应的node的可变引用，以及tokens中是否有mwc
+type GroupNode<'n, 'a, V, S, K, M> = (&'n mut Node<'a, V, S, K, M>, bool);
+
+// 从root开始找到tokens对应的node，没有则创建，同时返回tokens中是否有mwc。
+// 只借用需要的字段，调用者可以同时持有trie树的其它字段
+fn node_mut_or_insert<'n, 'a, V, S, K, M>(root: &'n mut Node<'a, V, S, K, M>, interner: &mut Option<Interner<K>>, tokens: &Tokens<'a, K>)
+    -> GroupNode<'n, 'a, V, S, K, M>
+where
+    K: TokenAtom,
+    M: ChildMap,
+    V: Eq + Hash,
+    S: BuildHasher + Default,
+{
+    // 是否遇到过了mwc
+    let mut hasmwc = false;
+    // 找到对应的node
+    let node = tokens.0.iter()
+        .fold(root,
+            |node, token| {
+                match token {
+                    Token::MultiWildcard => {
+                        hasmwc = true;
+                        node
+                    },
+                    _ => node.get_token_node_mut_or_insert(token, interner.as_mut()),
+                }
+        }
+    );
+    (node, hasmwc)
+}
+
+// 手动实现，避免derive带来的`V: Default`约束
+impl<'a, V, const N: usize, S, K, M> Default for Trie<'a, V, N, S, K, M>
+where
+    K: TokenAtom,
+    M: ChildMap,
+    V: Eq + Hash,
+    S: BuildHasher + Default,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// 只输出概要，不输出整棵树，整棵树的结构用`debug_tree`查看。缓存、观察者等字段不一定实现了Debug
+impl<'a, V, const N: usize, S, K, M> Debug for Trie<'a, V, N, S, K, M>
+where
+    K: TokenAtom,
+    M: ChildMap,
+    V: Eq + Hash,
+    S: BuildHasher + Default,
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Trie")
+            .field("nodes", &self.node_count())
+            .field("values", &self.root.subtree_len())
+            .field("depth", &self.root.depth())
+            .field("cache_len", &self.cache.len())
+            .field("cache_capacity", &self.cache_capacity())
+            .finish()
+    }
+}
+
+/// 只关心是否有模式匹配、不需要值的trie树。
+/// `()`是零大小的类型，每个组最多只有一个值，组中的`HashSet<()>`不需要为值本身分配存储
+impl<'a, const N: usize, S, K, M> Trie<'a, (), N, S, K, M>
+where
+    K: TokenAtom,
+    M: ChildMap,
+    S: BuildHasher + Default,
+{
+    /// 添加一个模式
+    pub fn insert_pattern(&mut self, tokens: &Tokens<'a, K>) {
+        self.insert(tokens, ());
+    }
+
+    /// 移除一个模式，如果模式存在则返回true
+    pub fn remove_pattern(&mut self, tokens: &Tokens<'a, K>) -> bool {
+        self.remove(tokens, &())
+    }
+
+    /// 是否有模式与keys匹配，不经过缓存
+    pub fn matches<'k>(&self, keys: impl AsRef<[&'k K::Key]>) -> bool {
+        self.snapshot().exist(keys)
+    }
+}
+
+impl<'a, V, const N: usize, S, K, M> IntoIterator for Trie<'a, V, N, S, K, M>
+where
+    K: TokenAtom,
+    M: ChildMap,
+    V: Eq + Hash,
+    S: BuildHasher + Default,
+{
+    type Item = (Tokens<'a, K>, V);
+    type IntoIter = IntoIter<'a, V, S, K, M>;
+
+    /// 消耗trie树，依次输出每一个值以及其对应的tokens。缓存直接丢弃
+    fn into_iter(self) -> Self::IntoIter {
+        IntoIter::new(self.root)
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use crate::token::*;
+    use std::collections::HashSet;
+    use std::collections::hash_map::DefaultHasher;
+    use std::hash::BuildHasherDefault;
+
+    // 两个迭代器中的元素在忽略顺序的情况下是否一一相等
+    fn vec_eq<V: Hash + Eq>(vec1: Vec<V>, vec2: Vec<V>) -> bool{
+        let set1: HashSet<V> = vec1.into_iter().collect();
+        let set2: HashSet<V> = vec2.into_iter().collect();
+        set1 == set2
+    }
+
+    fn sorted<V: Ord>(mut vec: Vec<V>) -> Vec<V> {
+        vec.sort();
+        vec
+    }
+
+    #[test]
+    #[allow(clippy::bool_assert_comparison)]
+    fn test_basic_trie() -> Result<(), CommonTokenError> {
+        let mut trie = Trie::<_, 10>::new();
+        let parser = CommonTokenParser::new('.', "*", ">");
+        trie.insert(&parser.parse_tokens("a")?, 1);
+        trie.insert(&parser.parse_tokens("a")?, 2);
+        trie.insert(&parser.parse_tokens("")?, 3);
+        trie.insert(&parser.parse_tokens("a.b")?, 5);
+        trie.insert(&parser.parse_tokens(".")?, 6);
+        trie.insert(&parser.parse_tokens("a")?, 8);
+        trie.insert(&parser.parse_tokens("a.b.c")?, 12);
+        assert!(vec_eq(trie.find(&["a"]), vec![1, 2, 8]));
+        assert!(vec_eq(trie.find(&[""]), vec![3, ]));
+        assert!(vec_eq(trie.find(&["a", "b"]), vec![5, ]));
+        assert!(vec_eq(trie.find(&["", ""]), vec![6, ]));
+        assert!(vec_eq(trie.find(&["a", "b", "c"]), vec![12,]));
+        assert_eq!(trie.find(vec!["b"]).len(), 0);
+        assert_eq!(trie.find(vec!["c"]).len(), 0);
+        assert_eq!(trie.remove(&parser.parse_tokens("a")?, &1), true);
+        assert_eq!(trie.remove(&parser.parse_tokens("a")?, &1), false);
+        assert_eq!(trie.remove(&parser.parse_tokens("a.b")?, &5), true);
+        assert_eq!(trie.remove(&parser.parse_tokens("a")?, &5), false);
+        assert!(vec_eq(trie.find(vec!["a"]), vec![2, 8, ]));
+        assert_eq!(trie.find(vec!["a", "b"]).len(), 0);
+        assert!(vec_eq(trie.find(vec!["a", "b", "c"]), vec![12, ]));
+        assert_eq!(trie.remove(&parser.parse_tokens("a.b")?, &5), false);
+        trie.insert(&parser.parse_tokens("a.b.c")?, 15);
+        trie.insert(&parser.parse_tokens("a.b.c")?, 17);
+        assert_eq!(trie.remove_all(&parser.parse_tokens("a.b.c")?), true);
+        assert_eq!(trie.find(vec!["a", "b", "c"]).len(), 0);
+        assert_eq!(trie.remove_all(&parser.parse_tokens("a")?), true);
+        assert_eq!(trie.remove_all(&parser.parse_tokens("a.b")?), false);
+        assert_eq!(trie.remove_all(&parser.parse_tokens("x.y.z")?), false);
+        Ok(())
+    }
+
+    #[test]
+    fn test_trie_with_wildcard() -> Result<(), CommonTokenError> {
+        let mut trie = Trie::<_, 10>::new();
+        let parser = CommonTokenParser::new('.', "*", ">");
+        trie.insert(&parser.parse_tokens("a")?, 1);
+        trie.insert(&parser.parse_tokens("a.b")?, 2);
+        trie.insert(&parser.parse_tokens("")?, 3);
+        trie.insert(&parser.parse_tokens("*")?, 4);
+        trie.insert(&parser.parse_tokens(">")?, 5);
+        trie.insert(&parser.parse_tokens("*.c")?, 6);
+        trie.insert(&parser.parse_tokens("a.*.c")?, 7);
+        trie.insert(&parser.parse_tokens("a.>")?, 8);
+
+        assert!(vec_eq(trie.find(vec!["a"]), vec![1, 4, 5]));
+        assert!(vec_eq(trie.find(vec!["b"]), vec![4, 5]));
+        assert!(vec_eq(trie.find(vec!["a", "b"]), vec![2, 5, 8]));
+        assert!(vec_eq(trie.find(vec!["a", "c"]), vec![5, 6, 8]));
+        assert!(vec_eq(trie.find(vec!["a", "b", "c"]), vec![5, 7, 8]));
+        Ok(())
+    }
+
+    #[test]
+    fn test_reserve() -> Result<(), CommonTokenError> {
+        let parser = CommonTokenParser::new('.', "*", ">");
+        let patterns: Vec<String> = (0..100).map(|i| format!("a.{}", i)).collect();
+        let mut trie = Trie::<_, 10>::new();
+        let a = parser.parse_tokens("a")?;
+        assert_eq!(trie.child_count(&a), None);
+        // 不存在的结点会被创建，但是没有值也没有子结点
+        trie.reserve(&a, 100);
+        assert_eq!(trie.node_count(), 2);
+        assert_eq!(trie.child_count(&a), Some(0));
+        assert_eq!(trie.find(["a"]).len(), 0);
+        assert!(!trie.exist(["a"]));
+        for (i, pattern) in patterns.iter().enumerate() {
+            trie.insert(&parser.parse_tokens(pattern)?, i);
+        }
+        assert_eq!(trie.child_count(&a), Some(100));
+        // 对已有的结点预留空间不改变其内容
+        trie.reserve(&a, 100);
+        let info = trie.node_info(&a).unwrap();
+        assert_eq!((info.value_count, info.child_count, info.has_owc), (0, 100, false));
+        assert_eq!(trie.find(["a", "42"]), vec![42]);
+        // 路径中的wildcard也会被创建
+        trie.reserve(&parser.parse_tokens("a.*")?, 10);
+        assert!(trie.node_info(&a).unwrap().has_owc);
+        Ok(())
+    }
+
+    #[test]
+    fn test_shrink_to_fit() -> Result<(), CommonTokenError> {
+        let mut trie = Trie::<_, 10>::new();
+        let parser = CommonTokenParser::new('.', "*", ">");
+        for i in 0..100 {
+            trie.insert(&parser.parse_tokens("a.b")?, i);
+        }
+        trie.insert(&parser.parse_tokens("a.*.c")?, 100);
+        trie.insert(&parser.parse_tokens("x.>")?, 101);
+        trie.insert(&parser.parse_tokens("x.y")?, 102);
+        assert!(trie.remove_all(&parser.parse_tokens("a.b")?));
+        assert!(trie.remove(&parser.parse_tokens("a.*.c")?, &100));
+        assert!(trie.remove(&parser.parse_tokens("x.y")?, &102));
+        trie.shrink_to_fit();
+        assert!(vec_eq(trie.find(["x", "y"]), vec![101]));
+        assert_eq!(trie.find(["a", "b"]).len(), 0);
+        assert!(trie.remove(&parser.parse_tokens("x.>")?, &101));
+        trie.shrink_to_fit();
+        assert!(trie.root.is_vacant());
+        // 缓存失效之后留下的容量也会被释放，缓存中的结果保留
+        let keys: Vec<String> = (0..500).map(|i| i.to_string()).collect();
+        let mut trie = Trie::<_, 1000>::new();
+        trie.insert(&parser.parse_tokens(">")?, 1);
+        for key in keys.iter() {
+            trie.find([key.as_str(), "a"]);
+            trie.find([key.as_str(), "b"]);
+        }
+        trie.find(["x"]);
+        trie.cache.remove(|keys| keys.len() > 1);
+        assert_eq!(trie.cache_len(), 1);
+        let allocated = trie.cache.allocated();
+        trie.shrink_to_fit();
+        assert!(trie.cache.allocated() < allocated);
+        assert_eq!(trie.cache_len(), 1);
+        assert_eq!(trie.find_traced(["x"]), (vec![1], CacheOutcome::Hit));
+        Ok(())
+    }
+
+    #[test]
+    fn test_custom_hasher() -> Result<(), CommonTokenError> {
+        type Hasher = BuildHasherDefault<DefaultHasher>;
+        let mut trie = Trie::<_, 10, Hasher>::new();
+        let parser = CommonTokenParser::new('.', "*", ">");
+        trie.insert(&parser.parse_tokens("a.b")?, 1);
+        trie.insert(&parser.parse_tokens("a.*")?, 2);
+        trie.insert(&parser.parse_tokens(">")?, 3);
+        assert!(vec_eq(trie.find(["a", "b"]), vec![1, 2, 3]));
+        assert!(trie.exist(["a", "c"]));
+        assert!(trie.remove(&parser.parse_tokens("a.*")?, &2));
+        assert!(vec_eq(trie.find(["a", "b"]), vec![1, 3]));
+        Ok(())
+    }
+
+    #[test]
+    fn test_take() -> Result<(), CommonTokenError> {
+        let mut trie = Trie::<_, 10>::new();
+        let parser = CommonTokenParser::new('.', "*", ">");
+        trie.insert(&parser.parse_tokens("a.b")?, 1);
+        trie.insert(&parser.parse_tokens("a.>")?, 2);
+        assert!(vec_eq(trie.find(["a", "b"]), vec![1, 2]));
+        assert_eq!(trie.take(&parser.parse_tokens("a.b")?, &1), Some(1));
+        assert_eq!(trie.take(&parser.parse_tokens("a.b")?, &1), None);
+        assert_eq!(trie.take(&parser.parse_tokens("x.y")?, &1), None);
+        assert!(vec_eq(trie.find(["a", "b"]), vec![2]));
+        assert_eq!(trie.take(&parser.parse_tokens("a.>")?, &2), Some(2));
+        assert_eq!(trie.find(["a", "b"]).len(), 0);
+        Ok(())
+    }
+
+    #[test]
+    fn test_remove_subtree() -> Result<(), CommonTokenError> {
+        let mut trie = Trie::<_, 10>::new();
+        let parser = CommonTokenParser::new('.', "*", ">");
+        trie.insert(&parser.parse_tokens("t1")?, 1);
+        trie.insert(&parser.parse_tokens("t1.a")?, 2);
+        trie.insert(&parser.parse_tokens("t1.a.b")?, 3);
+        trie.insert(&parser.parse_tokens("t1.*.c")?, 4);
+        trie.insert(&parser.parse_tokens("t1.>")?, 5);
+        trie.insert(&parser.parse_tokens("t2.a")?, 6);
+        trie.insert(&parser.parse_tokens(">")?, 7);
+        assert!(vec_eq(trie.find(["t1", "a"]), vec![2, 5, 7]));
+        assert_eq!(trie.remove_subtree(&parser.parse_tokens("t1.>")?), 5);
+        assert!(vec_eq(trie.find(["t1", "a"]), vec![7]));
+        assert!(vec_eq(trie.find(["t2", "a"]), vec![6, 7]));
+        assert_eq!(trie.remove_subtree(&parser.parse_tokens("t1")?), 0);
+        assert_eq!(trie.remove_subtree(&parser.parse_tokens("t2.a")?), 1);
+        // 变空的分支会被删除
+        assert!(trie.remove(&parser.parse_tokens(">")?, &7));
+        assert!(trie.root.is_vacant());
+        trie.insert(&parser.parse_tokens("x.y")?, 8);
+        trie.insert(&parser.parse_tokens(">")?, 9);
+        assert_eq!(trie.remove_subtree(&parser.parse_tokens(">")?), 2);
+        assert!(trie.root.is_vacant());
+        Ok(())
+    }
+
+    #[test]
+    fn test_snapshot() -> Result<(), CommonTokenError> {
+        let mut trie = Trie::<_, 10>::new();
+        let parser = CommonTokenParser::new('.', "*", ">");
+        trie.insert(&parser.parse_tokens("a.b")?, 1);
+        trie.insert(&parser.parse_tokens("a.*")?, 2);
+        trie.insert(&parser.parse_tokens("a.>")?, 3);
+        trie.insert(&parser.parse_tokens("a.>")?, 4);
+        let view = trie.snapshot();
+        assert!(vec_eq(view.find(["a", "b"]), vec![1, 2, 3, 4]));
+        assert_eq!(view.count(["a", "b"]), 4);
+        assert_eq!(view.count(["a", "c"]), 3);
+        assert_eq!(view.count(["b"]), 0);
+        assert!(view.exist(["a", "c", "d"]));
+        assert!(!view.exist(["a"]));
+        assert!(vec_eq(view.values_at(&parser.parse_tokens("a.>")?), vec![3, 4]));
+        assert!(vec_eq(view.values_at(&parser.parse_tokens("a.*")?), vec![2]));
+        assert_eq!(view.values_at(&parser.parse_tokens("a")?).len(), 0);
+        assert_eq!(view.values_at(&parser.parse_tokens("x")?).len(), 0);
+        assert_eq!(view.get_single(&parser.parse_tokens("a.*")?), Some(&2));
+        assert_eq!(view.get_single(&parser.parse_tokens("a.>")?), None);
+        assert_eq!(view.get_single(&parser.parse_tokens("a")?), None);
+        assert_eq!(view.get_single(&parser.parse_tokens("x")?), None);
+        assert_eq!(trie.get_single(&parser.parse_tokens("a.b")?), Some(&1));
+        // 视图与带缓存的查询结果一致
+        assert!(vec_eq(trie.find(["a", "b"]), vec![1, 2, 3, 4]));
+        Ok(())
+    }
+
+    #[test]
+    fn test_match_breakdown() -> Result<(), CommonTokenError> {
+        let mut trie = Trie::<_, 10>::new();
+        let parser = CommonTokenParser::new('.', "*", ">");
+        trie.insert(&parser.parse_tokens("a.b.c")?, 1);
+        trie.insert(&parser.parse_tokens("a.b.c")?, 2);
+        trie.insert(&parser.parse_tokens("a.*.c")?, 3);
+        trie.insert(&parser.parse_tokens("*.b.*")?, 4);
+        trie.insert(&parser.parse_tokens("a.>")?, 5);
+        trie.insert(&parser.parse_tokens(">")?, 6);
+        assert_eq!(trie.match_breakdown(["a", "b", "c"]), MatchBreakdown {
+            exact: 2,
+            one_wildcard: 2,
+            multi_wildcard: 2,
+        });
+        assert_eq!(trie.match_breakdown(["x", "b", "y"]), MatchBreakdown {
+            exact: 0,
+            one_wildcard: 1,
+            multi_wildcard: 1,
+        });
+        assert_eq!(trie.match_breakdown(["a"]), MatchBreakdown {
+            exact: 0,
+            one_wildcard: 0,
+            multi_wildcard: 1,
+        });
+        assert_eq!(trie.match_breakdown(Vec::<&str>::new()), MatchBreakdown::default());
+        Ok(())
+    }
+
+    #[test]
+    fn test_into_iter() -> Result<(), CommonTokenError> {
+        let mut trie = Trie::<_, 10>::new();
+        let parser = CommonTokenParser::new('.', "*", ">");
+        let entries = [("a", 1), ("a", 2), ("a.b", 3), ("a.*.c", 4), ("a.>", 5), ("*", 6), (">", 7)];
+        for (pattern, value) in entries.iter() {
+            trie.insert(&parser.parse_tokens(pattern)?, *value);
+        }
+        assert!(vec_eq(trie.find(["a"]), vec![1, 2, 6, 7]));
+        let drained: Vec<_> = trie.into_iter().collect();
+        assert_eq!(drained.len(), entries.len());
+        for (pattern, value) in entries.iter() {
+            let tokens = parser.parse_tokens(pattern)?;
+            assert!(drained.iter().any(|(t, v)| *t == tokens && v == value));
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_longest_match() -> Result<(), CommonTokenError> {
+        let mut trie = Trie::<_, 10>::new();
+        let parser = CommonTokenParser::new('.', "*", ">");
+        trie.insert(&parser.parse_tokens(">")?, 1);
+        trie.insert(&parser.parse_tokens("a.>")?, 2);
+        trie.insert(&parser.parse_tokens("a.*.c")?, 3);
+        trie.insert(&parser.parse_tokens("*.b.c")?, 4);
+        trie.insert(&parser.parse_tokens("*.b.c")?, 5);
+        trie.insert(&parser.parse_tokens("a.b.*")?, 6);
+        trie.insert(&parser.parse_tokens("x.y")?, 7);
+        let (tokens, values) = trie.longest_match(["a", "b", "c"]).unwrap();
+        assert_eq!(tokens, parser.parse_tokens("a.b.*")?);
+        assert!(vec_eq(values, vec![6]));
+        let (tokens, values) = trie.longest_match(["z", "b", "c"]).unwrap();
+        assert_eq!(tokens, parser.parse_tokens("*.b.c")?);
+        assert!(vec_eq(values, vec![4, 5]));
+        let (tokens, _) = trie.longest_match(["a", "x", "c"]).unwrap();
+        assert_eq!(tokens, parser.parse_tokens("a.*.c")?);
+        let (tokens, _) = trie.longest_match(["a", "x"]).unwrap();
+        assert_eq!(tokens, parser.parse_tokens("a.>")?);
+        let (tokens, _) = trie.longest_match(["x", "y"]).unwrap();
+        assert_eq!(tokens, parser.parse_tokens("x.y")?);
+        assert!(trie.longest_match(Vec::<&str>::new()).is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn test_trie_with_alternatives() -> Result<(), CommonTokenError> {
+        let mut trie = Trie::<_, 10>::new();
+        let parser = CommonTokenParser::new('.', "*", ">");
+        trie.insert(&parser.parse_tokens("a.(b|c).d")?, 1);
+        trie.insert(&parser.parse_tokens("a.(c|b).d")?, 2);
+        trie.insert(&parser.parse_tokens("a.(c|e).>")?, 3);
+        trie.insert(&parser.parse_tokens("a.*.d")?, 4);
+        assert!(vec_eq(trie.find(["a", "b", "d"]), vec![1, 2, 4]));
+        assert!(vec_eq(trie.find(["a", "c", "d"]), vec![1, 2, 3, 4]));
+        assert!(vec_eq(trie.find(["a", "e", "d"]), vec![3, 4]));
+        assert!(vec_eq(trie.find(["a", "x", "d"]), vec![4]));
+        assert!(trie.exist(["a", "e", "f"]));
+        assert!(!trie.exist(["a", "b", "f"]));
+        assert_eq!(trie.match_breakdown(["a", "c", "d"]).one_wildcard, 3);
+        let (tokens, _) = trie.longest_match(["a", "b", "d"]).unwrap();
+        assert_eq!(tokens, parser.parse_tokens("a.(b|c).d")?);
+        assert!(trie.remove(&parser.parse_tokens("a.(c|b).d")?, &1));
+        assert!(vec_eq(trie.find(["a", "b", "d"]), vec![2, 4]));
+        assert_eq!(trie.remove_subtree(&parser.parse_tokens("a.(b|c)")?), 1);
+        assert!(vec_eq(trie.find(["a", "b", "d"]), vec![4]));
+        let drained: Vec<_> = trie.into_iter().collect();
+        assert_eq!(drained.len(), 2);
+        assert!(drained.contains(&(parser.parse_tokens("a.(c|e).>")?, 3)));
+        Ok(())
+    }
+
+    #[test]
+    fn test_try_insert() {
+        let mut trie = Trie::<_, 10>::new();
+        let tokens: Tokens = vec![Token::from("a"), Token::MultiWildcard, Token::from("b")].into();
+        assert_eq!(trie.try_insert(&tokens, 1), Err(Error::TokenAfterMwc(2)));
+        assert!(!trie.exist(["a", "x", "b"]));
+        let tokens: Tokens = vec![Token::from("a"), Token::MultiWildcard].into();
+        assert_eq!(trie.try_insert(&tokens, 1), Ok(()));
+        assert!(vec_eq(trie.find(["a", "x", "b"]), vec![1]));
+    }
+
+    #[test]
+    fn test_exist_exact() -> Result<(), CommonTokenError> {
+        let mut trie = Trie::<_, 10>::new();
+        let parser = CommonTokenParser::new('.', "*", ">");
+        trie.insert(&parser.parse_tokens("a.b.c")?, 1);
+        trie.insert(&parser.parse_tokens("a.>")?, 2);
+        trie.insert(&parser.parse_tokens("a.*")?, 3);
+        assert!(trie.exist_exact(["a", "b", "c"]));
+        assert!(trie.exist(["a", "b", "d"]));
+        assert!(!trie.exist_exact(["a", "b", "d"]));
+        assert!(trie.exist(["a", "b"]));
+        assert!(!trie.exist_exact(["a", "b"]));
+        assert!(!trie.exist_exact(["a"]));
+        assert!(trie.remove(&parser.parse_tokens("a.b.c")?, &1));
+        assert!(!trie.exist_exact(["a", "b", "c"]));
+        Ok(())
+    }
+
+    #[test]
+    fn test_fold_case() -> Result<(), CommonTokenError> {
+        let parser = CommonTokenParser::new('.', "*", ">").fold_case(FoldMode::Unicode);
+        // 查询用的key也需要经过同样的处理
+        let keys: Vec<_> = "Σ.İ.X".split('.').map(|k| parser.normalize_key(k)).collect();
+        let keys: Vec<&str> = keys.iter().map(|k| k.as_ref()).collect();
+        let mut trie = Trie::<_, 10>::new();
+        trie.insert(&parser.parse_tokens("σ.İ.x")?, 1);
+        trie.insert(&parser.parse_tokens("Σ.*.X")?, 2);
+        trie.insert(&parser.parse_tokens("Σ.I.>")?, 3);
+        assert!(vec_eq(trie.find(&keys), vec![1, 2]));
+        assert!(vec_eq(trie.find(["σ", "i", "x"]), vec![2, 3]));
+        Ok(())
+    }
+
+    #[test]
+    fn test_matcher() -> Result<(), CommonTokenError> {
+        let mut trie = Trie::<_, 10>::new();
+        let parser = CommonTokenParser::new('.', "*", ">");
+        trie.insert(&parser.parse_tokens("a.b.c")?, 1);
+        trie.insert(&parser.parse_tokens("a.*")?, 2);
+        trie.insert(&parser.parse_tokens("a.>")?, 3);
+        trie.insert(&parser.parse_tokens("x.y")?, 4);
+        let mut matcher = trie.matcher();
+        assert!(matcher.matches().is_empty());
+        assert!(matcher.step("a"));
+        assert!(matcher.matches().is_empty());
+        assert!(matcher.step("b"));
+        assert!(vec_eq(matcher.matches(), vec![&2, &3]));
+        assert!(matcher.step("c"));
+        assert!(vec_eq(matcher.matches(), vec![&1, &3]));
+        // 多层wildcard仍然可以匹配
+        assert!(matcher.step("d"));
+        assert!(vec_eq(matcher.matches(), vec![&3]));
+
+        let mut matcher = trie.matcher();
+        assert!(matcher.step("x"));
+        assert!(!matcher.step("z"));
+        assert!(matcher.matches().is_empty());
+        assert!(!matcher.step("y"));
+
+        let mut matcher = trie.matcher();
+        for key in ["a", "b", "c"] {
+            matcher.step(key);
+        }
+        assert!(vec_eq(matcher.matches().into_iter().cloned().collect(), trie.find(["a", "b", "c"])));
+        Ok(())
+    }
+
+    #[test]
+    fn test_any_match() -> Result<(), CommonTokenError> {
+        let mut trie = Trie::<_, 10>::new();
+        let parser = CommonTokenParser::new('.', "*", ">");
+        trie.insert(&parser.parse_tokens("a.b")?, 1);
+        trie.insert(&parser.parse_tokens("a.*")?, 2);
+        trie.insert(&parser.parse_tokens("x.>")?, 3);
+        assert_eq!(trie.any_match(["x", "y", "z"]), Some(&3));
+        assert_eq!(trie.any_match(["a", "c"]), Some(&2));
+        assert!(matches!(trie.any_match(["a", "b"]), Some(1) | Some(2)));
+        assert_eq!(trie.any_match(["a"]), None);
+        assert_eq!(trie.any_match(["a", "b", "c"]), None);
+        assert_eq!(trie.any_match(["y"]), None);
+        Ok(())
+    }
+
+    #[test]
+    fn test_owned_keys() -> Result<(), CommonTokenError> {
+        let mut trie = Trie::<_, 10>::new();
+        let parser = CommonTokenParser::new('.', "*", ">");
+        trie.insert(&parser.parse_tokens("a.b")?, 1);
+        trie.insert(&parser.parse_tokens("a.*")?, 2);
+        trie.insert(&parser.parse_tokens("a.(b|c).>")?, 3);
+        let keys: Vec<String> = "a.b.c".split('.').map(String::from).collect();
+        assert!(vec_eq(trie.find_owned(&keys), vec![3]));
+        assert!(trie.exist_owned(&keys));
+        assert!(vec_eq(trie.find_owned(&keys[..2]), vec![1, 2]));
+        assert!(!trie.exist_owned(&[String::from("b")]));
+        // 只读的查询可以直接使用短生命周期的key
+        let (tokens, values) = {
+            let owned = String::from("c");
+            trie.longest_match(["a", owned.as_str(), "d"]).unwrap()
+        };
+        assert_eq!(tokens, parser.parse_tokens("a.(b|c).>")?);
+        assert!(vec_eq(values, vec![3]));
+        // 直接使用迭代器作为keys，结果与find相同，并且不写入缓存
+        let subject = String::from("a.b.c");
+        assert!(vec_eq(trie.find_iter_keys(subject.split('.')), vec![3]));
+        assert!(vec_eq(trie.find_iter_keys("a.b".split('.')), trie.find(["a", "b"])));
+        assert!(trie.find_iter_keys("b.c".split('.')).is_empty());
+        assert_eq!(trie.cache_len(), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn test_observer() -> Result<(), CommonTokenError> {
+        use std::sync::{Arc, Mutex};
+
+        let mut trie = Trie::<_, 10>::new();
+        let parser = CommonTokenParser::new('.', "*", ">");
+        let events = Arc::new(Mutex::new(vec![]));
+        let sink = events.clone();
+        trie.set_observer(Box::new(move |e| sink.lock().unwrap().push(e)));
+        trie.insert(&parser.parse_tokens("a.b")?, 1);
+        // 重复添加不会产生事件
+        trie.insert(&parser.parse_tokens("a.b")?, 1);
+        trie.insert(&parser.parse_tokens("a.>")?, 2);
+        assert!(trie.remove(&parser.parse_tokens("a.b")?, &1));
+        assert!(!trie.remove(&parser.parse_tokens("a.b")?, &1));
+        assert!(trie.remove_all(&parser.parse_tokens("a.>")?));
+        trie.insert(&parser.parse_tokens("x.y.z")?, 3);
+        assert_eq!(trie.remove_subtree(&parser.parse_tokens("x")?), 1);
+        trie.clear();
+        assert_eq!(*events.lock().unwrap(), vec![
+            TrieEvent::Inserted { pattern: parser.parse_tokens("a.b")?, value: 1 },
+            TrieEvent::Inserted { pattern: parser.parse_tokens("a.>")?, value: 2 },
+            TrieEvent::Removed { pattern: parser.parse_tokens("a.b")?, value: 1 },
+            TrieEvent::Removed { pattern: parser.parse_tokens("a.>")?, value: 2 },
+            TrieEvent::Inserted { pattern: parser.parse_tokens("x.y.z")?, value: 3 },
+            TrieEvent::Removed { pattern: parser.parse_tokens("x.y.z")?, value: 3 },
+            TrieEvent::Cleared,
+        ]);
+        assert!(trie.take_observer().is_some());
+        trie.insert(&parser.parse_tokens("a")?, 4);
+        assert_eq!(events.lock().unwrap().len(), 7);
+        Ok(())
+    }
+
+    #[test]
+    fn test_debug_tree() -> Result<(), CommonTokenError> {
+        let mut trie = Trie::<_, 10>::new();
+        let parser = CommonTokenParser::new('.', "*", ">");
+        trie.insert(&parser.parse_tokens("a.b.c")?, 2);
+        trie.insert(&parser.parse_tokens("a.b.c")?, 1);
+        trie.insert(&parser.parse_tokens("a.>")?, 3);
+        trie.insert(&parser.parse_tokens("a.*")?, 4);
+        trie.insert(&parser.parse_tokens("a.(y|x)")?, 5);
+        trie.insert(&parser.parse_tokens(">")?, 6);
+        assert_eq!(trie.debug_tree(), "\
+<root> => >{6}
+a => >{3}
+  b
+    c => {1, 2}
+  (x|y) => {5}
+  * => {4}
+");
+        assert_eq!(Trie::<u8, 10>::new().debug_tree(), "");
+        Ok(())
+    }
+
+    #[test]
+    fn test_fanout() -> Result<(), CommonTokenError> {
+        let mut trie = Trie::<_, 10>::new();
+        let parser = CommonTokenParser::new('.', "*", ">");
+        assert_eq!(trie.max_fanout(), 0);
+        assert_eq!(trie.child_count(&Tokens::default()), Some(0));
+        trie.insert(&parser.parse_tokens("a.b")?, 1);
+        trie.insert(&parser.parse_tokens("a.c")?, 2);
+        trie.insert(&parser.parse_tokens("a.*.x")?, 3);
+        trie.insert(&parser.parse_tokens("a.*.y")?, 4);
+        trie.insert(&parser.parse_tokens("a.*.z")?, 5);
+        assert_eq!(trie.child_count(&parser.parse_tokens("a")?), Some(2));
+        assert_eq!(trie.child_count(&parser.parse_tokens("a.*")?), Some(3));
+        assert_eq!(trie.child_count(&parser.parse_tokens("a.>")?), Some(2));
+        assert_eq!(trie.child_count(&parser.parse_tokens("a.b")?), Some(0));
+        assert_eq!(trie.child_count(&parser.parse_tokens("b")?), None);
+        assert_eq!(trie.max_fanout(), 3);
+        Ok(())
+    }
+
+    #[test]
+    fn test_subtree_values() -> Result<(), CommonTokenError> {
+        let mut trie = Trie::<_, 10>::new();
+        let parser = CommonTokenParser::new('.', "*", ">");
+        trie.insert(&parser.parse_tokens("a.b")?, 1);
+        trie.insert(&parser.parse_tokens("a.b.c")?, 2);
+        trie.insert(&parser.parse_tokens("a.b.*")?, 3);
+        trie.insert(&parser.parse_tokens("a.b.>")?, 4);
+        trie.insert(&parser.parse_tokens("a.b.(x|y).z")?, 5);
+        trie.insert(&parser.parse_tokens("a.c")?, 6);
+        trie.insert(&parser.parse_tokens("a.*")?, 7);
+        assert_eq!(sorted(trie.subtree_values(["a", "b"])), vec![1, 2, 3, 4, 5]);
+        assert_eq!(sorted(trie.subtree_values(["a"])), vec![1, 2, 3, 4, 5, 6, 7]);
+        assert_eq!(sorted(trie.subtree_values(["a", "b", "c"])), vec![2]);
+        // 前缀只与普通token比较
+        assert!(trie.subtree_values(["a", "d"]).is_empty());
+        assert_eq!(trie.subtree_values([]).len(), 7);
+        Ok(())
+    }
+
+    #[test]
+    fn test_update() -> Result<(), CommonTokenError> {
+        let mut trie = Trie::<_, 10>::new();
+        let parser = CommonTokenParser::new('.', "*", ">");
+        let tokens = parser.parse_tokens("a.*")?;
+        trie.insert(&tokens, (1, 0));
+        trie.insert(&tokens, (2, 0));
+        assert!(vec_eq(trie.find(["a", "b"]), vec![(1, 0), (2, 0)]));
+        assert!(trie.update(&tokens, &(1, 0), |v| v.1 += 1));
+        assert!(vec_eq(trie.find(["a", "b"]), vec![(1, 1), (2, 0)]));
+        assert!(!trie.update(&tokens, &(1, 0), |_| unreachable!()));
+        assert!(!trie.update(&parser.parse_tokens("b")?, &(2, 0), |_| unreachable!()));
+        // 修改后与已有的值相等时合并
+        assert!(trie.update(&tokens, &(2, 0), |v| *v = (1, 1)));
+        assert_eq!(trie.find(["a", "b"]), vec![(1, 1)]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_find_ranked() -> Result<(), CommonTokenError> {
+        let mut trie = Trie::<_, 10>::new();
+        let parser = CommonTokenParser::new('.', "*", ">");
+        trie.insert(&parser.parse_tokens("a.>")?, 1);
+        trie.insert(&parser.parse_tokens("a.*.c")?, 2);
+        trie.insert(&parser.parse_tokens("a.b.c")?, 3);
+        trie.insert(&parser.parse_tokens("a.(b|x).c")?, 4);
+        trie.insert(&parser.parse_tokens("a.b.d")?, 5);
+        let ranked = trie.find_ranked(["a", "b", "c"]);
+        assert_eq!(ranked[0], (2, 3));
+        assert!(vec_eq(ranked[1..3].to_vec(), vec![(1, 2), (1, 4)]));
+        assert_eq!(ranked[3], (0, 1));
+        assert_eq!(ranked.len(), 4);
+        assert!(trie.find_ranked(["b"]).is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_with_interner() -> Result<(), CommonTokenError> {
+        let mut trie = Trie::<_, 10>::with_interner();
+        let parser = CommonTokenParser::new('.', "*", ">").fold_case(FoldMode::Ascii);
+        trie.insert(&parser.parse_tokens("A.B")?, 1);
+        trie.insert(&parser.parse_tokens("B.A")?, 2);
+        trie.insert(&parser.parse_tokens("a.*")?, 3);
+        assert!(vec_eq(trie.find(["a", "b"]), vec![1, 3]));
+        assert_eq!(trie.find(["b", "a"]), vec![2]);
+        assert_eq!(
+            trie.longest_match(["a", "b"]),
+            Some((parser.parse_tokens("a.b")?, vec![1]))
+        );
+        assert!(trie.remove(&parser.parse_tokens("a.b")?, &1));
+        trie.shrink_to_fit();
+        assert_eq!(trie.find(["a", "b"]), vec![3]);
+        let pairs: Vec<_> = trie.into_iter().collect();
+        assert!(vec_eq(pairs, vec![
+            (parser.parse_tokens("b.a")?, 2),
+            (parser.parse_tokens("a.*")?, 3),
+        ]));
+        Ok(())
+    }
+
+    #[test]
+    fn test_cache_len() -> Result<(), CommonTokenError> {
+        let mut trie = Trie::<_, 2>::new();
+        let parser = CommonTokenParser::new('.', "*", ">");
+        assert_eq!(trie.cache_capacity(), 2);
+        assert_eq!(trie.cache_len(), 0);
+        trie.insert(&parser.parse_tokens("a")?, 1);
+        trie.insert(&parser.parse_tokens("b")?, 2);
+        trie.insert(&parser.parse_tokens("c")?, 3);
+        assert_eq!(trie.find(["a"]), vec![1]);
+        assert_eq!(trie.find(["b"]), vec![2]);
+        assert_eq!(trie.cache_len(), 2);
+        // 已满时淘汰最久没有使用的结果，被淘汰的key重新查询也不会得到其它key的结果
+        assert_eq!(trie.find(["a"]), vec![1]);
+        assert_eq!(trie.find(["c"]), vec![3]);
+        assert_eq!(trie.cache_len(), 2);
+        assert_eq!(trie.find(["b"]), vec![2]);
+        assert_eq!(trie.find(["a"]), vec![1]);
+        // 失效的结果会被移除
+        trie.insert(&parser.parse_tokens("a")?, 4);
+        assert_eq!(trie.cache_len(), 1);
+        trie.clear();
+        assert_eq!(trie.cache_len(), 0);
+        // 容量为0时不缓存
+        let mut trie = Trie::<_, 0>::new();
+        trie.insert(&parser.parse_tokens("a")?, 1);
+        assert_eq!(trie.find(["a"]), vec![1]);
+        assert_eq!(trie.cache_len(), 0);
+        Ok(())
+    }
+
+    #[test]
+    fn test_remove_matching() -> Result<(), CommonTokenError> {
+        let mut trie = Trie::<_, 10>::new();
+        let parser = CommonTokenParser::new('.', "*", ">");
+        trie.insert(&parser.parse_tokens("a.b.c")?, 1);
+        trie.insert(&parser.parse_tokens("a.*.c")?, 1);
+        trie.insert(&parser.parse_tokens("a.(b|x).c")?, 1);
+        trie.insert(&parser.parse_tokens("a.>")?, 1);
+        trie.insert(&parser.parse_tokens("a.b.d")?, 1);
+        trie.insert(&parser.parse_tokens("a.*.c")?, 2);
+        assert!(vec_eq(trie.find(["a", "b", "c"]), vec![1, 2]));
+        assert!(vec_eq(trie.find(["a", "x", "c"]), vec![1, 2]));
+        assert_eq!(trie.remove_matching(&["a", "b", "c"], &1), 4);
+        assert_eq!(trie.find(["a", "b", "c"]), vec![2]);
+        assert_eq!(trie.find(["a", "x", "c"]), vec![2]);
+        // 不匹配的组不受影响
+        assert_eq!(trie.find(["a", "b", "d"]), vec![1]);
+        assert_eq!(trie.remove_matching(&["a", "b", "c"], &1), 0);
+        // 变空的结点被删除
+        assert_eq!(trie.remove_matching(&["a", "b", "d"], &1), 1);
+        assert_eq!(trie.remove_matching(&["a", "b", "c"], &2), 1);
+        assert_eq!(trie.max_fanout(), 0);
+        Ok(())
+    }
+
+    #[test]
+    fn test_load_lines() -> Result<(), CommonTokenError> {
+        let mut trie = Trie::<_, 10>::new();
+        let parser = CommonTokenParser::new('.', "*", ">");
+        let text = "a.b\t1\n\na.*\t2\r\na.>\t3\n";
+        assert_eq!(trie.load_lines(text, &parser, |v| v.parse::<i32>().unwrap()), Ok(3));
+        assert!(vec_eq(trie.find(["a", "b"]), vec![1, 2, 3]));
+        assert_eq!(trie.find(["a", "b", "c"]), vec![3]);
+
+        let mut trie = Trie::<i32, 10>::new();
+        assert_eq!(
+            trie.load_lines("a\t1\nb 2", &parser, |_| 0),
+            Err(LoadError::MissingValue { line: 2 })
+        );
+        assert_eq!(
+            trie.load_lines("a\t1\na.>.b\t2", &parser, |_| 0),
+            Err(LoadError::Parse {
+                line: 2,
+                source: CommonTokenError::MultiWildcardNotAtEnd { input: "a.>.b".into(), span: 2..3 },
+            })
+        );
+        // 解析器给出了无效的tokens
+        struct TrailingMwcParser;
+        impl TokenParser for TrailingMwcParser {
+            type Error = CommonTokenError;
+            fn parse_tokens<'a>(&self, source: &'a str) -> Result<Tokens<'a>, Self::Error> {
+                Ok(vec![Token::MultiWildcard, Token::from(source)].into())
+            }
+        }
+        assert_eq!(
+            trie.load_lines("a\t1", &TrailingMwcParser, |_| 0),
+            Err(LoadError::Invalid { line: 1, source: Error::TokenAfterMwc(1) })
+        );
+        // 出错时不做任何修改
+        assert!(!trie.exist(["a"]));
+        Ok(())
+    }
+
+    #[test]
+    fn test_membership() -> Result<(), CommonTokenError> {
+        let mut trie = Trie::<(), 10>::new();
+        let parser = CommonTokenParser::new('.', "*", ">");
+        trie.insert_pattern(&parser.parse_tokens("a.*")?);
+        trie.insert_pattern(&parser.parse_tokens("b.>")?);
+        trie.insert_pattern(&parser.parse_tokens("a.*")?);
+        assert!(trie.matches(["a", "b"]));
+        assert!(trie.matches(["b", "c", "d"]));
+        assert!(!trie.matches(["a"]));
+        assert!(!trie.matches(["c", "d"]));
+        assert!(trie.remove_pattern(&parser.parse_tokens("a.*")?));
+        assert!(!trie.remove_pattern(&parser.parse_tokens("a.*")?));
+        assert!(!trie.matches(["a", "b"]));
+        Ok(())
+    }
+
+    #[test]
+    fn test_append() -> Result<(), CommonTokenError> {
+        let parser = CommonTokenParser::new('.', "*", ">");
+        let mut trie = Trie::<_, 10>::new();
+        trie.insert(&parser.parse_tokens("a.b")?, 1);
+        trie.insert(&parser.parse_tokens("a.*")?, 2);
+        assert!(vec_eq(trie.find(["a", "b"]), vec![1, 2]));
+        let mut other = Trie::<_, 10>::new();
+        other.insert(&parser.parse_tokens("a.b")?, 1);
+        other.insert(&parser.parse_tokens("a.b")?, 3);
+        other.insert(&parser.parse_tokens("a.>")?, 4);
+        other.insert(&parser.parse_tokens("c")?, 5);
+        assert_eq!(other.find(["c"]), vec![5]);
+        trie.append(&mut other);
+        assert!(vec_eq(trie.find(["a", "b"]), vec![1, 2, 3, 4]));
+        assert_eq!(trie.find(["c"]), vec![5]);
+        assert!(other.find(["c"]).is_empty());
+        assert_eq!(other.into_iter().count(), 0);
+        assert_eq!(trie.into_iter().count(), 5);
+        Ok(())
+    }
+
+    #[test]
+    fn test_append_keeps_bookkeeping() -> Result<(), CommonTokenError> {
+        use std::time::Duration;
+        let parser = CommonTokenParser::new('.', "*", ">");
+        let ab = parser.parse_tokens("a.b")?;
+        let later = Instant::now() + Duration::from_secs(60);
+        // 组已满时放不下的值被返回，不会丢失
+        let mut trie = Trie::<_, 10>::new();
+        trie.set_group_limit(1);
+        trie.insert(&ab, 1);
+        let mut other = Trie::<_, 10>::new();
+        other.insert(&ab, 1);
+        other.insert(&ab, 2);
+        other.insert(&parser.parse_tokens("c")?, 3);
+        assert_eq!(trie.append(&mut other), vec![(ab.clone(), 2)]);
+        assert_eq!(trie.find(["a", "b"]), vec![1]);
+        assert_eq!(trie.find(["c"]), vec![3]);
+        assert_eq!(other.into_iter().count(), 0);
+        // 次数和过期时间随值一起移动
+        let mut trie = Trie::<_, 10>::new();
+        trie.set_duplicate_mode(DuplicateMode::Count);
+        trie.insert(&ab, 1);
+        let mut other = Trie::<_, 10>::new();
+        other.set_duplicate_mode(DuplicateMode::Count);
+        other.insert(&ab, 1);
+        other.insert(&ab, 1);
+        other.insert(&parser.parse_tokens("a.>")?, 2);
+        other.insert(&parser.parse_tokens("a.>")?, 2);
+        other.insert_with_ttl(&parser.parse_tokens("a.*")?, 3, later);
+        assert!(trie.append(&mut other).is_empty());
+        assert_eq!(trie.count_of(&ab, &1), 3);
+        assert_eq!(trie.count_of(&parser.parse_tokens("a.>")?, &2), 2);
+        assert!(vec_eq(trie.find(["a", "b"]), vec![1, 2, 3]));
+        assert_eq!(other.expire(later), 0);
+        assert_eq!(trie.expire(later), 1);
+        assert!(vec_eq(trie.find(["a", "b"]), vec![1, 2]));
+        Ok(())
+    }
+
+    #[test]
+    fn test_match_priority() -> Result<(), CommonTokenError> {
+        let mut trie = Trie::<_, 10>::new();
+        let parser = CommonTokenParser::new('.', "*", ">");
+        trie.insert(&parser.parse_tokens("*.b")?, 1);
+        trie.insert(&parser.parse_tokens("a.*")?, 2);
+        trie.insert(&parser.parse_tokens(">")?, 3);
+        trie.insert(&parser.parse_tokens("a.b")?, 4);
+        let ranked = trie.find_ranked(["a", "b"]);
+        assert_eq!(ranked[0], (2, 4));
+        assert_eq!(ranked[3], (0, 3));
+
+        trie.set_priority(MatchPriority::LeftmostSpecific);
+        assert_eq!(trie.find_ranked(["a", "b"]), vec![(2, 4), (1, 2), (1, 1), (0, 3)]);
+
+        // 越短的模式越优先，长度相同时单层wildcard越靠后越优先
+        trie.set_priority(MatchPriority::Custom(Box::new(|a, b| {
+            let owcs = |p: &Tokens| p.0.iter().map(|t| matches!(t, Token::OneWildcard(_))).collect::<Vec<_>>();
+            a.0.len().cmp(&b.0.len()).then_with(|| owcs(a).cmp(&owcs(b)))
+        })));
+        assert_eq!(trie.find_ranked(["a", "b"]), vec![(0, 3), (2, 4), (1, 2), (1, 1)]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_deep_trie() {
+        const DEPTH: usize = 100_000;
+        let keys = vec!["a"; DEPTH];
+        let tokens: Tokens = keys.iter().map(|&k| Token::from(k)).collect::<Vec<_>>().into();
+        let mut trie = Trie::<_, 10>::new();
+        trie.insert(&tokens, 1);
+        trie.insert(&tokens, 2);
+        assert!(vec_eq(trie.find(&keys), vec![1, 2]));
+        assert_eq!(trie.subtree_values(["a"]).len(), 2);
+        assert_eq!(trie.max_fanout(), 1);
+        assert_eq!(trie.remove_matching(&keys, &1), 1);
+        trie.shrink_to_fit();
+        assert_eq!(trie.find(&keys), vec![2]);
+        let prefix: Tokens = keys[..DEPTH / 2].iter().map(|&k| Token::from(k)).collect::<Vec<_>>().into();
+        assert_eq!(trie.remove_subtree(&prefix), 1);
+        assert_eq!(trie.max_fanout(), 0);
+        trie.insert(&tokens, 3);
+        assert_eq!(trie.into_iter().count(), 1);
+        // 整条链被压缩为一条边
+        let mut trie = Trie::<_, 10>::new();
+        trie.insert(&tokens, 4);
+        let compact = trie.compact();
+        assert_eq!(compact.node_count(), 2);
+        assert_eq!(compact.find(&keys), vec![4]);
+        assert!(!compact.exist(&keys[1..]));
+    }
+
+    #[test]
+    fn test_matching_patterns() -> Result<(), CommonTokenError> {
+        let mut trie = Trie::<_, 10>::new();
+        let parser = CommonTokenParser::new('.', "*", ">");
+        trie.insert(&parser.parse_tokens("a.b")?, 1);
+        trie.insert(&parser.parse_tokens("a.*")?, 2);
+        trie.insert(&parser.parse_tokens("a.>")?, 3);
+        trie.insert(&parser.parse_tokens("a.(b|c)")?, 4);
+        trie.insert(&parser.parse_tokens("a.c")?, 5);
+        // 空的组不会被返回
+        trie.insert(&parser.parse_tokens("*.b")?, 6);
+        trie.remove(&parser.parse_tokens("*.b")?, &6);
+        assert!(vec_eq(trie.matching_patterns(["a", "b"]), vec![
+            parser.parse_tokens("a.b")?,
+            parser.parse_tokens("a.*")?,
+            parser.parse_tokens("a.>")?,
+            parser.parse_tokens("a.(b|c)")?,
+        ]));
+        assert!(trie.matching_patterns(["b", "b"]).is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_match_keys_consistent_with_find() -> Result<(), CommonTokenError> {
+        // 缓存的失效依赖于match_keys与find的结果完全一致
+        let parser = CommonTokenParser::new('.', "*", ">");
+        let patterns = [">", "*", "a", "a.>", "a.*", "*.>", "a.b", "(a|b).>", ""];
+        let keys: Vec<Vec<&str>> = vec![
+            vec![], vec![""], vec!["a"], vec!["b"], vec!["a", "b"], vec!["b", "a"], vec!["a", "b", "c"],
+        ];
+        let mut trie = Trie::<_, 10>::new();
+        for (i, pattern) in patterns.iter().enumerate() {
+            trie.insert(&parser.parse_tokens(pattern)?, i);
+        }
+        // 空的tokens匹配空的keys
+        trie.insert(&Tokens::default(), patterns.len());
+        for key in keys.iter() {
+            let found = trie.find(key);
+            for (i, pattern) in patterns.iter().enumerate() {
+                let tokens = parser.parse_tokens(pattern)?;
+                assert_eq!(tokens.match_keys(key), found.contains(&i), "{:?} {:?}", pattern, key);
+            }
+            assert_eq!(Tokens::<Cow<str>>::default().match_keys(key), found.contains(&patterns.len()), "{:?}", key);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_with_root_capacity() -> Result<(), CommonTokenError> {
+        let mut trie = Trie::<_, 10>::with_root_capacity(100);
+        let parser = CommonTokenParser::new('.', "*", ">");
+        trie.insert(&parser.parse_tokens("a.b")?, 1);
+        assert_eq!(trie.find(["a", "b"]), vec![1]);
+        assert_eq!(trie.child_count(&Tokens::default()), Some(1));
+        Ok(())
+    }
+
+    #[test]
+    fn test_op_log() -> Result<(), CommonTokenError> {
+        let parser = CommonTokenParser::new('.', "*", ">");
+        let mut trie = Trie::<_, 10>::new();
+        trie.insert(&parser.parse_tokens("x")?, 0);
+        trie.enable_op_log();
+        trie.clear();
+        trie.insert(&parser.parse_tokens("a.b")?, 1);
+        trie.insert(&parser.parse_tokens("a.b")?, 1);
+        trie.insert(&parser.parse_tokens("a.*")?, 2);
+        trie.insert(&parser.parse_tokens("a.>")?, 3);
+        trie.insert(&parser.parse_tokens("a.>")?, 4);
+        assert!(trie.remove(&parser.parse_tokens("a.*")?, &2));
+        assert!(!trie.remove(&parser.parse_tokens("a.*")?, &2));
+        assert!(trie.remove_all(&parser.parse_tokens("a.>")?));
+        trie.insert(&parser.parse_tokens("c")?, 5);
+        let log = trie.op_log().unwrap().clone();
+        assert_eq!(log.len(), 9);
+        assert_eq!(log.ops()[0], Op::Clear);
+        assert_eq!(log.ops()[1], Op::Insert { pattern: parser.parse_tokens("a.b")?.to_static(), value: 1 });
+
+        // 在新的trie树上重放得到相同的内容
+        let mut replayed = Trie::<_, 10>::new();
+        replayed.insert(&parser.parse_tokens("y")?, 6);
+        replayed.replay(&log);
+        assert_eq!(replayed.find(["a", "b"]), vec![1]);
+        assert_eq!(replayed.find(["c"]), vec![5]);
+        assert!(replayed.find(["y"]).is_empty());
+        assert_eq!(replayed.into_iter().count(), 2);
+
+        assert_eq!(trie.take_op_log(), Some(log));
+        trie.insert(&parser.parse_tokens("d")?, 7);
+        assert!(trie.op_log().is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn test_find_captures() -> Result<(), CommonTokenError> {
+        let mut trie = Trie::<_, 10>::new();
+        let parser = CommonTokenParser::new('.', "*", ">").capture_prefix(':');
+        trie.insert(&parser.parse_tokens("users.:id.posts.:post")?, 1);
+        trie.insert(&parser.parse_tokens("users.:uid.>")?, 2);
+        trie.insert(&parser.parse_tokens("users.*.posts.*")?, 3);
+        trie.insert(&parser.parse_tokens("users.(a|b).posts.:post")?, 4);
+        trie.insert(&parser.parse_tokens("users.a.posts.x")?, 5);
+
+        let mut found = trie.find_captures(["users", "a", "posts", "x"]);
+        found.sort_by_key(|(v, _)| *v);
+        let expected: Vec<(i32, HashMap<&str, &str>)> = vec![
+            // 同一组中后插入的tokens没有名字，覆盖之前的捕获名
+            (1, HashMap::new()),
+            (2, HashMap::from([("uid", "a")])),
+            (3, HashMap::new()),
+            (4, HashMap::from([("post", "x")])),
+            (5, HashMap::new()),
+        ];
+        assert_eq!(found, expected);
+        assert!(vec_eq(found.into_iter().map(|(v, _)| v).collect(), trie.find(["users", "a", "posts", "x"])));
+
+        // 捕获名不影响结点，带名字和不带名字的tokens对应同一个组
+        assert!(trie.remove(&parser.parse_tokens("users.*.>")?, &2));
+        assert!(trie.find_captures(["users", "a", "b"]).is_empty());
+        trie.insert(&parser.parse_tokens("users.:name.>")?, 2);
+        assert_eq!(
+            trie.find_captures(["users", "c", "d"]),
+            vec![(2, HashMap::from([("name", "c")]))]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_owc_chain() -> Result<(), CommonTokenError> {
+        let mut trie = Trie::<_, 10>::new();
+        let parser = CommonTokenParser::new('.', "*", ">");
+        let chain = |n: usize| Tokens::from(vec![Token::OneWildcard(None); n]);
+        for n in 1..=3 {
+            trie.insert(&chain(n), n);
+        }
+        assert_eq!(chain(3), parser.parse_tokens("*.*.*")?);
+        // 先查询，让缓存中有结果
+        let keys = ["a", "b", "c", "d"];
+        for n in 0..=4 {
+            let expected: Vec<usize> = (1..=3).filter(|&m| m == n).collect();
+            assert_eq!(trie.find(&keys[..n]), expected);
+            assert_eq!(trie.exist(&keys[..n]), !expected.is_empty());
+        }
+        // 单层wildcard只匹配一个key，不会跨越层级
+        trie.insert(&parser.parse_tokens("*.*.c")?, 4);
+        assert_eq!(sorted(trie.find(["a", "b", "c"])), vec![3, 4]);
+        assert_eq!(trie.find(["a", "b", "d"]), vec![3]);
+        assert_eq!(trie.find(["a", "c"]), vec![2]);
+        // 缓存中的结果随着修改失效
+        assert!(trie.remove(&chain(3), &3));
+        assert_eq!(trie.find(["a", "b", "c"]), vec![4]);
+        assert!(trie.find(["a", "b", "d"]).is_empty());
+        assert!(!trie.exist(["a", "b", "d"]));
+        assert_eq!(trie.find(["a", "b"]), vec![2]);
+        assert_eq!(trie.find(["a"]), vec![1]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_exist_batch() -> Result<(), CommonTokenError> {
+        let mut trie = Trie::<_, 10>::new();
+        let parser = CommonTokenParser::new('.', "*", ">");
+        trie.insert(&parser.parse_tokens("a.b")?, 1);
+        trie.insert(&parser.parse_tokens("a.*.c")?, 2);
+        trie.insert(&parser.parse_tokens("b.>")?, 3);
+        trie.insert(&parser.parse_tokens("(c|d).x")?, 4);
+        let subjects: Vec<&[&str]> = vec![
+            &[], &["a"], &["a", "b"], &["a", "x", "c"], &["a", "x"], &["b"], &["b", "y", "z"],
+            &["c", "x"], &["d", "x"], &["d", "y"], &["e"], &["a", "b"],
+        ];
+        let expected: Vec<bool> = subjects.iter().map(|keys| trie.exist(keys)).collect();
+        assert_eq!(trie.exist_batch(&subjects), expected);
+        assert_eq!(
+            expected,
+            vec![false, false, true, true, false, false, true, true, true, false, false, true]
+        );
+        trie.insert(&parser.parse_tokens(">")?, 5);
+        assert_eq!(trie.exist_batch(&subjects).iter().filter(|e| **e).count(), 11);
+        assert!(trie.exist_batch(&[]).is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_remove_if() -> Result<(), CommonTokenError> {
+        // 值只以名字比较，计数不参与比较
+        #[derive(Debug, Clone)]
+        struct Sub(&'static str, u32);
+        impl PartialEq for Sub {
+            fn eq(&self, other: &Self) -> bool { self.0 == other.0 }
+        }
+        impl Eq for Sub {}
+        impl Hash for Sub {
+            fn hash<H: std::hash::Hasher>(&self, state: &mut H) { self.0.hash(state) }
+        }
+
+        let mut trie = Trie::<_, 10>::new();
+        let parser = CommonTokenParser::new('.', "*", ">");
+        trie.insert(&parser.parse_tokens("a.b")?, Sub("x", 2));
+        trie.insert(&parser.parse_tokens("a.>")?, Sub("y", 0));
+        assert_eq!(trie.find(["a", "b"]).len(), 2);
+        let idle = |s: &Sub| s.1 == 0;
+        assert!(!trie.remove_if(&parser.parse_tokens("a.b")?, &Sub("x", 0), idle));
+        assert!(!trie.remove_if(&parser.parse_tokens("a.c")?, &Sub("x", 0), idle));
+        assert!(trie.remove_if(&parser.parse_tokens("a.>")?, &Sub("y", 5), idle));
+        // 缓存随着移除失效
+        assert_eq!(trie.find(["a", "b"]), vec![Sub("x", 2)]);
+        assert!(trie.remove_if(&parser.parse_tokens("a.b")?, &Sub("x", 0), |s| s.1 == 2));
+        assert!(trie.find(["a", "b"]).is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_schema_trie() -> Result<(), Error> {
+        let mut trie = SchemaTrie::<_, 3, 10>::new();
+        trie.insert(["eu", "auth", "i-1"], 1)?;
+        trie.insert([Token::from("eu"), Token::OneWildcard(None), Token::from("i-1")], 2)?;
+        trie.insert([Token::OneOf(vec!["eu".into(), "us".into()]), "auth".into(), Token::OneWildcard(None)], 3)?;
+        // 多层wildcard只能在最后
+        assert_eq!(trie.insert([Token::from("eu"), Token::MultiWildcard, Token::from("i-1")], 4), Err(Error::TokenAfterMwc(2)));
+        assert_eq!(trie.insert([Token::from("eu"), Token::from("auth"), Token::MultiWildcard], 5), Ok(()));
+        assert_eq!(sorted(trie.find(["eu", "auth", "x"])), vec![3, 5]);
+        assert!(trie.remove([Token::from("eu"), Token::from("auth"), Token::MultiWildcard], &5));
+        assert_eq!(sorted(trie.find(["eu", "auth", "i-1"])), vec![1, 2, 3]);
+        assert_eq!(trie.find(["us", "auth", "i-2"]), vec![3]);
+        assert!(trie.exist(["eu", "db", "i-1"]));
+        assert!(!trie.exist(["ap", "auth", "i-1"]));
+        assert!(trie.remove(["eu", "auth", "i-1"], &1));
+        assert!(!trie.remove(["eu", "auth", "i-1"], &1));
+        assert_eq!(sorted(trie.find(["eu", "auth", "i-1"])), vec![2, 3]);
+        assert_eq!(trie.as_trie().find_owned(&["eu", "db", "i-1"]), vec![2]);
+        assert_eq!(trie.into_inner().into_iter().count(), 2);
+        Ok(())
+    }
+
+    #[test]
+    fn test_mwc_includes_prefix() -> Result<(), CommonTokenError> {
+        let mut trie = Trie::<_, 10>::new();
+        let parser = CommonTokenParser::new('.', "*", ">");
+        trie.insert(&parser.parse_tokens("a")?, 1);
+        trie.insert(&parser.parse_tokens("a.>")?, 2);
+        trie.insert(&parser.parse_tokens("*.>")?, 3);
+        trie.insert(&parser.parse_tokens("b.c.>")?, 4);
+        // 默认多层wildcard组至少匹配一个token
+        assert_eq!(trie.find(["a"]), vec![1]);
+        assert_eq!(trie.find(["b", "c"]), vec![3]);
+        assert!(trie.find(Vec::<&str>::new()).is_empty());
+
+        trie.set_mwc_includes_prefix(true);
+        // 多层wildcard组的值保存在前缀对应的结点上，key在前缀处结束时也会被读取
+        assert_eq!(sorted(trie.find(["a"])), vec![1, 2, 3]);
+        assert_eq!(sorted(trie.find(["a", "b"])), vec![2, 3]);
+        assert_eq!(sorted(trie.find(["b", "c"])), vec![3, 4]);
+        assert_eq!(trie.snapshot().count(["a"]), 3);
+        assert_eq!(trie.match_breakdown(["b", "c"]).multi_wildcard, 2);
+        assert!(trie.exist(["x"]));
+        assert!(trie.any_match(["x"]).is_some());
+        assert!(vec_eq(
+            trie.matching_patterns(["b", "c"]),
+            vec![parser.parse_tokens("*.>")?, parser.parse_tokens("b.c.>")?]
+        ));
+        let mut matcher = trie.matcher();
+        matcher.step("b");
+        matcher.step("c");
+        assert_eq!(sorted(matcher.matches()), vec![&3, &4]);
+        assert_eq!(trie.find(["b"]), vec![3]);
+        // 修改多层wildcard组时，与前缀相同的key的缓存结果也会过期
+        assert!(trie.remove(&parser.parse_tokens("b.c.>")?, &4));
+        assert_eq!(trie.find(["b", "c"]), vec![3]);
+        trie.insert(&parser.parse_tokens("b.c.>")?, 5);
+        assert_eq!(sorted(trie.find(["b", "c"])), vec![3, 5]);
+
+        // 关闭之后恢复原来的行为，缓存也被清空
+        trie.set_mwc_includes_prefix(false);
+        assert_eq!(trie.find(["a"]), vec![1]);
+        assert_eq!(trie.find(["b", "c"]), vec![3]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_compact() -> Result<(), CommonTokenError> {
+        // 多层wildcard组是否也匹配其前缀本身，压缩之后保持不变
+        for includes in [false, true] {
+            let mut trie = Trie::<_, 10>::new();
+            trie.set_mwc_includes_prefix(includes);
+            let parser = CommonTokenParser::new('.', "*", ">");
+            let patterns = [
+                "com.example.service.v1.api.users",
+                "com.example.service.v1.api.orders",
+                "com.example.service.v2.*.users",
+                "com.example.(service|backend).>",
+                "com.other.x.y.z",
+                "com.>",
+                "a",
+            ];
+            for (v, pattern) in patterns.iter().enumerate() {
+                trie.insert(&parser.parse_tokens(pattern)?, v);
+            }
+            let subjects = [
+                "com.example.service.v1.api.users",
+                "com.example.service.v1.api",
+                "com.example.service.v2.x.users",
+                "com.example.backend.v1",
+                "com.other.x.y.z",
+                "com.other.x.y",
+                "com.other.x.y.z.w",
+                "com",
+                "a",
+                "a.b",
+                "",
+            ];
+            let expected: Vec<Vec<usize>> = subjects.iter()
+                .map(|s| sorted(trie.find_owned(&s.split('.').collect::<Vec<_>>())))
+                .collect();
+            let before = trie.node_count();
+            let compact = trie.compact();
+            // `other`之后的`x.y.z`、`v1`之后的`api`以及`v2.*`之后的`users`都合并到边上
+            assert_eq!(compact.edge_token_count(), before - 1);
+            assert_eq!(compact.node_count(), before - 5);
+            for (subject, expected) in subjects.iter().zip(expected) {
+                let keys: Vec<&str> = subject.split('.').collect();
+                assert_eq!(sorted(compact.find(&keys)), expected, "{}", subject);
+                assert_eq!(compact.count(&keys), expected.len());
+                assert_eq!(compact.exist(&keys), !expected.is_empty());
+            }
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_compact_insert() -> Result<(), CommonTokenError> {
+        let parser = CommonTokenParser::new('.', "*", ">");
+        let mut trie = Trie::<_, 10>::new();
+        trie.insert(&parser.parse_tokens("com.example.service.v1.api.users")?, 0);
+        let mut compact = trie.compact();
+        assert_eq!((compact.node_count(), compact.edge_token_count()), (2, 6));
+        // 在service之后分叉，拆分原来的边
+        compact.insert(&parser.parse_tokens("com.example.service.v2")?, 1);
+        assert_eq!((compact.node_count(), compact.edge_token_count()), (4, 7));
+        // 在边的中间添加值，也要拆分边
+        compact.insert(&parser.parse_tokens("com.example")?, 2);
+        assert_eq!((compact.node_count(), compact.edge_token_count()), (5, 7));
+        // wildcard之后的普通token放在同一条边上
+        compact.insert(&parser.parse_tokens("com.*.x.y")?, 3);
+        assert_eq!((compact.node_count(), compact.edge_token_count()), (7, 10));
+        // 边上已有的token不再生成结点
+        compact.insert(&parser.parse_tokens("com.example.service.v1.api.users")?, 4);
+        compact.insert(&parser.parse_tokens("com.example.service.v1.api.users.>")?, 5);
+        compact.insert(&parser.parse_tokens("com.*.x.(y|z)")?, 6);
+        compact.insert(&parser.parse_tokens("com.*.x.[1-5].>")?, 7);
+        assert_eq!((compact.node_count(), compact.edge_token_count()), (10, 12));
+
+        // 与先添加到trie树再压缩的结果相同
+        let patterns = [
+            "com.example.service.v1.api.users",
+            "com.example.service.v2",
+            "com.example",
+            "com.*.x.y",
+            "com.example.service.v1.api.users",
+            "com.example.service.v1.api.users.>",
+            "com.*.x.(y|z)",
+            "com.*.x.[1-5].>",
+        ];
+        let mut trie = Trie::<_, 10>::new();
+        for (v, pattern) in patterns.iter().enumerate() {
+            trie.insert(&parser.parse_tokens(pattern)?, v);
+        }
+        let subjects = [
+            "com.example.service.v1.api.users",
+            "com.example.service.v1.api.users.x",
+            "com.example.service.v1",
+            "com.example.service.v2",
+            "com.example",
+            "com.other.x.y",
+            "com.example.x.z",
+            "com.other.x.3.a",
+            "com",
+        ];
+        for subject in subjects.iter() {
+            let keys: Vec<&str> = subject.split('.').collect();
+            assert_eq!(sorted(compact.find(&keys)), sorted(trie.find_owned(&keys)), "{}", subject);
+        }
+        let expected = trie.compact();
+        assert_eq!(compact.node_count(), expected.node_count());
+        assert_eq!(compact.edge_token_count(), expected.edge_token_count());
+        Ok(())
+    }
+
+    #[test]
+    fn test_find_grouped() -> Result<(), CommonTokenError> {
+        let mut trie = Trie::<_, 10>::new();
+        let parser = CommonTokenParser::new('.', "*", ">");
+        trie.insert(&parser.parse_tokens("a.b")?, 1);
+        trie.insert(&parser.parse_tokens("a.b")?, 2);
+        trie.insert(&parser.parse_tokens("a.*")?, 3);
+        trie.insert(&parser.parse_tokens("a.>")?, 4);
+        trie.insert(&parser.parse_tokens("a.c")?, 5);
+        let mut groups: Vec<(Tokens, Vec<&i32>)> = trie.find_grouped(["a", "b"]).into_iter()
+            .map(|(pattern, values)| (pattern, sorted(values)))
+            .collect();
+        groups.sort_by_key(|(_, values)| *values[0]);
+        assert_eq!(groups, vec![
+            (parser.parse_tokens("a.b")?, vec![&1, &2]),
+            (parser.parse_tokens("a.*")?, vec![&3]),
+            (parser.parse_tokens("a.>")?, vec![&4]),
+        ]);
+        let flattened: Vec<i32> = groups.into_iter().flat_map(|(_, values)| values).copied().collect();
+        assert!(vec_eq(flattened, trie.find(["a", "b"])));
+        assert!(trie.find_grouped(["b"]).is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_retain_with_pattern() -> Result<(), CommonTokenError> {
+        let mut trie = Trie::<_, 10>::new();
+        use std::sync::{Arc, Mutex};
+        let parser = CommonTokenParser::new('.', "*", ">");
+        trie.insert(&parser.parse_tokens("a")?, 1);
+        trie.insert(&parser.parse_tokens("a.b.c")?, 2);
+        trie.insert(&parser.parse_tokens("a.b.c")?, 3);
+        trie.insert(&parser.parse_tokens("a.*.c.d")?, 4);
+        trie.insert(&parser.parse_tokens("a.(b|c).>")?, 5);
+        trie.insert(&parser.parse_tokens("x.y.z.>")?, 6);
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let recorded = events.clone();
+        trie.set_observer(Box::new(move |e| {
+            if let TrieEvent::Removed { pattern, value } = e {
+                recorded.lock().unwrap().push((pattern, value));
+            }
+        }));
+        assert_eq!(trie.find(["a", "b", "c", "d"]).len(), 2);
+
+        // 移除深度大于3的组中的值，以及值为3的
+        let removed = trie.retain_with_pattern(|pattern, v| pattern.len() <= 3 && *v != 3);
+        assert_eq!(removed, 3);
+        assert!(vec_eq(
+            events.lock().unwrap().clone(),
+            vec![
+                (parser.parse_tokens("a.b.c")?, 3),
+                (parser.parse_tokens("a.*.c.d")?, 4),
+                (parser.parse_tokens("x.y.z.>")?, 6),
+            ]
+        ));
+        // 缓存被清空，变空的结点被删除
+        assert_eq!(trie.find(["a", "b", "c", "d"]), vec![5]);
+        assert_eq!(sorted(trie.find(["a", "b", "c"])), vec![2, 5]);
+        assert_eq!(trie.node_count(), 5);
+        assert_eq!(trie.retain_with_pattern(|_, _| true), 0);
+        Ok(())
+    }
+
+    #[test]
+    fn test_default_without_value_default() {
+        // 值的类型不需要实现Default
+        #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+        struct NoDefault(u32);
+        let mut trie = Trie::<NoDefault, 8>::default();
+        trie.insert(&vec![Token::from("a")].into(), NoDefault(1));
+        assert_eq!(trie.find(["a"]), vec![NoDefault(1)]);
+        let mut schema = SchemaTrie::<NoDefault, 1, 8>::default();
+        assert!(schema.insert(["a"], NoDefault(2)).is_ok());
+        assert_eq!(schema.find(["a"]), vec![NoDefault(2)]);
+    }
+
+    #[test]
+    fn test_insert_values() -> Result<(), CommonTokenError> {
+        let mut trie = Trie::<_, 10>::new();
+        let parser = CommonTokenParser::new('.', "*", ">");
+        trie.enable_op_log();
+        trie.insert(&parser.parse_tokens("a.>")?, 1);
+        assert_eq!(trie.find(["a", "b"]), vec![1]);
+        assert_eq!(trie.insert_values(&parser.parse_tokens("a.>")?, vec![1, 2, 3, 2]), 2);
+        assert_eq!(trie.insert_values(&parser.parse_tokens("a.b")?, 4..6), 2);
+        assert_eq!(trie.insert_values(&parser.parse_tokens("c")?, vec![]), 0);
+        // 缓存中的结果已经过期
+        assert_eq!(sorted(trie.find(["a", "b"])), vec![1, 2, 3, 4, 5]);
+        assert_eq!(trie.op_log().unwrap().len(), 5);
+        Ok(())
+    }
+
+    #[test]
+    fn test_covers() -> Result<(), CommonTokenError> {
+        let mut trie = Trie::<_, 10>::new();
+        let parser = CommonTokenParser::new('.', "*", ">");
+        trie.insert(&parser.parse_tokens("a.>")?, 1);
+        trie.insert(&parser.parse_tokens("b.*.c")?, 2);
+        trie.insert(&parser.parse_tokens("c.(x|y|z)")?, 3);
+        trie.insert(&parser.parse_tokens("d")?, 4);
+        trie.insert(&parser.parse_tokens("c.w")?, 5);
+        let covers = |candidate: &str| -> Result<Option<Tokens>, CommonTokenError> {
+            Ok(trie.covers(&parser.parse_tokens(candidate)?))
+        };
+        let a_mwc = Some(parser.parse_tokens("a.>")?);
+        assert_eq!(covers("a.b.c")?, a_mwc);
+        assert_eq!(covers("a.*")?, a_mwc);
+        assert_eq!(covers("a.b.>")?, a_mwc);
+        assert_eq!(covers("a.>")?, a_mwc);
+        assert_eq!(covers("a")?, None);
+        let b_owc = Some(parser.parse_tokens("b.*.c")?);
+        assert_eq!(covers("b.x.c")?, b_owc);
+        assert_eq!(covers("b.*.c")?, b_owc);
+        assert_eq!(covers("b.(x|y).c")?, b_owc);
+        assert_eq!(covers("b.>")?, None);
+        assert_eq!(covers("b.x.c.d")?, None);
+        let c_alt = Some(parser.parse_tokens("c.(x|y|z)")?);
+        assert_eq!(covers("c.y")?, c_alt);
+        assert_eq!(covers("c.(x|z)")?, c_alt);
+        assert_eq!(covers("c.(w|x)")?, None);
+        assert_eq!(covers("c.*")?, None);
+        assert_eq!(covers("d")?, Some(parser.parse_tokens("d")?));
+        // 只有一个备选token的多选一token被对应的普通token覆盖
+        assert_eq!(covers("(d)")?, Some(parser.parse_tokens("d")?));
+        let c_w = Some(parser.parse_tokens("c.w")?);
+        assert_eq!(covers("c.(w)")?, c_w);
+        assert_eq!(covers("c.(w|w)")?, c_w);
+        assert_eq!(covers("c.(w|x)")?, None);
+        assert_eq!(covers("*")?, None);
+        assert_eq!(covers(">")?, None);
+        trie.set_mwc_includes_prefix(true);
+        assert_eq!(trie.covers(&parser.parse_tokens("a")?), a_mwc);
+        Ok(())
+    }
+
+    #[test]
+    fn test_find_set() -> Result<(), CommonTokenError> {
+        let mut trie = Trie::<_, 10>::new();
+        let parser = CommonTokenParser::new('.', "*", ">");
+        trie.insert(&parser.parse_tokens("a.b")?, 1);
+        trie.insert(&parser.parse_tokens("a.*")?, 1);
+        trie.insert(&parser.parse_tokens("a.>")?, 1);
+        trie.insert(&parser.parse_tokens("a.>")?, 2);
+        assert_eq!(trie.find(["a", "b"]).len(), 4);
+        assert_eq!(trie.find_set(["a", "b"]), HashSet::from([1, 2]));
+        assert_eq!(trie.find_set(["a", "c", "d"]), HashSet::from([1, 2]));
+        assert!(trie.find_set(["b"]).is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_num_range() -> Result<(), CommonTokenError> {
+        let mut trie = Trie::<_, 10>::new();
+        let parser = CommonTokenParser::new('.', "*", ">");
+        trie.insert(&parser.parse_tokens("sensor.[1-100].temp")?, 1);
+        trie.insert(&parser.parse_tokens("sensor.[50-60].>")?, 2);
+        trie.insert(&parser.parse_tokens("sensor.42.temp")?, 3);
+        assert!(vec_eq(trie.find(["sensor", "42", "temp"]), vec![1, 3]));
+        assert!(vec_eq(trie.find(["sensor", "55", "temp"]), vec![1, 2]));
+        assert!(vec_eq(trie.find(["sensor", "100", "temp"]), vec![1]));
+        assert!(trie.find(["sensor", "101", "temp"]).is_empty());
+        assert!(trie.find(["sensor", "x", "temp"]).is_empty());
+        assert!(trie.exist(["sensor", "60", "humidity"]));
+        assert!(!trie.exist(["sensor", "61", "humidity"]));
+        let mut matcher = trie.matcher();
+        assert!(matcher.step("sensor") && matcher.step("7") && matcher.step("temp"));
+        assert_eq!(matcher.matches(), vec![&1]);
+        assert!(vec_eq(
+            trie.matching_patterns(["sensor", "55", "temp"]),
+            vec![parser.parse_tokens("sensor.[1-100].temp")?, parser.parse_tokens("sensor.[50-60].>")?]
+        ));
+        assert_eq!(
+            trie.covers(&parser.parse_tokens("sensor.[10-20].temp")?),
+            Some(parser.parse_tokens("sensor.[1-100].temp")?)
+        );
+        assert_eq!(
+            trie.covers(&parser.parse_tokens("sensor.(51|59).x")?),
+            Some(parser.parse_tokens("sensor.[50-60].>")?)
+        );
+        assert_eq!(trie.covers(&parser.parse_tokens("sensor.[0-20].temp")?), None);
+        assert_eq!(trie.debug_tree(), "sensor\n  42\n    temp => {3}\n  [1-100]\n    temp => {1}\n  [50-60] => >{2}\n");
+        assert!(trie.remove(&parser.parse_tokens("sensor.[1-100].temp")?, &1));
+        assert!(vec_eq(trie.find(["sensor", "42", "temp"]), vec![3]));
+        let drained: Vec<_> = trie.into_iter().collect();
+        assert!(drained.contains(&(parser.parse_tokens("sensor.[50-60].>")?, 2)));
+        let mut trie = Trie::<_, 10>::new();
+        trie.insert(&parser.parse_tokens("sensor.[50-60].>")?, 2);
+        let compact = trie.compact();
+        assert!(vec_eq(compact.find(["sensor", "55", "x"]), vec![2]));
+        assert!(compact.find(["sensor", "5", "x"]).is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_multi_wildcard_values() -> Result<(), CommonTokenError> {
+        let mut trie = Trie::<_, 10>::new();
+        let parser = CommonTokenParser::new('.', "*", ">");
+        trie.insert(&parser.parse_tokens(">")?, 1);
+        trie.insert(&parser.parse_tokens("a.>")?, 2);
+        trie.insert(&parser.parse_tokens("a.>")?, 3);
+        trie.insert(&parser.parse_tokens("a.*.(b|c).>")?, 4);
+        trie.insert(&parser.parse_tokens("a.b")?, 5);
+        trie.insert(&parser.parse_tokens("a.*")?, 6);
+        assert!(vec_eq(trie.multi_wildcard_values(), vec![
+            (parser.parse_tokens(">")?, &1),
+            (parser.parse_tokens("a.>")?, &2),
+            (parser.parse_tokens("a.>")?, &3),
+            (parser.parse_tokens("a.*.(b|c).>")?, &4),
+        ]));
+        assert!(Trie::<i32, 10>::new().multi_wildcard_values().is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_exact_fast_path() -> Result<(), CommonTokenError> {
+        let mut trie = Trie::<_, 10>::new();
+        let parser = CommonTokenParser::new('.', "*", ">");
+        trie.insert(&parser.parse_tokens("a.b")?, 1);
+        trie.insert(&parser.parse_tokens("a")?, 2);
+        assert!(!trie.has_any_wildcard);
+        assert_eq!(trie.find(["a", "b"]), vec![1]);
+        assert_eq!(trie.find(["a"]), vec![2]);
+        assert!(trie.find(["a", "c"]).is_empty());
+        assert!(trie.exist(["a", "b"]) && !trie.exist(["b"]));
+        trie.insert(&parser.parse_tokens("a.*")?, 3);
+        assert!(trie.has_any_wildcard);
+        assert!(vec_eq(trie.find(["a", "b"]), vec![1, 3]));
+        assert!(trie.exist(["a", "c"]));
+        // 只有shrink_to_fit时才重新计算是否有wildcard
+        assert!(trie.remove(&parser.parse_tokens("a.*")?, &3));
+        assert!(trie.has_any_wildcard);
+        trie.shrink_to_fit();
+        assert!(!trie.has_any_wildcard);
+        assert_eq!(trie.find(["a", "b"]), vec![1]);
+        trie.insert(&parser.parse_tokens(">")?, 4);
+        assert!(vec_eq(trie.find(["a", "b"]), vec![1, 4]));
+        trie.clear();
+        assert!(!trie.has_any_wildcard);
+        Ok(())
+    }
+
+    #[cfg(feature = "hashbrown")]
+    #[test]
+    fn test_insert_prehashed() -> Result<(), CommonTokenError> {
+        use std::collections::hash_map::DefaultHasher;
+        use std::hash::{BuildHasher, BuildHasherDefault};
+
+        type State = BuildHasherDefault<DefaultHasher>;
+        let parser = CommonTokenParser::new('.', "*", ">");
+        let patterns = ["a.b", "a.*", "a.>", ">", "a.(b|c)"];
+        let mut normal = Trie::<String, 10, State>::new();
+        let mut prehashed = Trie::<String, 10, State>::new();
+        for i in 0..100 {
+            let value = format!("value-{}", i);
+            let hash = State::default().hash_one(&value);
+            for pattern in patterns.iter().skip(i % patterns.len()) {
+                let tokens = parser.parse_tokens(pattern)?;
+                normal.insert(&tokens, value.clone());
+                prehashed.insert_prehashed(&tokens, value.clone(), hash);
+                // 再次添加不做任何修改
+                prehashed.insert_prehashed(&tokens, value.clone(), hash);
+            }
+        }
+        for keys in [vec!["a", "b"], vec!["a", "c"], vec!["a", "b", "c"], vec!["x"]].iter() {
+            assert_eq!(sorted(normal.find(keys)), sorted(prehashed.find(keys)));
+        }
+        let tokens = parser.parse_tokens("a.b")?;
+        assert!(prehashed.remove(&tokens, &"value-0".to_string()));
+        assert!(!prehashed.snapshot().values_at(&tokens).contains(&"value-0".to_string()));
+        // 计数模式下重复添加增加次数
+        let value = "counted".to_string();
+        let hash = State::default().hash_one(&value);
+        prehashed.set_duplicate_mode(DuplicateMode::Count);
+        for tokens in [parser.parse_tokens("x")?, parser.parse_tokens("x.>")?].iter() {
+            prehashed.insert_prehashed(tokens, value.clone(), hash);
+            prehashed.insert_prehashed(tokens, value.clone(), hash);
+            assert_eq!(prehashed.count_of(tokens, &value), 2);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_debug_summary() -> Result<(), CommonTokenError> {
+        #[derive(Debug)]
+        struct Router<'a> {
+            routes: Trie<'a, u32, 10>,
+        }
+        let mut router = Router { routes: Trie::new() };
+        assert_eq!(
+            format!("{:?}", router.routes),
+            "Trie { nodes: 1, values: 0, depth: 0, cache_len: 0, cache_capacity: 10 }"
+        );
+        let parser = CommonTokenParser::new('.', "*", ">");
+        router.routes.insert(&parser.parse_tokens("a.b.c")?, 1);
+        router.routes.insert(&parser.parse_tokens("a.*")?, 2);
+        router.routes.insert(&parser.parse_tokens("a.>")?, 3);
+        router.routes.find(["a", "b"]);
+        assert_eq!(
+            format!("{:?}", router),
+            "Router { routes: Trie { nodes: 5, values: 3, depth: 3, cache_len: 1, cache_capacity: 10 } }"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_literal_wildcard_chars() -> Result<(), CommonTokenError> {
+        let mut trie = Trie::<_, 10>::new();
+        let parser = CommonTokenParser::new('.', "*", ">");
+        // 字面的`*`只能直接构造token来添加，或者使用wildcard字符不同的解析器
+        let literal = Tokens::from(vec![Token::from("a"), Token::from("*")]);
+        trie.insert(&literal, 1);
+        trie.insert(&CommonTokenParser::new('.', "+", "#").parse_tokens("a.*.>")?, 2);
+        trie.insert(&parser.parse_tokens("a.*")?, 3);
+        trie.insert(&parser.parse_tokens("a.b")?, 4);
+        // 字面的token是普通子结点，与wildcard分开保存
+        assert_eq!(trie.child_count(&parser.parse_tokens("a")?), Some(2));
+        // 查询的key都是字面的：`*`匹配字面的token，并且与其它key一样也被wildcard匹配
+        assert!(vec_eq(trie.find(["a", "*"]), vec![1, 3]));
+        assert!(vec_eq(trie.find(["a", "b"]), vec![3, 4]));
+        assert!(vec_eq(trie.find(["a", "*", ">"]), vec![2]));
+        assert!(trie.find(["a", "*", "x"]).is_empty());
+        // 只有字面的组，不进行wildcard匹配
+        assert_eq!(trie.snapshot().values_at(&literal), vec![1]);
+        assert!(trie.exist_exact(["a", "*"]));
+        assert!(!trie.exist_exact(["a", "c"]));
+        assert!(trie.remove(&literal, &1));
+        assert_eq!(trie.find(["a", "*"]), vec![3]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_group_limit() -> Result<(), CommonTokenError> {
+        let mut trie = Trie::<_, 10>::new();
+        let parser = CommonTokenParser::new('.', "*", ">");
+        let (exact, mwc) = (parser.parse_tokens("a.b")?, parser.parse_tokens("a.>")?);
+        trie.set_group_limit(2);
+        assert_eq!(trie.try_insert(&exact, 1), Ok(()));
+        assert_eq!(trie.try_insert(&exact, 2), Ok(()));
+        assert_eq!(trie.try_insert(&exact, 3), Err(Error::GroupFull));
+        // 组中已经有的值不算新添加的值
+        assert_eq!(trie.try_insert(&exact, 2), Ok(()));
+        trie.insert(&exact, 4);
+        assert!(vec_eq(trie.find(["a", "b"]), vec![1, 2]));
+        // 同一个结点的多层wildcard组单独计算上限
+        assert_eq!(trie.insert_values(&mwc, vec![5, 6, 7]), 2);
+        assert_eq!(trie.try_insert(&mwc, 8), Err(Error::GroupFull));
+        assert!(vec_eq(trie.find(["a", "b"]), vec![1, 2, 5, 6]));
+        // 移除之后又可以添加
+        assert!(trie.remove(&exact, &1));
+        assert_eq!(trie.try_insert(&exact, 3), Ok(()));
+        trie.set_group_limit(0);
+        assert_eq!(trie.try_insert(&parser.parse_tokens("x.y")?, 1), Err(Error::GroupFull));
+        assert_eq!(trie.node_count(), 3);
+        Ok(())
+    }
+
+    #[test]
+    fn test_find_limited() -> Result<(), CommonTokenError> {
+        let mut trie = Trie::<_, 10>::new();
+        let parser = CommonTokenParser::new('.', "*", ">");
+        trie.insert_values(&parser.parse_tokens("a.>")?, 0..100);
+        trie.insert_values(&parser.parse_tokens("a.*")?, 100..200);
+        trie.insert(&parser.parse_tokens("a.b")?, 200);
+        let all = trie.snapshot().find(["a", "b"]);
+        for limit in [0, 1, 50, 100, 150, 201, 500].iter() {
+            let limited = trie.find_limited(["a", "b"], *limit);
+            assert_eq!(limited.len(), all.len().min(*limit));
+            assert!(limited.iter().all(|v| all.contains(v)));
+            // 同一棵树返回相同的值
+            assert_eq!(limited, trie.find_limited(["a", "b"], *limit));
+        }
+        assert!(trie.find_limited(["b"], 10).is_empty());
+        trie.set_mwc_includes_prefix(true);
+        assert_eq!(trie.find_limited(["a"], 10).len(), 10);
+        Ok(())
+    }
+
+    #[test]
+    fn test_find_traced() -> Result<(), CommonTokenError> {
+        let mut trie = Trie::<_, 10>::new();
+        let parser = CommonTokenParser::new('.', "*", ">");
+        trie.insert(&parser.parse_tokens("a.*")?, 1);
+        assert_eq!(trie.find_traced(["a", "b"]), (vec![1], CacheOutcome::Miss));
+        assert_eq!(trie.find_traced(["a", "b"]), (vec![1], CacheOutcome::Hit));
+        assert_eq!(trie.find_traced(["a", "c"]), (vec![1], CacheOutcome::Miss));
+        // 添加之后与之匹配的缓存结果过期
+        trie.insert(&parser.parse_tokens("a.b")?, 2);
+        let (values, outcome) = trie.find_traced(["a", "b"]);
+        assert!(vec_eq(values, vec![1, 2]));
+        assert_eq!(outcome, CacheOutcome::Miss);
+        assert_eq!(trie.find_traced(["a", "c"]).1, CacheOutcome::Hit);
+        // 没有缓存时每次查询都不命中
+        let mut uncached = Trie::<_, 0>::new();
+        uncached.insert(&parser.parse_tokens("a")?, 1);
+        assert_eq!(uncached.find_traced(["a"]), (vec![1], CacheOutcome::Miss));
+        assert_eq!(uncached.find_traced(["a"]), (vec![1], CacheOutcome::Miss));
+        Ok(())
+    }
+
+    #[test]
+    fn test_reverse_trie() {
+        let tokens = |v: Vec<Token<'static>>| Tokens::from(v);
+        let mut trie = ReverseTrie::<_, 10>::new();
+        trie.insert(&tokens(vec![Token::MultiWildcard, "example".into(), "com".into()]), 1);
+        trie.insert(&tokens(vec![Token::OneWildcard(None), "example".into(), "com".into()]), 2);
+        trie.insert(&tokens(vec!["www".into(), "example".into(), "com".into()]), 3);
+        trie.insert(&tokens(vec![Token::MultiWildcard, "org".into()]), 4);
+        assert!(vec_eq(trie.find(["www", "example", "com"]), vec![1, 2, 3]));
+        assert!(vec_eq(trie.find(["api", "example", "com"]), vec![1, 2]));
+        // 多层wildcard匹配前面的一个或者多个token
+        assert!(vec_eq(trie.find(["a", "b", "example", "com"]), vec![1]));
+        assert!(trie.find(["example", "com"]).is_empty());
+        assert!(vec_eq(trie.find(["example", "org"]), vec![4]));
+        assert!(!trie.exist(["example", "net"]));
+        assert!(trie.exist(["x", "org"]));
+        assert!(vec_eq(trie.matching_patterns(["api", "example", "com"]), vec![
+            tokens(vec![Token::MultiWildcard, "example".into(), "com".into()]),
+            tokens(vec![Token::OneWildcard(None), "example".into(), "com".into()]),
+        ]));
+        // 多层wildcard必须在最前面，错误中的位置是原来的tokens中的位置
+        assert_eq!(
+            trie.try_insert(&tokens(vec!["a".into(), Token::MultiWildcard, "com".into()]), 5),
+            Err(Error::TokenAfterMwc(0))
+        );
+        assert_eq!(
+            trie.try_insert(&tokens(vec![Token::OneOf(vec![]), "com".into()]), 5),
+            Err(Error::EmptyToken(0))
+        );
+        assert!(trie.remove(&tokens(vec![Token::MultiWildcard, "example".into(), "com".into()]), &1));
+        assert!(vec_eq(trie.find(["a", "example", "com"]), vec![2]));
+        assert_eq!(trie.as_trie().matching_patterns(["com", "example", "a"]).len(), 1);
+    }
+
+    #[test]
+    fn test_freeze() -> Result<(), Box<dyn std::error::Error>> {
+        fn assert_send_sync<T: Send + Sync>(_: &T) {}
+        let parser = CommonTokenParser::new('.', "*", ">");
+        let mut trie = Trie::<_, 10>::new();
+        trie.insert(&parser.parse_tokens("a.b")?, 1);
+        trie.insert(&parser.parse_tokens("a.*")?, 2);
+        let alt = Tokens::builder().normal("a").token(Token::OneOf(vec!["b".into(), "c".into()])).token(Token::NumRange(1, 5)).build()?;
+        trie.insert(&alt, 3);
+        trie.insert(&parser.parse_tokens("a.>")?, 4);
+        let frozen = trie.freeze();
+        assert_send_sync(&frozen);
+        // 快照中看不到冻结之后的修改
+        trie.insert(&parser.parse_tokens("a.b")?, 5);
+        trie.remove(&parser.parse_tokens("a.*")?, &2);
+        assert!(vec_eq(frozen.find(["a", "b"]), vec![1, 2, 4]));
+        assert!(vec_eq(trie.find(["a", "b"]), vec![1, 4, 5]));
+        assert!(vec_eq(frozen.find(["a", "c", "3"]), vec![3, 4]));
+        assert_eq!(frozen.count(["a", "b", "9"]), 1);
+        assert!(frozen.exist(["a", "x"]));
+        assert!(!frozen.exist(["b"]));
+        assert_eq!(frozen.view().values_at(&alt), vec![3]);
+        let shared = frozen.clone();
+        let found = std::thread::spawn(move || shared.find(["a", "c"])).join().unwrap();
+        assert!(vec_eq(found, vec![2, 4]));
+        Ok(())
+    }
+
+    #[test]
+    fn test_rename_segment() -> Result<(), CommonTokenError> {
+        use std::sync::{Arc, Mutex};
+
+        let parser = CommonTokenParser::new('.', "*", ">");
+        // 简单的移动
+        let mut trie = Trie::<_, 10>::new();
+        trie.insert(&parser.parse_tokens("api.v1.users")?, 1);
+        trie.insert(&parser.parse_tokens("api.v1.>")?, 2);
+        trie.insert(&parser.parse_tokens("*.v1.items")?, 3);
+        assert!(vec_eq(trie.find(["api", "v1", "users"]), vec![1, 2]));
+        assert_eq!(trie.rename_segment(1, "v1", "v2"), 2);
+        assert_eq!(trie.cache_len(), 0);
+        assert!(trie.find(["api", "v1", "users"]).is_empty());
+        assert!(vec_eq(trie.find(["api", "v2", "users"]), vec![1, 2]));
+        assert!(vec_eq(trie.find(["x", "v2", "items"]), vec![3]));
+        assert_eq!(trie.rename_segment(1, "v1", "v2"), 0);
+        assert_eq!(trie.rename_segment(0, "v2", "v2"), 0);
+        // 合并到已经存在的子树中
+        let mut trie = Trie::<_, 10>::new();
+        let events = Arc::new(Mutex::new(vec![]));
+        let sink = events.clone();
+        trie.insert(&parser.parse_tokens("a.old.b")?, 1);
+        trie.insert(&parser.parse_tokens("a.old.>")?, 2);
+        trie.insert(&parser.parse_tokens("a.old.c.d")?, 3);
+        trie.insert(&parser.parse_tokens("a.new.b")?, 1);
+        trie.insert(&parser.parse_tokens("a.new.b")?, 4);
+        trie.insert(&parser.parse_tokens("a.new.*")?, 5);
+        trie.set_observer(Box::new(move |e| sink.lock().unwrap().push(e)));
+        assert_eq!(trie.rename_segment(1, "old", "new"), 1);
+        assert!(vec_eq(trie.snapshot().values_at(&parser.parse_tokens("a.new.b")?), vec![1, 4]));
+        assert!(vec_eq(trie.find(["a", "new", "b"]), vec![1, 2, 4, 5]));
+        assert!(vec_eq(trie.find(["a", "new", "c", "d"]), vec![2, 3]));
+        assert!(!trie.exist(["a", "old", "b"]));
+        assert_eq!(trie.node_count(), 7);
+        let events = events.lock().unwrap();
+        assert_eq!(events.len(), 5);
+        assert!(events.contains(&TrieEvent::Removed { pattern: parser.parse_tokens("a.old.b")?, value: 1 }));
+        assert!(!events.contains(&TrieEvent::Inserted { pattern: parser.parse_tokens("a.new.b")?, value: 1 }));
+        assert!(events.contains(&TrieEvent::Inserted { pattern: parser.parse_tokens("a.new.>")?, value: 2 }));
+        assert!(events.contains(&TrieEvent::Inserted { pattern: parser.parse_tokens("a.new.c.d")?, value: 3 }));
+        Ok(())
+    }
+
+    #[test]
+    fn test_deep_drop() {
+        const DEPTH: usize = 100_000;
+        // 普通子结点和单层wildcard子结点交替组成的链
+        let tokens: Tokens = (0..DEPTH)
+            .map(|i| if i % 2 == 0 { Token::from("a") } else { Token::OneWildcard(None) })
+            .collect::<Vec<_>>()
+            .into();
+        let mut trie = Trie::<_, 10>::new();
+        trie.insert(&tokens, 1);
+        assert_eq!(trie.node_count(), DEPTH + 1);
+        // 快照和合并也不会递归
+        let frozen = trie.freeze();
+        let mut copy = tokens.0.clone();
+        copy[0] = Token::from("b");
+        trie.insert(&copy.into(), 2);
+        assert_eq!(trie.rename_segment(0, "b", "a"), 1);
+        assert_eq!(trie.node_count(), DEPTH + 1);
+        assert!(vec_eq(trie.snapshot().values_at(&tokens), vec![1, 2]));
+        drop(trie);
+        assert_eq!(frozen.view().values_at(&tokens), vec![1]);
+        drop(frozen);
+    }
+
+    #[test]
+    fn test_find_pattern() -> Result<(), CommonTokenError> {
+        let parser = CommonTokenParser::new('.', "*", ">");
+        let stored = ["a.b", "a.*", "a.>", "a.b.c", "a.*.c", "a", "a.[1-9]", "*.>"];
+        let mut trie = Trie::<_, 10>::new();
+        for (i, pattern) in stored.iter().enumerate() {
+            trie.insert(&parser.parse_tokens(pattern)?, i);
+        }
+        // 每一行是query，每一列对应stored中的模式
+        let table = [
+            ("a.b",   [1, 1, 1, 0, 0, 0, 0, 1]),
+            ("a.*",   [1, 1, 1, 0, 0, 0, 1, 1]),
+            ("a.>",   [1, 1, 1, 1, 1, 0, 1, 1]),
+            ("a",     [0, 0, 0, 0, 0, 1, 0, 0]),
+            ("*",     [0, 0, 0, 0, 0, 1, 0, 0]),
+            ("*.c",   [0, 1, 1, 0, 0, 0, 0, 1]),
+            ("*.*.c", [0, 0, 1, 1, 1, 0, 0, 1]),
+            (">",     [1, 1, 1, 1, 1, 1, 1, 1]),
+            ("a.5",   [0, 1, 1, 0, 0, 0, 1, 1]),
+            ("b.>",   [0, 0, 0, 0, 0, 0, 0, 1]),
+        ];
+        for (query, row) in table.iter() {
+            let query = parser.parse_tokens(query)?;
+            let expected: Vec<_> = row.iter().enumerate().filter(|(_, &m)| m == 1).map(|(i, _)| i).collect();
+            assert_eq!(sorted(trie.find_pattern(&query)), expected, "{:?}", query);
+            for (i, pattern) in stored.iter().enumerate() {
+                assert_eq!(patterns_overlap(&query, &parser.parse_tokens(pattern)?), row[i] == 1);
+            }
+        }
+        // 多层wildcard组也匹配其前缀本身时，`a`也与`a.>`和`*.>`有交集
+        trie.set_mwc_includes_prefix(true);
+        assert_eq!(sorted(trie.find_pattern(&parser.parse_tokens("a")?)), vec![2, 5, 7]);
+        assert_eq!(sorted(trie.find_pattern(&parser.parse_tokens("*")?)), vec![2, 5, 7]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_group_mut() -> Result<(), CommonTokenError> {
+        let parser = CommonTokenParser::new('.', "*", ">");
+        let mut trie = Trie::<_, 10>::new();
+        trie.insert(&parser.parse_tokens("a.b")?, 1);
+        trie.insert(&parser.parse_tokens("a.>")?, 2);
+        assert!(vec_eq(trie.find(["a", "b"]), vec![1, 2]));
+        assert!(vec_eq(trie.find(["a", "c"]), vec![2]));
+        {
+            let mut group = trie.group_mut(&parser.parse_tokens("a.b")?);
+            assert!(group.contains(&1));
+            assert!(group.remove(&1));
+            assert!(group.insert(3).is_ok());
+        }
+        // 只有与a.b匹配的缓存结果过期
+        assert_eq!(trie.cache_len(), 1);
+        assert!(vec_eq(trie.find(["a", "b"]), vec![2, 3]));
+        trie.group_mut(&parser.parse_tokens("a.>")?).retain(|&v| v != 2);
+        assert_eq!(trie.cache_len(), 0);
+        assert!(vec_eq(trie.find(["a", "b"]), vec![3]));
+        assert!(trie.find(["a", "c"]).is_empty());
+        // 组不存在时创建
+        assert!(trie.group_mut(&parser.parse_tokens("x.*")?).insert(4).is_ok());
+        assert_eq!(trie.find(["x", "y"]), vec![4]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_group_mut_bookkeeping() -> Result<(), CommonTokenError> {
+        let parser = CommonTokenParser::new('.', "*", ">");
+        let ab = parser.parse_tokens("a.b")?;
+        let mut trie = Trie::<_, 10>::new();
+        trie.set_duplicate_mode(DuplicateMode::Count);
+        trie.set_group_limit(2);
+        trie.enable_op_log();
+        for _ in 0..3 {
+            trie.insert(&ab, 1);
+        }
+        trie.insert_with_ttl(&ab, 2, Instant::now() + std::time::Duration::from_secs(60));
+        {
+            let mut group = trie.group_mut(&ab);
+            // 移除值本身，而不只是减少次数
+            assert!(group.remove(&1));
+            assert!(!group.contains(&1));
+            assert!(group.insert(3).is_ok());
+            assert_eq!(group.insert(4), Err(Error::GroupFull));
+            assert_eq!(group.retain(|&v| v != 2), 1);
+            assert_eq!(group.len(), 1);
+        }
+        trie.insert(&ab, 1);
+        assert_eq!(trie.count_of(&ab, &1), 1);
+        // 移除的值不再过期
+        assert!(trie.remove(&ab, &3));
+        trie.insert(&ab, 2);
+        assert_eq!(trie.expire(Instant::now() + std::time::Duration::from_secs(120)), 0);
+        assert!(vec_eq(trie.find(["a", "b"]), vec![1, 2]));
+        let log = trie.take_op_log().unwrap();
+        let ops = &log.ops()[log.len() - 6..];
+        assert_eq!(ops[0], Op::Remove { pattern: ab.to_static(), value: 1 });
+        assert_eq!(ops[1], Op::Insert { pattern: ab.to_static(), value: 3 });
+        assert_eq!(ops[2], Op::Remove { pattern: ab.to_static(), value: 2 });
+        Ok(())
+    }
+
+    #[test]
+    fn test_empty_subject() -> Result<(), CommonTokenError> {
+        let keys: [&str; 0] = [];
+        // 默认空的subject是一个空token
+        let parser = CommonTokenParser::new('.', "*", ">");
+        let mut trie = Trie::<_, 10>::new();
+        trie.insert(&parser.parse_tokens("")?, 1);
+        assert_eq!(trie.find([""]), vec![1]);
+        assert!(trie.find(keys).is_empty());
+        // 空的subject没有token时，值保存在根结点上
+        let parser = parser.treat_empty_as_no_tokens(true);
+        let mut trie = Trie::<_, 10>::new();
+        trie.insert(&parser.parse_tokens("")?, 2);
+        assert_eq!(trie.find(keys), vec![2]);
+        assert!(trie.find([""]).is_empty());
+        assert_eq!(trie.node_count(), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn test_find_sorted() -> Result<(), CommonTokenError> {
+        let parser = CommonTokenParser::new('.', "*", ">");
+        let mut trie = Trie::<_, 10>::new();
+        for (i, pattern) in ["a.b", "a.*", "a.>", "*.b"].iter().enumerate().rev() {
+            trie.insert(&parser.parse_tokens(pattern)?, i * 10);
+            trie.insert(&parser.parse_tokens(pattern)?, i);
+        }
+        assert_eq!(trie.find_sorted(["a", "b"]), vec![0, 1, 2, 3, 10, 20, 30]);
+        assert_eq!(trie.find_sorted(["a", "c"]), vec![1, 2, 10, 20]);
+        assert!(trie.find_sorted(["b"]).is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_subscription_stats() -> Result<(), CommonTokenError> {
+        let parser = CommonTokenParser::new('.', "*", ">");
+        let mut trie = Trie::<_, 10>::new();
+        assert_eq!(trie.subscription_stats(), SubStats::default());
+        trie.insert(&parser.parse_tokens("a.b")?, 1);
+        trie.insert(&parser.parse_tokens("a.b")?, 2);
+        trie.insert(&parser.parse_tokens("a")?, 3);
+        trie.insert(&parser.parse_tokens("a.*")?, 4);
+        trie.insert(&parser.parse_tokens("*.b.c")?, 5);
+        trie.insert(&parser.parse_tokens("a.[1-9]")?, 6);
+        trie.insert(&parser.parse_tokens("a.>")?, 7);
+        trie.insert(&parser.parse_tokens("*.>")?, 1);
+        assert_eq!(trie.subscription_stats(), SubStats { exact: 3, one_wildcard: 3, multi_wildcard: 2 });
+        Ok(())
+    }
+
+    #[test]
+    fn test_insert_default() -> Result<(), CommonTokenError> {
+        let parser = CommonTokenParser::new('.', "*", ">");
+        let mut trie = Trie::<u32, 10>::new();
+        let tokens = parser.parse_tokens("a.*")?;
+        *trie.insert_default(&tokens).unwrap() += 1;
+        assert_eq!(trie.find(["a", "b"]), vec![1]);
+        // 之前的查询结果已经过期
+        *trie.insert_default(&tokens).unwrap() += 2;
+        assert_eq!(trie.find(["a", "b"]), vec![3]);
+        assert_eq!(*trie.insert_default(&parser.parse_tokens("x")?).unwrap(), 0);
+        assert_eq!(trie.find(["x"]), vec![0]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_insert_default_multiple_values() -> Result<(), CommonTokenError> {
+        let parser = CommonTokenParser::new('.', "*", ">");
+        let mut trie = Trie::<u32, 10>::new();
+        let tokens = parser.parse_tokens("a")?;
+        trie.insert(&tokens, 1);
+        trie.insert(&tokens, 2);
+        // 组中有多个值时不做任何修改
+        assert!(trie.insert_default(&tokens).is_none());
+        assert!(vec_eq(trie.find(["a"]), vec![1, 2]));
+        trie.set_group_limit(0);
+        assert!(trie.insert_default(&parser.parse_tokens("x")?).is_none());
+        assert!(trie.find(["x"]).is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_visit() -> Result<(), CommonTokenError> {
+        let parser = CommonTokenParser::new('.', "*", ">");
+        let mut trie = Trie::<_, 10>::new();
+        let patterns = ["a.b", "a.*", "a.>", "a.(b|c).d", "[1-5]", "a.b"];
+        for (i, pattern) in patterns.iter().enumerate() {
+            trie.insert(&parser.parse_tokens(pattern)?, i);
+        }
+        let mut visited = Vec::new();
+        trie.visit(|tokens, &v| visited.push((tokens.clone(), v)));
+        let expected: Vec<_> = patterns.iter().enumerate()
+            .map(|(i, p)| Ok((parser.parse_tokens(p)?, i)))
+            .collect::<Result<_, CommonTokenError>>()?;
+        assert!(vec_eq(visited, expected));
+        let mut total = 0;
+        trie.visit(|_, v| total += v);
+        assert_eq!(total, 15);
+        Ok(())
+    }
+
+    #[test]
+    fn test_cache_eviction_order() -> Result<(), CommonTokenError> {
+        let parser = CommonTokenParser::new('.', "*", ">");
+        let mut trie = Trie::<_, 3>::new();
+        for key in ["v", "w", "x", "y", "z"].iter() {
+            trie.insert(&parser.parse_tokens(key)?, key.to_string());
+        }
+        for key in ["x", "y", "z", "x"].iter() {
+            trie.find([*key]);
+        }
+        // 失效的结果不再参与淘汰
+        trie.insert(&parser.parse_tokens("y")?, "y2".to_string());
+        assert_eq!(trie.cache_len(), 2);
+        assert_eq!(trie.find_traced(["w"]).1, CacheOutcome::Miss);
+        assert_eq!(trie.cache_len(), 3);
+        // 已满时淘汰最久没有使用的z，x刚刚被命中，不会被淘汰
+        assert_eq!(trie.find_traced(["v"]).1, CacheOutcome::Miss);
+        assert_eq!(trie.cache_len(), 3);
+        assert_eq!(trie.find_traced(["x"]).1, CacheOutcome::Hit);
+        assert_eq!(trie.find_traced(["w"]).1, CacheOutcome::Hit);
+        // 重新缓存z时淘汰的是v
+        assert_eq!(trie.find_traced(["z"]).1, CacheOutcome::Miss);
+        assert_eq!(trie.find_traced(["x"]).1, CacheOutcome::Hit);
+        assert_eq!(trie.find_traced(["v"]).1, CacheOutcome::Miss);
+        Ok(())
+    }
+
+    #[test]
+    fn test_cache_buckets() -> Result<(), CommonTokenError> {
+        let parser = CommonTokenParser::new('.', "*", ">");
+        let mut trie = Trie::<_, 10>::new();
+        trie.insert(&parser.parse_tokens("a.b")?, 1);
+        trie.insert(&parser.parse_tokens("b.b")?, 2);
+        trie.insert(&parser.parse_tokens("*.b")?, 3);
+        let cached = |trie: &mut Trie<'static, i32, 10>| {
+            for keys in [["a", "b"], ["b", "b"], ["c", "b"], ["a", "c"]].iter() {
+                trie.find(keys);
+            }
+        };
+        cached(&mut trie);
+        assert_eq!(trie.cache_len(), 4);
+        // 以普通token开头的模式只影响第一个key相同的结果
+        trie.insert(&parser.parse_tokens("a.b")?, 4);
+        assert_eq!(trie.cache_len(), 3);
+        assert!(vec_eq(trie.find(["a", "b"]), vec![1, 3, 4]));
+        trie.insert(&parser.parse_tokens("(b|c).b")?, 5);
+        assert_eq!(trie.cache_len(), 2);
+        assert!(vec_eq(trie.find(["c", "b"]), vec![3, 5]));
+        // 以wildcard开头的模式可能影响所有的结果
+        cached(&mut trie);
+        trie.insert(&parser.parse_tokens("*.c")?, 6);
+        assert_eq!(trie.cache_len(), 3);
+        assert_eq!(trie.find(["a", "c"]), vec![6]);
+        trie.insert(&parser.parse_tokens(">")?, 7);
+        assert_eq!(trie.cache_len(), 0);
+        // 空的前缀移除所有的结果
+        cached(&mut trie);
+        trie.remove_subtree(&Tokens::default());
+        assert_eq!(trie.cache_len(), 0);
+        assert!(trie.find(["a", "b"]).is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_to_pairs() -> Result<(), Box<dyn std::error::Error>> {
+        let parser = CommonTokenParser::new('.', "*", ">");
+        let mut trie = Trie::<_, 10>::new();
+        trie.insert(&parser.parse_tokens("a.b")?, 1);
+        trie.insert(&parser.parse_tokens("a.*")?, 2);
+        trie.insert(&parser.parse_tokens("a.(b|c).[1-5]")?, 3);
+        trie.insert(&parser.parse_tokens("a.>")?, 4);
+        trie.insert(&parser.parse_tokens("a.b")?, 5);
+        let pairs = trie.to_pairs('/', "+", "#");
+        assert!(vec_eq(pairs.clone(), vec![
+            ("a/b".to_string(), 1),
+            ("a/+".to_string(), 2),
+            ("a/(b|c)/[1-5]".to_string(), 3),
+            ("a/#".to_string(), 4),
+            ("a/b".to_string(), 5),
+        ]));
+        // 导出的文本可以重新加载
+        let text: String = pairs.iter().map(|(p, v)| format!("{}\t{}\n", p, v)).collect();
+        let mut loaded = Trie::<_, 10>::new();
+        let parser = CommonTokenParser::mqtt().extended_syntax(true);
+        assert_eq!(loaded.load_lines(&text, &parser, |v| v.parse().unwrap())?, 5);
+        assert!(vec_eq(loaded.to_pairs('.', "*", ">"), trie.to_pairs('.', "*", ">")));
+        Ok(())
+    }
+
+    #[test]
+    fn test_non_clone_values() -> Result<(), Box<dyn std::error::Error>> {
+        // 没有实现Clone的值，例如持有连接的句柄
+        #[derive(Debug, PartialEq, Eq, Hash)]
+        struct Handle(u32);
+
+        let parser = CommonTokenParser::new('.', "*", ">");
+        let mut trie = Trie::<_, 10>::new();
+        trie.insert(&parser.parse_tokens("a.b")?, Handle(1));
+        trie.insert(&parser.parse_tokens("a.*")?, Handle(2));
+        trie.insert(&parser.parse_tokens("a.>")?, Handle(3));
+        assert!(trie.exist(["a", "b"]));
+        assert!(!trie.exist(["b"]));
+        assert_eq!(trie.get_single(&parser.parse_tokens("a.b")?), Some(&Handle(1)));
+        assert!(trie.any_match(["a", "c"]).is_some());
+        let mut seen = Vec::new();
+        trie.visit(|_, v| seen.push(v.0));
+        assert!(vec_eq(seen, vec![1, 2, 3]));
+        assert_eq!(trie.find_grouped(["a", "b"]).len(), 3);
+        // 移除时可以取回值的所有权
+        assert_eq!(trie.take(&parser.parse_tokens("a.*")?, &Handle(2)), Some(Handle(2)));
+        assert!(trie.remove(&parser.parse_tokens("a.>")?, &Handle(3)));
+        assert_eq!(trie.find_grouped(["a", "b"]).len(), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn test_cache_mode() -> Result<(), CommonTokenError> {
+        let parser = CommonTokenParser::new('.', "*", ">");
+        let mut trie = Trie::<_, 10>::new();
+        trie.insert(&parser.parse_tokens("a.b")?, 1);
+        trie.insert(&parser.parse_tokens("a.*")?, 2);
+        trie.insert(&parser.parse_tokens("a.>")?, 3);
+        trie.insert(&parser.parse_tokens("a.(b|c)")?, 4);
+        trie.find(["a", "b"]);
+        assert_eq!(trie.cache_len(), 1);
+        // 改变模式时清空缓存，相同的模式不清空
+        trie.set_cache_mode(CacheMode::Patterns);
+        assert_eq!(trie.cache_len(), 0);
+        trie.find(["a", "b"]);
+        trie.set_cache_mode(CacheMode::Patterns);
+        assert_eq!(trie.cache_len(), 1);
+        let (values, outcome) = trie.find_traced(["a", "b"]);
+        assert_eq!(outcome, CacheOutcome::Hit);
+        assert!(vec_eq(values, vec![1, 2, 3, 4]));
+        // 与不经过缓存的查询结果相同
+        for keys in [vec!["a"], vec!["a", "c"], vec!["a", "b", "c"], vec!["b"]].iter() {
+            let expected = trie.snapshot().find(keys);
+            assert!(vec_eq(trie.find_traced(keys).0, expected.clone()));
+            assert!(vec_eq(trie.find_traced(keys).0, expected));
+        }
+        // 修改之后不会查询到过期的结果
+        trie.insert(&parser.parse_tokens("a.b")?, 5);
+        assert!(vec_eq(trie.find(["a", "b"]), vec![1, 2, 3, 4, 5]));
+        assert!(trie.remove(&parser.parse_tokens("a.*")?, &2));
+        assert!(vec_eq(trie.find(["a", "b"]), vec![1, 3, 4, 5]));
+        // 移除的值不在缓存中，缓存的组的tokens仍然有效
+        assert_eq!(trie.remove_matching(&["a", "b"], &3), 1);
+        assert_eq!(trie.find_traced(["a", "b"]).1, CacheOutcome::Hit);
+        assert!(vec_eq(trie.find(["a", "b"]), vec![1, 4, 5]));
+        Ok(())
+    }
+
+    #[test]
+    fn test_duplicate_mode() -> Result<(), CommonTokenError> {
+        let parser = CommonTokenParser::new('.', "*", ">");
+        let ab = parser.parse_tokens("a.b")?;
+        let mwc = parser.parse_tokens("a.>")?;
+        // 默认忽略重复的值
+        let mut trie = Trie::<_, 10>::new();
+        trie.insert(&ab, 1);
+        trie.insert(&ab, 1);
+        assert_eq!(trie.count_of(&ab, &1), 1);
+        assert!(trie.remove(&ab, &1));
+        assert_eq!(trie.count_of(&ab, &1), 0);
+
+        let mut trie = Trie::<_, 10>::new();
+        trie.set_duplicate_mode(DuplicateMode::Count);
+        trie.insert(&ab, 1);
+        trie.insert(&ab, 1);
+        trie.try_insert(&ab, 1).unwrap();
+        assert_eq!(trie.insert_values(&mwc, vec![2, 2, 3]), 2);
+        assert_eq!(trie.count_of(&ab, &1), 3);
+        assert_eq!(trie.count_of(&mwc, &2), 2);
+        assert_eq!(trie.count_of(&mwc, &3), 1);
+        assert_eq!(trie.count_of(&mwc, &1), 0);
+        assert_eq!(trie.count_of(&parser.parse_tokens("a.c")?, &1), 0);
+        assert!(vec_eq(trie.find(["a", "b"]), vec![1, 2, 3]));
+        // 每次移除减少一次，次数为0时才从组中移除
+        assert!(trie.remove(&ab, &1));
+        assert!(trie.remove(&ab, &1));
+        assert_eq!(trie.count_of(&ab, &1), 1);
+        assert!(vec_eq(trie.find(["a", "b"]), vec![1, 2, 3]));
+        assert!(trie.remove(&ab, &1));
+        assert_eq!(trie.count_of(&ab, &1), 0);
+        assert!(!trie.remove(&ab, &1));
+        assert!(vec_eq(trie.find(["a", "b"]), vec![2, 3]));
+        // 其它的移除方法直接移除值，之后重新添加的次数从1开始
+        assert!(trie.remove_all(&mwc));
+        trie.insert(&mwc, 2);
+        assert_eq!(trie.count_of(&mwc, &2), 1);
+        // 合并子树时次数相加
+        trie.insert(&parser.parse_tokens("a.c")?, 4);
+        trie.insert(&parser.parse_tokens("a.d")?, 4);
+        trie.insert(&parser.parse_tokens("a.d")?, 4);
+        assert_eq!(trie.rename_segment(1, "c", "d"), 1);
+        assert_eq!(trie.count_of(&parser.parse_tokens("a.d")?, &4), 3);
+        Ok(())
+    }
+
+    #[test]
+    fn test_duplicate_mode_op_log() -> Result<(), CommonTokenError> {
+        let parser = CommonTokenParser::new('.', "*", ">");
+        let ab = parser.parse_tokens("a.b")?;
+        let mut trie = Trie::<_, 10>::new();
+        trie.set_duplicate_mode(DuplicateMode::Count);
+        trie.enable_op_log();
+        trie.insert(&ab, 1);
+        trie.insert(&ab, 1);
+        assert_eq!(trie.insert_values(&ab, vec![1, 2]), 1);
+        assert!(trie.remove(&ab, &1));
+        // 次数的每次增减都被记录
+        let log = trie.op_log().unwrap().clone();
+        assert_eq!(log.len(), 5);
+        assert_eq!(log.ops()[1], Op::Insert { pattern: ab.to_static(), value: 1 });
+        assert_eq!(log.ops()[4], Op::Remove { pattern: ab.to_static(), value: 1 });
+        // 重放之后次数相同
+        let mut replayed = Trie::<_, 10>::new();
+        replayed.set_duplicate_mode(DuplicateMode::Count);
+        replayed.replay(&log);
+        assert_eq!(replayed.count_of(&ab, &1), 2);
+        assert_eq!(replayed.count_of(&ab, &2), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn test_duplicate_mode_replay() -> Result<(), CommonTokenError> {
+        use std::time::Duration;
+        let parser = CommonTokenParser::new('.', "*", ">");
+        let ab = parser.parse_tokens("a.b")?;
+        let patterns = [ab.clone(), parser.parse_tokens("a.>")?, parser.parse_tokens("a.c")?, parser.parse_tokens("x.y")?];
+        // 一次移除值的所有次数的方法，重放之后次数也要相同
+        type Removal = for<'t> fn(&mut Trie<'t, i32, 10>, &Tokens<'t>);
+        let removals: Vec<(&str, Removal)> = vec![
+            ("take", |trie, ab| assert_eq!(trie.take(ab, &1), Some(1))),
+            ("remove_all", |trie, ab| assert!(trie.remove_all(ab))),
+            ("remove_if", |trie, ab| assert!(trie.remove_if(ab, &1, |_| true))),
+            ("remove_matching", |trie, _| assert_eq!(trie.remove_matching(&["a", "b"], &1), 2)),
+            ("remove_subtree", |trie, _| assert_eq!(trie.remove_subtree(&Tokens::from(vec![Token::from("a")])), 4)),
+            ("retain_with_pattern", |trie, _| assert_eq!(trie.retain_with_pattern(|_, v| *v != 1), 3)),
+            ("expire", |trie, _| assert_eq!(trie.expire(Instant::now() + Duration::from_secs(120)), 1)),
+            ("update", |trie, ab| assert!(trie.update(ab, &1, |v| *v = 3))),
+            ("rename_segment", |trie, _| assert_eq!(trie.rename_segment(1, "b", "c"), 1)),
+        ];
+        for (name, removal) in removals {
+            let mut trie = Trie::<_, 10>::new();
+            trie.set_duplicate_mode(DuplicateMode::Count);
+            trie.enable_op_log();
+            for pattern in patterns[..2].iter().chain(&patterns[3..]) {
+                trie.insert(pattern, 1);
+                trie.insert(pattern, 1);
+                trie.insert(pattern, 2);
+            }
+            trie.insert_with_ttl(&ab, 1, Instant::now() + Duration::from_secs(60));
+            removal(&mut trie, &ab);
+            let mut replayed = Trie::<_, 10>::new();
+            replayed.set_duplicate_mode(DuplicateMode::Count);
+            replayed.replay(trie.op_log().unwrap());
+            for pattern in patterns.iter() {
+                for value in 1..=3 {
+                    assert_eq!(replayed.count_of(pattern, &value), trie.count_of(pattern, &value), "{} {:?} {}", name, pattern, value);
+                }
+            }
+            assert!(vec_eq(replayed.find(["a", "b"]), trie.find(["a", "b"])), "{}", name);
+        }
+
+        // 修改后的值保留原来的次数
+        let mut trie = Trie::<_, 10>::new();
+        trie.set_duplicate_mode(DuplicateMode::Count);
+        trie.insert(&ab, 1);
+        trie.insert(&ab, 1);
+        assert!(trie.update(&ab, &1, |v| *v = 2));
+        assert_eq!(trie.count_of(&ab, &2), 2);
+        assert!(trie.remove(&ab, &2));
+        assert_eq!(trie.find(["a", "b"]), vec![2]);
+        // 过期时间也保留
+        trie.insert_with_ttl(&ab, 5, Instant::now() + Duration::from_secs(60));
+        assert!(trie.update(&ab, &5, |v| *v = 6));
+        assert_eq!(trie.expire(Instant::now() + Duration::from_secs(120)), 1);
+        assert_eq!(trie.find(["a", "b"]), vec![2]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_find_subject() -> Result<(), CommonTokenError> {
+        let parser = CommonTokenParser::new('.', "*", ">");
+        let mut trie = Trie::<_, 10>::new();
+        trie.insert(&parser.parse_tokens("a.b")?, 1);
+        trie.insert(&parser.parse_tokens("a.*")?, 2);
+        trie.insert(&parser.parse_tokens("a.>")?, 3);
+        trie.insert(&Tokens::from(vec![Token::from("a"), Token::from("")]), 4);
+        trie.insert(&Tokens::from(vec![Token::from("")]), 5);
+        assert!(vec_eq(trie.find_subject("a.b", '.'), vec![1, 2, 3]));
+        assert!(vec_eq(trie.find_subject("a/b", '/'), vec![1, 2, 3]));
+        assert_eq!(trie.find_traced(["a", "b"]).1, CacheOutcome::Hit);
+        // 查询中的wildcard只是字面的key
+        assert!(vec_eq(trie.find_subject("a.*", '.'), vec![2, 3]));
+        assert!(vec_eq(trie.find_subject("a.b.c", '.'), vec![3]));
+        // 末尾的分隔符分割出空的key
+        assert!(vec_eq(trie.find_subject("a.", '.'), vec![2, 3, 4]));
+        assert!(vec_eq(trie.find_subject("", '.'), vec![5]));
+        assert!(trie.find_subject("b", '.').is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_node_info() -> Result<(), CommonTokenError> {
+        let parser = CommonTokenParser::new('.', "*", ">");
+        let mut trie = Trie::<_, 10>::new();
+        trie.insert(&parser.parse_tokens("a")?, 1);
+        trie.insert(&parser.parse_tokens("a.>")?, 2);
+        trie.insert(&parser.parse_tokens("a.>")?, 3);
+        trie.insert(&parser.parse_tokens("a.b")?, 4);
+        trie.insert(&parser.parse_tokens("a.c.d")?, 5);
+        trie.insert(&parser.parse_tokens("a.*")?, 6);
+        trie.insert(&parser.parse_tokens("a.(b|c)")?, 7);
+        let a = trie.node_info(&parser.parse_tokens("a")?).unwrap();
+        assert_eq!(a, NodeInfo { value_count: 1, mwc_count: 2, child_count: 2, has_owc: true, owc_value_count: 1 });
+        assert_eq!(trie.node_info(&parser.parse_tokens("a.>")?), Some(a));
+        assert_eq!(trie.node_info(&parser.parse_tokens("a.c")?), Some(NodeInfo { child_count: 1, ..NodeInfo::default() }));
+        assert_eq!(trie.node_info(&parser.parse_tokens("a.*")?).map(|i| i.value_count), Some(1));
+        assert_eq!(trie.node_info(&Tokens::default()).map(|i| (i.child_count, i.has_owc)), Some((1, false)));
+        assert_eq!(trie.node_info(&parser.parse_tokens("a.d")?), None);
+        Ok(())
+    }
+
+    #[test]
+    fn test_pattern_matches_is_consistent_with_find() {
+        // 穷举由这些token组成的、最多3个token的模式，以及由这些key组成的、最多3个key的keys
+        let tokens = [
+            Token::from("a"),
+            Token::from("b"),
+            Token::OneWildcard(None),
+            Token::OneOf(vec!["a".into(), "1".into()]),
+            Token::NumRange(1, 2),
+        ];
+        let key_set = ["a", "b", "c", "1", "3", ""];
+        let mut patterns: Vec<Tokens> = vec![Tokens::default()];
+        let mut keys: Vec<Vec<&str>> = vec![vec![]];
+        for len in 1..=3 {
+            let shorter: Vec<Tokens> = patterns.iter().filter(|p| p.len() == len - 1).cloned().collect();
+            for p in shorter.iter() {
+                for t in tokens.iter() {
+                    let mut longer = p.0.clone();
+                    longer.push(t.clone());
+                    patterns.push(longer.into());
+                }
+            }
+            let shorter: Vec<Vec<&str>> = keys.iter().filter(|k| k.len() == len - 1).cloned().collect();
+            for k in shorter.iter() {
+                for key in key_set.iter() {
+                    let mut longer = k.clone();
+                    longer.push(*key);
+                    keys.push(longer);
+                }
+            }
+        }
+        // 以多层wildcard结尾的模式
+        let with_mwc: Vec<Tokens> = patterns.iter()
+            .filter(|p| p.len() < 3)
+            .map(|p| {
+                let mut p = p.0.clone();
+                p.push(Token::MultiWildcard);
+                p.into()
+            })
+            .collect();
+        patterns.extend(with_mwc);
+
+        for includes in [false, true].iter() {
+            let mut trie = Trie::<_, 0>::new();
+            trie.set_mwc_includes_prefix(*includes);
+            for (v, p) in patterns.iter().enumerate() {
+                trie.insert(p, v);
+            }
+            for k in keys.iter() {
+                let found: HashSet<usize> = trie.find(k).into_iter().collect();
+                for (v, p) in patterns.iter().enumerate() {
+                    assert_eq!(found.contains(&v), trie.pattern_matches(p, k), "{:?} {:?} {}", p, k, includes);
+                    if !includes {
+                        assert_eq!(trie.pattern_matches(p, k), p.match_keys(k));
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_u32_tokens() {
+        let mut trie: Trie<_, 10, RandomState, u32> = Trie::new();
+        let tokens = |tokens: Vec<Token<'static, u32>>| Tokens::from(tokens);
+        trie.insert(&tokens(vec![Token::Normal(1), Token::Normal(2)]), "exact");
+        trie.insert(&tokens(vec![Token::Normal(1), Token::OneWildcard(None)]), "owc");
+        trie.insert(&tokens(vec![Token::Normal(1), Token::NumRange(10, 20)]), "range");
+        trie.insert(&tokens(vec![Token::OneOf(vec![3, 1]), Token::MultiWildcard]), "mwc");
+        assert!(vec_eq(trie.find([&1, &2]), vec!["exact", "owc", "mwc"]));
+        assert!(vec_eq(trie.find([&1, &15]), vec!["owc", "range", "mwc"]));
+        assert_eq!(trie.find_traced([&1, &15]).1, CacheOutcome::Hit);
+        assert!(vec_eq(trie.find([&3, &4, &5]), vec!["mwc"]));
+        assert!(trie.find([&2, &2]).is_empty());
+        assert!(trie.exist_exact([&1, &2]));
+        assert_eq!(trie.matching_patterns([&3, &4]), vec![tokens(vec![Token::OneOf(vec![1, 3]), Token::MultiWildcard])]);
+        // 移除之后缓存的结果也会过期
+        assert!(trie.remove(&tokens(vec![Token::Normal(1), Token::OneWildcard(None)]), &"owc"));
+        assert!(vec_eq(trie.find([&1, &15]), vec!["range", "mwc"]));
+        assert_eq!(trie.rename_segment(0, &1, &7), 1);
+        assert!(vec_eq(trie.find([&7, &2]), vec!["exact"]));
+        assert_eq!(trie.debug_tree(), "7\n  2 => {\"exact\"}\n  [10-20] => {\"range\"}\n  *\n(1|3) => >{\"mwc\"}\n");
+        // 操作日志中的模式可以在另一棵trie树上重放
+        let mut log_trie: Trie<_, 0, RandomState, u32> = Trie::new();
+        log_trie.enable_op_log();
+        log_trie.insert(&tokens(vec![Token::Normal(5)]), 1);
+        let mut replayed: Trie<_, 0, RandomState, u32> = Trie::new();
+        replayed.replay(log_trie.op_log().unwrap());
+        assert_eq!(replayed.find([&5]), vec![1]);
+    }
+
+    #[test]
+    fn test_hot_keys() -> Result<(), CommonTokenError> {
+        let parser = CommonTokenParser::new('.', "*", ">");
+        let mut trie = Trie::<_, 2>::new();
+        trie.insert(&parser.parse_tokens("a.*")?, 1);
+        // 没有开始统计时不记录
+        trie.find(["a", "b"]);
+        assert!(trie.hot_keys(10).is_empty());
+        trie.enable_hot_keys();
+        for _ in 0..3 {
+            trie.find(["a", "b"]);
+        }
+        trie.find(["a", "c"]);
+        trie.find(["x"]);
+        trie.find_subject("x", '.');
+        // 命中和没有命中缓存的查询都会被统计，不经过缓存的查询不统计
+        trie.snapshot().find(["a", "c"]);
+        assert_eq!(trie.hot_keys(10), vec![(vec!["a", "b"], 3), (vec!["x"], 2), (vec!["a", "c"], 1)]);
+        assert_eq!(trie.hot_keys(1), vec![(vec!["a", "b"], 3)]);
+        assert!(trie.hot_keys(0).is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_has_catchall() -> Result<(), CommonTokenError> {
+        let parser = CommonTokenParser::new('.', "*", ">");
+        let mut trie = Trie::<_, 10>::new();
+        trie.insert(&parser.parse_tokens("a.>")?, 1);
+        trie.insert(&parser.parse_tokens("b.*")?, 2);
+        trie.insert(&parser.parse_tokens("c.x.y")?, 3);
+        assert!(trie.has_catchall(&parser.parse_tokens("a")?));
+        assert!(trie.has_catchall(&parser.parse_tokens("a.b.c")?));
+        assert!(trie.has_catchall(&parser.parse_tokens("a.>")?));
+        // `b.*`只覆盖`b`之下的一层
+        assert!(!trie.has_catchall(&parser.parse_tokens("b")?));
+        assert!(!trie.has_catchall(&parser.parse_tokens("c")?));
+        assert!(!trie.has_catchall(&parser.parse_tokens("c.x")?));
+        assert!(!trie.has_catchall(&parser.parse_tokens(">")?));
+        trie.insert(&parser.parse_tokens("b.*.>")?, 4);
+        assert!(trie.has_catchall(&parser.parse_tokens("b")?));
+        assert!(trie.has_catchall(&parser.parse_tokens("b.*")?));
+        // prefix中的多选一token要求每一个备选token之下都被覆盖
+        assert!(trie.has_catchall(&parser.parse_tokens("(a|b)")?));
+        assert!(!trie.has_catchall(&parser.parse_tokens("(a|c)")?));
+        assert!(!trie.has_catchall(&parser.parse_tokens("*")?));
+        trie.insert(&parser.parse_tokens("*.x.>")?, 5);
+        assert!(trie.has_catchall(&parser.parse_tokens("c.x")?));
+        assert!(trie.has_catchall(&parser.parse_tokens("*.x")?));
+        assert!(!trie.has_catchall(&parser.parse_tokens("*.y")?));
+        trie.insert(&parser.parse_tokens(">")?, 6);
+        assert!(trie.has_catchall(&parser.parse_tokens(">")?));
+        assert!(trie.has_catchall(&parser.parse_tokens("*.y")?));
+        Ok(())
+    }
+
+    #[test]
+    fn test_try_find() -> Result<(), CommonTokenError> {
+        let parser = CommonTokenParser::new('.', "*", ">");
+        let mut trie = Trie::<_, 10>::new();
+        trie.insert(&parser.parse_tokens("a.b.c")?, 1);
+        trie.insert(&parser.parse_tokens("x.*")?, 2);
+        trie.insert(&parser.parse_tokens("m.>")?, 3);
+        assert_eq!(trie.try_find(["a", "b", "c"]), FindResult::Matched(vec![1]));
+        assert_eq!(trie.try_find(["x", "y"]), FindResult::Matched(vec![2]));
+        // 多层wildcard组匹配之后即使没有更深的结点也有值
+        assert_eq!(trie.try_find(["m", "n", "o"]), FindResult::Matched(vec![3]));
+        assert_eq!(trie.try_find(["a", "c"]), FindResult::NoPath);
+        assert_eq!(trie.try_find(["a", "b", "c", "d"]), FindResult::NoPath);
+        assert_eq!(trie.try_find(["a", "b"]), FindResult::NoValues(true));
+        assert_eq!(trie.try_find(["x"]), FindResult::NoValues(true));
+        trie.insert(&parser.parse_tokens("x.*.z")?, 4);
+        // 只经过单层wildcard到达的结点
+        assert_eq!(trie.try_find(["x", "y", "z", "w"]), FindResult::NoPath);
+        trie.insert(&parser.parse_tokens("x.*.z.w")?, 5);
+        trie.remove(&parser.parse_tokens("x.*.z.w")?, &5);
+        assert_eq!(trie.try_find(["x", "y", "z"]), FindResult::Matched(vec![4]));
+        trie.remove(&parser.parse_tokens("x.*.z")?, &4);
+        assert_eq!(trie.try_find(["x", "y", "z"]), FindResult::NoValues(false));
+        Ok(())
+    }
+
+    #[test]
+    fn test_insert_with_ttl() -> Result<(), CommonTokenError> {
+        use std::time::Duration;
+        let parser = CommonTokenParser::new('.', "*", ">");
+        let mut trie = Trie::<_, 10>::new();
+        let now = Instant::now();
+        let second = Duration::from_secs(1);
+        trie.insert(&parser.parse_tokens("a.b")?, 1);
+        trie.insert_with_ttl(&parser.parse_tokens("a.b")?, 2, now + second);
+        trie.insert_with_ttl(&parser.parse_tokens("a.*")?, 3, now + second * 2);
+        trie.insert_with_ttl(&parser.parse_tokens("a.>")?, 4, now + second * 3);
+        trie.insert_with_ttl(&parser.parse_tokens("x")?, 5, now + second);
+        assert!(vec_eq(trie.find(["a", "b"]), vec![1, 2, 3, 4]));
+        assert_eq!(trie.expire(now), 0);
+        // 过期的值在expire之后不再出现在查询结果中，缓存也会过期
+        assert_eq!(trie.expire(now + second), 2);
+        assert!(vec_eq(trie.find(["a", "b"]), vec![1, 3, 4]));
+        assert!(trie.find(["x"]).is_empty());
+        // 更新过期时间
+        trie.insert_with_ttl(&parser.parse_tokens("a.*")?, 3, now + second * 4);
+        // 移除之后再添加的值不再过期
+        trie.remove(&parser.parse_tokens("a.>")?, &4);
+        trie.insert(&parser.parse_tokens("a.>")?, 4);
+        assert_eq!(trie.expire(now + second * 3), 0);
+        assert!(vec_eq(trie.find(["a", "b"]), vec![1, 3, 4]));
+        assert_eq!(trie.expire(now + second * 4), 1);
+        assert!(vec_eq(trie.find(["a", "b"]), vec![1, 4]));
+        trie.insert_with_ttl(&parser.parse_tokens("y")?, 6, now);
+        trie.clear();
+        trie.insert(&parser.parse_tokens("y")?, 6);
+        assert_eq!(trie.expire(now + second), 0);
+        assert_eq!(trie.find(["y"]), vec![6]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_ttl_removed_values() -> Result<(), CommonTokenError> {
+        use std::time::Duration;
+        let parser = CommonTokenParser::new('.', "*", ">");
+        let later = Instant::now() + Duration::from_secs(60);
+        let after = later + Duration::from_secs(1);
+        let ab = parser.parse_tokens("a.b")?;
+        // 以各种方式移除之后再添加的值不再过期
+        let removals: Vec<for<'t> fn(&mut Trie<'t, i32, 10>, &Tokens<'t>)> = vec![
+            |trie, tokens| assert_eq!(trie.take(tokens, &1), Some(1)),
+            |trie, tokens| assert!(trie.remove_if(tokens, &1, |v| *v == 1)),
+            |trie, tokens| assert!(trie.remove_all(tokens)),
+            |trie, _| assert_eq!(trie.remove_matching(&["a", "b"], &1), 1),
+            |trie, _| assert_eq!(trie.remove_subtree(&Tokens::from(vec![Token::Normal("a".into())])), 1),
+            |trie, _| assert_eq!(trie.retain_with_pattern(|_, v| *v != 1), 1),
+        ];
+        for remove in removals {
+            let mut trie = Trie::<_, 10>::new();
+            trie.insert_with_ttl(&ab, 1, later);
+            remove(&mut trie, &ab);
+            trie.insert(&ab, 1);
+            assert_eq!(trie.expire(after), 0);
+            assert_eq!(trie.find(["a", "b"]), vec![1]);
+        }
+        // 改名之后过期时间跟随值移动
+        let mut trie = Trie::<_, 10>::new();
+        trie.insert_with_ttl(&ab, 1, later);
+        trie.insert_with_ttl(&parser.parse_tokens("a.b.*")?, 2, later);
+        trie.insert(&parser.parse_tokens("a.c")?, 3);
+        trie.insert_with_ttl(&parser.parse_tokens("a.c")?, 4, after);
+        assert_eq!(trie.rename_segment(1, "b", "c"), 1);
+        assert_eq!(trie.expire(later), 2);
+        assert!(vec_eq(trie.find(["a", "c"]), vec![3, 4]));
+        assert!(trie.find(["a", "c", "d"]).is_empty());
+        // 目标组中原本就有的值保留原来的过期时间
+        trie.insert_with_ttl(&parser.parse_tokens("a.d")?, 3, later);
+        assert_eq!(trie.rename_segment(1, "d", "c"), 1);
+        assert_eq!(trie.expire(later), 0);
+        assert_eq!(trie.expire(after), 1);
+        assert_eq!(trie.find(["a", "c"]), vec![3]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_ttl_hidden_from_find() -> Result<(), CommonTokenError> {
+        let parser = CommonTokenParser::new('.', "*", ">");
+        let mut trie = Trie::<_, 10>::new();
+        trie.set_cache_mode(CacheMode::Values);
+        trie.insert(&parser.parse_tokens("a.b")?, 1);
+        trie.insert_with_ttl(&parser.parse_tokens("a.*")?, 2, Instant::now() + std::time::Duration::from_secs(60));
+        assert!(vec_eq(trie.find(["a", "b"]), vec![1, 2]));
+        // 已经过期的值在find之前被移除，缓存的结果也会过期
+        trie.insert_with_ttl(&parser.parse_tokens("a.*")?, 2, Instant::now());
+        assert_eq!(trie.find(["a", "b"]), vec![1]);
+        assert_eq!(trie.expire(Instant::now()), 0);
+        Ok(())
+    }
+
+    #[test]
+    fn test_find_with_depth() -> Result<(), CommonTokenError> {
+        let parser = CommonTokenParser::new('.', "*", ">");
+        let mut trie = Trie::<_, 10>::new();
+        trie.insert(&parser.parse_tokens("a.b.c")?, 1);
+        trie.insert(&parser.parse_tokens("a.*.c")?, 2);
+        trie.insert(&parser.parse_tokens("a.>")?, 3);
+        trie.insert(&parser.parse_tokens(">")?, 4);
+        trie.insert(&parser.parse_tokens("a.b.>")?, 5);
+        let mut found = trie.find_with_depth(["a", "b", "c"]);
+        found.sort_unstable();
+        assert_eq!(found, vec![(1, 3), (2, 3), (3, 1), (4, 0), (5, 2)]);
+        let mut found = trie.find_with_depth(["a", "x"]);
+        found.sort_unstable();
+        assert_eq!(found, vec![(3, 1), (4, 0)]);
+        assert_eq!(trie.find_with_depth(["a"]), vec![(4, 0)]);
+        trie.set_mwc_includes_prefix(true);
+        let mut found = trie.find_with_depth(["a"]);
+        found.sort_unstable();
+        assert_eq!(found, vec![(3, 1), (4, 0)]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_ordered_trie() -> Result<(), CommonTokenError> {
+        let parser = CommonTokenParser::new('.', "*", ">");
+        let mut trie = OrderedTrie::<_, 10>::new();
+        for (pattern, value) in [("c.x", 1), ("a.z", 2), ("b", 3), ("a.y", 4), ("a.*", 5), ("a.>", 6)] {
+            trie.insert(&parser.parse_tokens(pattern)?, value);
+        }
+        assert!(vec_eq(trie.find(["a", "y"]), vec![4, 5, 6]));
+        let mut visited = Vec::new();
+        trie.snapshot().visit(|tokens, value| visited.push((tokens.render('.', "*", ">"), *value)));
+        let expected = vec![("a.>".to_string(), 6), ("a.y".to_string(), 4), ("a.z".to_string(), 2),
+                            ("a.*".to_string(), 5), ("b".to_string(), 3), ("c.x".to_string(), 1)];
+        assert_eq!(visited, expected);
+        assert_eq!(trie.debug_tree(), "a => >{6}\n  y => {4}\n  z => {2}\n  * => {5}\nb => {3}\nc\n  x => {1}\n");
+        assert!(trie.remove(&parser.parse_tokens("a.y")?, &4));
+        let pairs: Vec<_> = trie.into_iter().map(|(tokens, value)| (tokens.render('.', "*", ">"), value)).collect();
+        assert_eq!(pairs, vec![("a.>".to_string(), 6), ("a.z".to_string(), 2), ("a.*".to_string(), 5),
+                               ("b".to_string(), 3), ("c.x".to_string(), 1)]);
+        Ok(())
+    }
+}
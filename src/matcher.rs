@@ -0,0 +1,63 @@
+use crate::children::{ChildMap, HashedChildren};
+use crate::node::Node;
+use crate::token::TokenAtom;
+use std::borrow::Cow;
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hash};
+
+/// 逐个接收key中的token进行匹配，适用于key中的token逐个到达的场景
+pub struct Matcher<'t, 'a, V, S = RandomState, K: TokenAtom = Cow<'a, str>, M: ChildMap = HashedChildren<S>> {
+    // 与已经接收的token匹配的node
+    nodes: Vec<&'t Node<'a, V, S, K, M>>,
+    // 多层wildcard组与已经接收的token匹配的node
+    mwc_nodes: Vec<&'t Node<'a, V, S, K, M>>,
+    // 多层wildcard组是否也匹配其前缀本身
+    mwc_includes_prefix: bool,
+}
+
+impl<'t, 'a, V, S, K, M> Matcher<'t, 'a, V, S, K, M>
+where
+    K: TokenAtom,
+    M: ChildMap,
+    V: Eq + Hash,
+    S: BuildHasher + Default,
+{
+    /// 从root开始匹配
+    pub(crate) fn new(root: &'t Node<'a, V, S, K, M>, mwc_includes_prefix: bool) -> Self {
+        Matcher {
+            nodes: vec![root],
+            mwc_nodes: vec![],
+            mwc_includes_prefix,
+        }
+    }
+
+    /// 接收下一个token，返回是否还有可能匹配到值。
+    /// 返回false之后，无论再接收什么token都不会有匹配的值
+    pub fn step(&mut self, token: &K::Key) -> bool {
+        let mut next_nodes = Vec::new();
+        for node in self.nodes.iter() {
+            // 多层wildcard必然满足之后所有token的需求
+            if !node.is_mwc_empty() {
+                self.mwc_nodes.push(node);
+            }
+            // 符合当前token的node可以是token对应的，也可以是owc、多选一token或者数字范围token对应的
+            next_nodes.extend(node.owc_node());
+            next_nodes.extend(node.alt_nodes_matching(token).map(|(_, n)| n));
+            next_nodes.extend(node.range_nodes_matching(token).map(|(_, n)| n));
+            if let Some(n) = node.get_child_node(token) {
+                next_nodes.push(n);
+            }
+        }
+        self.nodes = next_nodes;
+        !self.nodes.is_empty() || !self.mwc_nodes.is_empty()
+    }
+
+    /// 与已经接收的token组成的key匹配的所有值，结果与对这个key调用`find`相同
+    pub fn matches(&self) -> Vec<&'t V> {
+        // 多层wildcard组也匹配其前缀本身时，当前的node上的多层wildcard组也算在内
+        let prefix_nodes = if self.mwc_includes_prefix { self.nodes.as_slice() } else { &[] };
+        self.mwc_nodes.iter().chain(prefix_nodes).flat_map(|n| n.mwc_values())
+            .chain(self.nodes.iter().flat_map(|n| n.values()))
+            .collect()
+    }
+}
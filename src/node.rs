@@ -1,32 +1,150 @@
-use std::collections::HashSet;
+use std::borrow::{Borrow, Cow};
 use std::collections::HashMap;
-use std::collections::hash_set::{Iter, IntoIter};
-use std::hash::Hash;
+use std::collections::hash_map::RandomState;
+use std::fmt::{Debug, Write};
+use std::hash::{BuildHasher, Hash};
+use crate::children::{ChildContainer, ChildMap, HashedChildren};
+use crate::intern::{Atom, ChildKey, Interner};
+use crate::token::{normalize_alternatives, Token, TokenAtom};
+
+// 组中的值的集合。开启hashbrown特性时使用hashbrown的实现，可以用调用者事先计算的hash插入值
+#[cfg(feature = "hashbrown")]
+pub(crate) type ValueSet<V, S> = hashbrown::HashSet<V, S>;
+#[cfg(not(feature = "hashbrown"))]
+pub(crate) type ValueSet<V, S> = std::collections::HashSet<V, S>;
+// 组中的值的迭代器
+#[cfg(feature = "hashbrown")]
+pub(crate) type Iter<'s, V> = hashbrown::hash_set::Iter<'s, V>;
+#[cfg(not(feature = "hashbrown"))]
+pub(crate) type Iter<'s, V> = std::collections::hash_set::Iter<'s, V>;
+// 拆解之后组中的值的迭代器
+#[cfg(feature = "hashbrown")]
+pub(crate) type ValueIntoIter<V> = hashbrown::hash_set::IntoIter<V>;
+#[cfg(not(feature = "hashbrown"))]
+pub(crate) type ValueIntoIter<V> = std::collections::hash_set::IntoIter<V>;
+
+// 子结点的集合
+pub(crate) type Children<'a, V, S, K, M> = <M as ChildMap>::Map<ChildKey<K>, Box<Node<'a, V, S, K, M>>>;
+// 多选一token对应的子结点的集合，每一项的备选token都已经排序去重
+pub(crate) type AltNodes<'a, V, S, K, M> = Vec<(Vec<K>, Box<Node<'a, V, S, K, M>>)>;
+// 数字范围token对应的子结点的集合，每一项为闭区间的上下界
+pub(crate) type RangeNodes<'a, V, S, K, M> = Vec<((u64, u64), Box<Node<'a, V, S, K, M>>)>;
+
+// 组的tokens中每个单层wildcard的捕获名，按照出现的顺序排列，没有名字的为None
+pub(crate) type CaptureNames<'a> = Vec<Option<Cow<'a, str>>>;
+
+// 结点上两个组各自的捕获名
+#[derive(Default, Debug, Clone)]
+struct GroupCaptures<'a> {
+    // 当前结点对应的组
+    exact: CaptureNames<'a>,
+    // 多层wildcard组
+    mwc: CaptureNames<'a>,
+}
+
+/// 向组中重复添加已经存在的值时的行为
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum DuplicateMode {
+    /// 忽略重复的值，组是值的集合
+    #[default]
+    Ignore,
+    /// 记录每个值被添加的次数，移除时减少一次，次数为0时才从组中移除
+    Count,
+}
+
+// 计数模式下结点上两个组中重复添加的值的额外次数，值的次数为1加上额外次数
+#[derive(Debug)]
+pub(crate) struct GroupDuplicates<V, S> {
+    // 当前结点对应的组
+    pub(crate) exact: GroupCounts<V, S>,
+    // 多层wildcard组
+    pub(crate) mwc: GroupCounts<V, S>,
+}
+
+/// 一个组中的值的额外次数
+pub(crate) type GroupCounts<V, S> = HashMap<V, usize, S>;
+
+// 子结点在父结点中的位置
+enum Slot<K: TokenAtom> {
+    Child(ChildKey<K>),
+    OneWildcard,
+    OneOf(Vec<K>),
+    NumRange(u64, u64),
+}
+
+// 拆下来的子结点，以及其在父结点中的位置
+type SubNodes<'a, V, S, K, M> = Vec<(Slot<K>, Box<Node<'a, V, S, K, M>>)>;
+
+// 子结点的引用，以及其在父结点中的位置
+type SubNodeRefs<'n, 'a, V, S, K, M> = Vec<(Slot<K>, &'n Node<'a, V, S, K, M>)>;
+
+// 待复制的结点，以及其父结点在列表中的位置和其在父结点中的位置
+type SourceNodes<'n, 'a, V, S, K, M> = Vec<(Option<usize>, Slot<K>, &'n Node<'a, V, S, K, M>)>;
+
+// 普通子结点中保存的键，以及子结点的引用
+pub(crate) type ChildEntry<'n, 'a, V, S, K, M> = (&'n ChildKey<K>, &'n Node<'a, V, S, K, M>);
+
+// 拆下来的结点，以及其父结点在列表中的位置和其在父结点中的位置
+type DetachedNodes<'a, V, S, K, M> = Vec<(Option<usize>, Slot<K>, Box<Node<'a, V, S, K, M>>)>;
+
+// 等待合并的两个结点，以及合并之后的结点的父结点在列表中的位置和其在父结点中的位置
+type PendingMerges<'a, V, S, K, M> = Vec<(Option<usize>, Slot<K>, Box<Node<'a, V, S, K, M>>, Box<Node<'a, V, S, K, M>>)>;
+
+/// 结点拆解之后的各个部分
+pub(crate) struct NodeParts<'a, V, S, K: TokenAtom, M: ChildMap> {
+    pub(crate) children: Children<'a, V, S, K, M>,
+    pub(crate) o_node: Option<Box<Node<'a, V, S, K, M>>>,
+    pub(crate) alt_nodes: AltNodes<'a, V, S, K, M>,
+    pub(crate) range_nodes: RangeNodes<'a, V, S, K, M>,
+    pub(crate) m_value_set: ValueSet<V, S>,
+    pub(crate) value_set: ValueSet<V, S>,
+    pub(crate) duplicates: Option<Box<GroupDuplicates<V, S>>>,
+}
 
 /// trie树结点
-#[derive(Default, Debug)]
-pub struct Node<'a, V> {
+#[derive(Default)]
+pub struct Node<'a, V, S = RandomState, K: TokenAtom = Cow<'a, str>, M: ChildMap = HashedChildren<S>> {
     // 子结点
-    children: HashMap<&'a str, Box<Node<'a, V>>>,
+    children: Children<'a, V, S, K, M>,
     // 订阅了单层wildcard对应的node
-    o_node: Option<Box<Node<'a, V>>>,
+    o_node: Option<Box<Node<'a, V, S, K, M>>>,
+    // 订阅了多选一token对应的node
+    alt_nodes: AltNodes<'a, V, S, K, M>,
+    // 订阅了数字范围token对应的node
+    range_nodes: RangeNodes<'a, V, S, K, M>,
     // 订阅了多层wildcard对应的组
-    m_value_set: HashSet<V>,
+    m_value_set: ValueSet<V, S>,
     // 当前结点对应的值
-    value_set: HashSet<V>,
+    value_set: ValueSet<V, S>,
+    // 组的捕获名，只有插入过带名字的单层wildcard时才分配
+    captures: Option<Box<GroupCaptures<'a>>>,
+    // 组中的值的额外次数，只有计数模式下重复添加过值时才分配
+    duplicates: Option<Box<GroupDuplicates<V, S>>>,
 }
 
-impl<'a, V> Node<'a, V>
+impl<'a, V, S, K, M> Node<'a, V, S, K, M>
 where
-    V: Eq + Hash + Clone
+    K: TokenAtom,
+    M: ChildMap,
+    V: Eq + Hash,
+    S: BuildHasher + Default,
 {
     /// 生成一个新节点
     pub(crate) fn new() -> Self {
-        return Node{
-            children: HashMap::new(),
-            value_set: HashSet::new(),
+        Self::with_capacity(0)
+    }
+
+    /// 生成一个新节点，并为children个子节点预留空间
+    pub(crate) fn with_capacity(children: usize) -> Self {
+        Node {
+            children: ChildContainer::with_capacity(children),
+            value_set: ValueSet::with_hasher(S::default()),
             o_node: None,
-            m_value_set: HashSet::new(),
+            alt_nodes: Vec::new(),
+            range_nodes: Vec::new(),
+            m_value_set: ValueSet::with_hasher(S::default()),
+            captures: None,
+            duplicates: None,
         }
     }
 
@@ -36,21 +154,52 @@ where
     }
 
     /// 返回当前的values的引用
-    #[allow(dead_code)]
-    fn values(&self) -> Iter<'_, V>{
+    pub(crate) fn values(&self) -> Iter<'_, V>{
         self.value_set.iter()
     }
 
-    /// 返回当前values的复制
-    pub(crate) fn values_owned(&self) -> IntoIter<V> {
-        self.value_set.to_owned().into_iter()
+    /// 返回当前结点对应的组
+    pub(crate) fn value_set(&self) -> &ValueSet<V, S> {
+        &self.value_set
     }
 
     /// 移除一个value
     pub(crate) fn remove(&mut self, value: &V) -> bool {
+        self.forget(value, false);
         self.value_set.remove(value)
     }
 
+    /// 是否存在value
+    pub(crate) fn contains(&self, value: &V) -> bool {
+        self.value_set.contains(value)
+    }
+
+    /// 返回组中与value相等的值的引用，如果不存在则返回None
+    pub(crate) fn get(&self, value: &V) -> Option<&V> {
+        self.value_set.get(value)
+    }
+
+    /// 取出一个value，如果不存在则返回None
+    pub(crate) fn take(&mut self, value: &V) -> Option<V> {
+        self.forget(value, false);
+        self.value_set.take(value)
+    }
+
+    /// 组中已经有max个值并且不包含value，即添加value之后会超过max
+    pub(crate) fn is_group_full(&self, value: &V, mwc: bool, max: usize) -> bool {
+        let (len, contains) = if mwc {
+            (self.mwc_len(), self.mwc_contains(value))
+        } else {
+            (self.len(), self.contains(value))
+        };
+        len >= max && !contains
+    }
+
+    /// 当前组中值的数量
+    pub(crate) fn len(&self) -> usize {
+        self.value_set.len()
+    }
+
     /// 不存在value
     pub(crate) fn is_empty(&self) -> bool {
         self.value_set.is_empty()
@@ -61,34 +210,355 @@ where
         if self.is_empty() {
             false
         } else {
+            if let Some(duplicates) = self.duplicates.as_mut() {
+                duplicates.exact.clear();
+            }
             self.value_set.clear();
             true
         }
     }
 
-    /// 所有子节点的不可变引用
-    #[allow(dead_code)]
-    fn child_nodes(&self) -> impl Iterator<Item=&Node<V>> {
-        self.children.values().map(|n| n.as_ref())
+    /// 为子节点预留空间，避免子节点较多时反复扩容
+    pub(crate) fn reserve(&mut self, additional: usize) {
+        self.children.reserve(additional)
+    }
+
+    /// 当前结点及其子结点中都没有任何值，也没有任何子结点
+    pub(crate) fn is_vacant(&self) -> bool {
+        self.is_empty() && self.is_mwc_empty() && self.children.is_empty()
+            && self.o_node.is_none() && self.alt_nodes.is_empty() && self.range_nodes.is_empty()
+    }
+
+    /// 删除所有不包含值的子树，返回当前结点删除之后是否已经为空。
+    /// 先把所有结点拆下来，再从最深的结点开始装回去，不会因为树太深而栈溢出
+    pub(crate) fn prune(&mut self) -> bool {
+        let root = std::mem::replace(self, Node::new());
+        // 拆下来的结点，以及其父结点在列表中的位置，None表示根结点。父结点总是在子结点之前
+        let mut nodes: DetachedNodes<'a, V, S, K, M> = Vec::new();
+        let mut root = Box::new(root);
+        for (slot, child) in root.detach_sub_nodes() {
+            nodes.push((None, slot, child));
+        }
+        let mut i = 0;
+        while i < nodes.len() {
+            let subs = nodes[i].2.detach_sub_nodes();
+            nodes.extend(subs.into_iter().map(|(slot, child)| (Some(i), slot, child)));
+            i += 1;
+        }
+        // 子结点都已经处理过了，所以此时为空的结点就是不包含值的子树
+        while let Some((parent, slot, node)) = nodes.pop() {
+            if node.is_vacant() {
+                continue;
+            }
+            match parent {
+                None => root.attach_sub_node(slot, node),
+                Some(p) => nodes[p].2.attach_sub_node(slot, node),
+            }
+        }
+        *self = *root;
+        self.is_vacant()
+    }
+
+    /// 将other及其所有子结点合并到当前结点中，两边相同位置的子结点也逐层合并。
+    /// 合并之后的结点先拆下来，最后再装回去，不会因为树太深而栈溢出
+    pub(crate) fn merge(&mut self, mut other: Box<Node<'a, V, S, K, M>>) {
+        // 合并好的结点，以及其父结点在列表中的位置，None表示当前结点。父结点总是在子结点之前
+        let mut merged: DetachedNodes<'a, V, S, K, M> = Vec::new();
+        // 还要合并的结点对，以及合并之后的结点的父结点和位置
+        let mut pending = Vec::new();
+        self.absorb(&mut other, None, &mut pending);
+        while let Some((parent, slot, mut node, mut other)) = pending.pop() {
+            node.absorb(&mut other, Some(merged.len()), &mut pending);
+            merged.push((parent, slot, node));
+        }
+        while let Some((parent, slot, node)) = merged.pop() {
+            match parent {
+                None => self.attach_sub_node(slot, node),
+                Some(p) => merged[p].2.attach_sub_node(slot, node),
+            }
+        }
+    }
+
+    /// 将other的值和子结点移动到当前结点中，other的子结点如果与当前结点的子结点位置相同，
+    /// 就把两者都拆下来放到pending中等待合并，否则直接装到当前结点上
+    fn absorb(&mut self, other: &mut Node<'a, V, S, K, M>, index: Option<usize>, pending: &mut PendingMerges<'a, V, S, K, M>) {
+        self.absorb_group(other, false);
+        self.absorb_group(other, true);
+        if self.captures.is_none() {
+            self.captures = other.captures.take();
+        }
+        for (slot, child) in other.detach_sub_nodes() {
+            match self.detach_slot(&slot) {
+                Some(node) => pending.push((index, slot, node, child)),
+                None => self.attach_sub_node(slot, child),
+            }
+        }
+    }
+
+    /// 将other的一个组中的值移动到当前结点的组中，两个组都有的值的次数相加
+    fn absorb_group(&mut self, other: &mut Node<'a, V, S, K, M>, mwc: bool) {
+        let values = std::mem::take(if mwc { &mut other.m_value_set } else { &mut other.value_set });
+        for value in values {
+            let extra = other.forget(&value, mwc);
+            let set = if mwc { &mut self.m_value_set } else { &mut self.value_set };
+            if set.contains(&value) {
+                self.add_extra(value, mwc, 1);
+            } else {
+                set.insert(value);
+            }
+            if let Some((value, extra)) = extra {
+                self.add_extra(value, mwc, extra);
+            }
+        }
+    }
+
+    /// 拆下slot位置的子结点，如果不存在则返回None
+    fn detach_slot(&mut self, slot: &Slot<K>) -> Option<Box<Node<'a, V, S, K, M>>> {
+        match slot {
+            Slot::Child(k) => self.children.remove(&**k),
+            Slot::OneWildcard => self.o_node.take(),
+            Slot::OneOf(a) => {
+                let i = self.alt_nodes.iter().position(|(alternatives, _)| alternatives == a)?;
+                Some(self.alt_nodes.remove(i).1)
+            },
+            Slot::NumRange(lo, hi) => {
+                let i = self.range_nodes.iter().position(|(range, _)| *range == (*lo, *hi))?;
+                Some(self.range_nodes.remove(i).1)
+            },
+        }
+    }
+
+    /// 拆下token对应的普通子结点，如果不存在则返回None
+    pub(crate) fn remove_child_node(&mut self, token: &K::Key) -> Option<Box<Node<'a, V, S, K, M>>> {
+        self.children.remove(token)
+    }
+
+    /// 释放当前结点及子结点中多余的容量
+    pub(crate) fn shrink_to_fit(&mut self) {
+        let mut stack = vec![self];
+        while let Some(node) = stack.pop() {
+            node.children.shrink_to_fit();
+            node.value_set.shrink_to_fit();
+            node.m_value_set.shrink_to_fit();
+            node.alt_nodes.shrink_to_fit();
+            node.range_nodes.shrink_to_fit();
+            stack.extend(node.child_nodes_mut());
+        }
+    }
+
+    /// 以当前结点为起点深度优先地遍历子树中所有的结点，包括当前结点。
+    /// 使用显式的栈，不会因为树太深而栈溢出
+    pub(crate) fn descendants(&self) -> impl Iterator<Item = &Node<'a, V, S, K, M>> {
+        let mut stack = vec![self];
+        std::iter::from_fn(move || {
+            let node = stack.pop()?;
+            stack.extend(node.child_nodes());
+            Some(node)
+        })
+    }
+
+    /// 当前结点及所有子结点中值的总数
+    pub(crate) fn subtree_len(&self) -> usize {
+        self.descendants().map(|n| n.len() + n.mwc_len()).sum()
+    }
+
+    /// 当前结点及所有子结点中是否有wildcard组或者wildcard对应的子结点
+    pub(crate) fn has_wildcard(&self) -> bool {
+        self.descendants().any(|n| !n.is_mwc_empty() || n.o_node.is_some()
+            || !n.alt_nodes.is_empty() || !n.range_nodes.is_empty())
+    }
+
+    /// 直接子结点的数量，不包含单层wildcard、多选一token和数字范围token对应的结点
+    pub(crate) fn child_count(&self) -> usize {
+        self.children.len()
+    }
+
+    /// 当前结点及所有子结点中最大的直接子结点数量
+    pub(crate) fn max_fanout(&self) -> usize {
+        self.descendants().map(|n| n.children.len()).max().unwrap_or(0)
+    }
+
+    /// 以当前结点为根的子树的深度，即从当前结点到最深的子结点的边数
+    pub(crate) fn depth(&self) -> usize {
+        let mut stack = vec![(self, 0)];
+        let mut depth = 0;
+        while let Some((node, d)) = stack.pop() {
+            depth = depth.max(d);
+            stack.extend(node.child_nodes().map(|n| (n, d + 1)));
+        }
+        depth
+    }
+
+    /// 将path对应的子树摘下并返回，如果不存在则返回None。
+    /// 路径上因此变空的结点会被一并删除
+    pub(crate) fn detach(&mut self, path: &[Token<'a, K>]) -> Option<Box<Node<'a, V, S, K, M>>> {
+        let (last, parents) = path.split_last()?;
+        let mut node = &mut *self;
+        for token in parents.iter() {
+            node = node.get_token_node_mut(token)?;
+        }
+        let detached = node.remove_token_node(last);
+        self.prune_path(parents);
+        detached
+    }
+
+    /// 删除path末端因为没有值也没有其它子结点而不再需要的结点
+    pub(crate) fn prune_path(&mut self, path: &[Token<'a, K>]) {
+        // 路径上的结点，不包括当前结点
+        let mut chain = Vec::new();
+        let mut node = &*self;
+        for token in path.iter() {
+            match node.get_token_node(token) {
+                Some(child) => {
+                    chain.push(child);
+                    node = child;
+                },
+                None => break,
+            }
+        }
+        // 从最深的结点向上找到可以一起删除的结点：最深的结点为空，其上的结点只有通向它的子结点
+        let mut cut = chain.len();
+        while cut > 0 {
+            let node = chain[cut - 1];
+            let sub_nodes = if cut == chain.len() { 0 } else { 1 };
+            if node.is_empty() && node.is_mwc_empty() && node.child_nodes().count() == sub_nodes {
+                cut -= 1;
+            } else {
+                break;
+            }
+        }
+        if cut == chain.len() {
+            return;
+        }
+        let mut node = &mut *self;
+        for token in path[..cut].iter() {
+            node = match node.get_token_node_mut(token) {
+                Some(child) => child,
+                None => return,
+            };
+        }
+        node.remove_token_node(&path[cut]);
+    }
+
+    /// 拆解当前结点
+    pub(crate) fn into_parts(mut self) -> NodeParts<'a, V, S, K, M> {
+        NodeParts {
+            children: std::mem::take(&mut self.children),
+            o_node: self.o_node.take(),
+            alt_nodes: std::mem::take(&mut self.alt_nodes),
+            range_nodes: std::mem::take(&mut self.range_nodes),
+            m_value_set: std::mem::take(&mut self.m_value_set),
+            value_set: std::mem::take(&mut self.value_set),
+            duplicates: self.duplicates.take(),
+        }
+    }
+
+    /// 所有子节点的不可变引用，包括单层wildcard、多选一token和数字范围token对应的子结点
+    fn child_nodes(&self) -> impl Iterator<Item=&Node<'a, V, S, K, M>> {
+        self.children.values()
+            .chain(self.o_node.as_ref())
+            .chain(self.alt_nodes.iter().map(|(_, n)| n))
+            .chain(self.range_nodes.iter().map(|(_, n)| n))
+            .map(|n| n.as_ref())
     }
 
-    /// 所有子节点的可变引用
-    #[allow(dead_code)]
-    fn child_nodes_mut(&mut self) -> impl Iterator<Item=&mut Node<'a, V>> {
-        self.children.values_mut().map(|n| n.as_mut())
+    /// 所有子节点的可变引用，包括单层wildcard、多选一token和数字范围token对应的子结点
+    fn child_nodes_mut(&mut self) -> impl Iterator<Item=&mut Node<'a, V, S, K, M>> {
+        self.children.values_mut()
+            .chain(self.o_node.as_mut())
+            .chain(self.alt_nodes.iter_mut().map(|(_, n)| n))
+            .chain(self.range_nodes.iter_mut().map(|(_, n)| n))
+            .map(|n| n.as_mut())
+    }
+
+    /// 所有子结点的不可变引用，以及从当前结点到达该子结点的token
+    pub(crate) fn token_child_nodes(&self) -> impl Iterator<Item = (Token<'a, K>, &Node<'a, V, S, K, M>)> {
+        self.children.iter()
+            .map(|(k, n)| (Token::Normal(k.to_atom()), n.as_ref()))
+            .chain(self.o_node.as_deref().map(|n| (Token::OneWildcard(None), n)))
+            .chain(self.alt_nodes.iter().map(|(a, n)| (Token::OneOf(a.clone()), n.as_ref())))
+            .chain(self.range_nodes.iter().map(|((lo, hi), n)| (Token::NumRange(*lo, *hi), n.as_ref())))
+    }
+
+    /// 所有子结点的可变引用，以及从当前结点到达该子结点的token
+    pub(crate) fn token_child_nodes_mut(&mut self) -> impl Iterator<Item = (Token<'a, K>, &mut Node<'a, V, S, K, M>)> {
+        self.children.iter_mut()
+            .map(|(k, n)| (Token::Normal(k.to_atom()), n.as_mut()))
+            .chain(self.o_node.as_deref_mut().map(|n| (Token::OneWildcard(None), n)))
+            .chain(self.alt_nodes.iter_mut().map(|(a, n)| (Token::OneOf(a.clone()), n.as_mut())))
+            .chain(self.range_nodes.iter_mut().map(|((lo, hi), n)| (Token::NumRange(*lo, *hi), n.as_mut())))
+    }
+
+    /// 拆下所有的子结点。多选一token和数字范围token对应的子结点是倒序的，依次装回去之后能够恢复原来的顺序
+    fn detach_sub_nodes(&mut self) -> SubNodes<'a, V, S, K, M> {
+        let mut subs: Vec<_> = self.children.drain()
+            .map(|(k, n)| (Slot::Child(k), n))
+            .collect();
+        subs.extend(self.o_node.take().map(|n| (Slot::OneWildcard, n)));
+        subs.extend(self.alt_nodes.drain(..).rev().map(|(a, n)| (Slot::OneOf(a), n)));
+        subs.extend(self.range_nodes.drain(..).rev().map(|((lo, hi), n)| (Slot::NumRange(lo, hi), n)));
+        subs
+    }
+
+    /// 所有子结点的不可变引用，以及其在当前结点中的位置。与`detach_sub_nodes`的顺序相同
+    fn sub_node_slots(&self) -> SubNodeRefs<'_, 'a, V, S, K, M> {
+        let mut subs: Vec<_> = self.children.iter()
+            .map(|(k, n)| (Slot::Child(k.clone()), n.as_ref()))
+            .collect();
+        subs.extend(self.o_node.as_deref().map(|n| (Slot::OneWildcard, n)));
+        subs.extend(self.alt_nodes.iter().rev().map(|(a, n)| (Slot::OneOf(a.clone()), n.as_ref())));
+        subs.extend(self.range_nodes.iter().rev().map(|((lo, hi), n)| (Slot::NumRange(*lo, *hi), n.as_ref())));
+        subs
+    }
+
+    /// 将子结点装回到slot的位置
+    fn attach_sub_node(&mut self, slot: Slot<K>, node: Box<Node<'a, V, S, K, M>>) {
+        match slot {
+            Slot::Child(k) => {
+                self.children.insert(k, node);
+            },
+            Slot::OneWildcard => self.o_node = Some(node),
+            Slot::OneOf(a) => self.alt_nodes.push((a, node)),
+            Slot::NumRange(lo, hi) => self.range_nodes.push(((lo, hi), node)),
+        }
     }
     
     /// 返回单层wildcard对应的node的不可变引用，如果已经有node，则返回，如果没有对应node，则创建并返回
-    pub(crate) fn owc_node(&self) -> Option<&Node<V>> {
+    pub(crate) fn owc_node(&self) -> Option<&Node<'a, V, S, K, M>> {
         self.o_node.as_ref().map(|n| (*n).as_ref())
     }
 
     /// 返回单层wildcard对应的node的可变引用，如果已经有node，则返回，如果没有对应node，则创建并返回
-    pub(crate) fn owc_node_mut(&mut self) -> &mut Node<'a, V> {
+    pub(crate) fn owc_node_mut(&mut self) -> &mut Node<'a, V, S, K, M> {
         // 如果是None则插入新的值，并返回对应的引用
         self.o_node.get_or_insert(Box::new(Node::new()))
     }
 
+    /// 设置组的捕获名，后插入的tokens中的名字会覆盖之前的
+    pub(crate) fn set_capture_names(&mut self, names: CaptureNames<'a>, mwc: bool) {
+        if self.captures.is_none() && names.iter().all(Option::is_none) {
+            return;
+        }
+        let captures = self.captures.get_or_insert_with(Default::default);
+        if mwc {
+            captures.mwc = names;
+        } else {
+            captures.exact = names;
+        }
+    }
+
+    /// 将组的捕获名与路径上各个单层wildcard匹配的key一一对应，没有名字的key会被忽略
+    pub(crate) fn bind_captures<'k>(&self, keys: &[&'k K::Key], mwc: bool) -> HashMap<&str, &'k K::Key> {
+        let names = match (&self.captures, mwc) {
+            (None, _) => return HashMap::new(),
+            (Some(captures), true) => &captures.mwc,
+            (Some(captures), false) => &captures.exact,
+        };
+        names.iter().zip(keys.iter())
+            .filter_map(|(name, key)| name.as_deref().map(|name| (name, *key)))
+            .collect()
+    }
+
     /// 向多层wildcard组中插入值
     pub(crate) fn mwc_add(&mut self, value: V) -> bool {
         self.m_value_set.insert(value)
@@ -96,18 +566,39 @@ where
 
     /// 从多层wildcard组中移除值
     pub(crate) fn mwc_remove(&mut self, value: &V) -> bool {
+        self.forget(value, true);
         self.m_value_set.remove(value)
     }
 
+    /// 从多层wildcard组中取出值，如果不存在则返回None
+    pub(crate) fn mwc_take(&mut self, value: &V) -> Option<V> {
+        self.forget(value, true);
+        self.m_value_set.take(value)
+    }
+
     /// 返回多层wildcard组中所有的值的引用
-    #[allow(dead_code)]
-    fn mwc_values(&self) -> Iter<'_, V> {
+    pub(crate) fn mwc_values(&self) -> Iter<'_, V> {
         self.m_value_set.iter()
     }
 
-    /// 返回多层wildcard组中所有值的复制
-    pub(crate) fn mwc_values_owned(&self) -> IntoIter<V> {
-        self.m_value_set.to_owned().into_iter()
+    /// 返回多层wildcard组
+    pub(crate) fn mwc_value_set(&self) -> &ValueSet<V, S> {
+        &self.m_value_set
+    }
+
+    /// 多层wildcard组中值的数量
+    pub(crate) fn mwc_len(&self) -> usize {
+        self.m_value_set.len()
+    }
+
+    /// 多层wildcard组中是否存在value
+    pub(crate) fn mwc_contains(&self, value: &V) -> bool {
+        self.m_value_set.contains(value)
+    }
+
+    /// 返回多层wildcard组中与value相等的值的引用，如果不存在则返回None
+    pub(crate) fn mwc_get(&self, value: &V) -> Option<&V> {
+        self.m_value_set.get(value)
     }
 
     /// 多层wildcard组是否是空的
@@ -120,23 +611,482 @@ where
         if self.is_mwc_empty() {
             false
         } else {
+            if let Some(duplicates) = self.duplicates.as_mut() {
+                duplicates.mwc.clear();
+            }
             self.m_value_set.clear();
             true
         }
     }
 
-    /// 获得一个token对应的子节点。如果不存在，则创建
-    pub(crate) fn get_child_node_mut_or_insert(&mut self, token: &'a str) -> &mut Node<'a, V> {
-        self.children.entry(token).or_insert(Box::new(Node::new()))
+    /// 按照mode向组中添加value，返回是否新添加了值
+    pub(crate) fn add_in_mode(&mut self, value: V, mwc: bool, mode: DuplicateMode) -> bool {
+        match mode {
+            DuplicateMode::Count => self.add_counted(value, mwc),
+            // 如果存在mwc则放在mwc里面去
+            DuplicateMode::Ignore => if mwc { self.mwc_add(value) } else { self.add(value) },
+        }
+    }
+
+    /// 与`add_in_mode`相同，但是用事先计算的hash添加value，hash必须与`S::default()`计算的相同
+    #[cfg(feature = "hashbrown")]
+    pub(crate) fn add_prehashed_in_mode(&mut self, value: V, hash: u64, mwc: bool, mode: DuplicateMode) -> bool {
+        let set = if mwc { &mut self.m_value_set } else { &mut self.value_set };
+        match insert_prehashed(set, value, hash) {
+            None => true,
+            Some(value) => {
+                if mode == DuplicateMode::Count {
+                    self.add_extra(value, mwc, 1);
+                }
+                false
+            }
+        }
+    }
+
+    /// 按照mode添加从另一个组中移出的value，计数模式下再加上value在原来的组中的额外次数，
+    /// extra中的值是原来的组中保存额外次数的值。返回是否新添加了值
+    pub(crate) fn add_moved(&mut self, value: V, mwc: bool, mode: DuplicateMode, extra: Option<(V, usize)>) -> bool {
+        let added = self.add_in_mode(value, mwc, mode);
+        if let (DuplicateMode::Count, Some((value, extra))) = (mode, extra) {
+            self.add_extra(value, mwc, extra);
+        }
+        added
+    }
+
+    /// 计数模式下向组中添加value，已经存在时增加一次其次数。返回是否新添加了值
+    fn add_counted(&mut self, value: V, mwc: bool) -> bool {
+        let set = if mwc { &mut self.m_value_set } else { &mut self.value_set };
+        if !set.contains(&value) {
+            return set.insert(value);
+        }
+        self.add_extra(value, mwc, 1);
+        false
+    }
+
+    // 增加组中已经存在的value的额外次数
+    fn add_extra(&mut self, value: V, mwc: bool, extra: usize) {
+        let duplicates = self.duplicates.get_or_insert_with(|| Box::new(GroupDuplicates {
+            exact: HashMap::with_hasher(S::default()),
+            mwc: HashMap::with_hasher(S::default()),
+        }));
+        let counts = if mwc { &mut duplicates.mwc } else { &mut duplicates.exact };
+        *counts.entry(value).or_insert(0) += extra;
+    }
+
+    /// 计数模式下减少一次value的次数，返回value是否被重复添加过。
+    /// 返回true时value仍然在组中，返回false时由调用者从组中移除
+    pub(crate) fn release(&mut self, value: &V, mwc: bool) -> bool {
+        let counts = match (self.duplicates.as_mut(), mwc) {
+            (None, _) => return false,
+            (Some(duplicates), true) => &mut duplicates.mwc,
+            (Some(duplicates), false) => &mut duplicates.exact,
+        };
+        match counts.get_mut(value) {
+            None => false,
+            Some(extra) => {
+                *extra -= 1;
+                if *extra == 0 {
+                    counts.remove(value);
+                }
+                true
+            }
+        }
+    }
+
+    /// 组中value被添加的次数，不存在时为0，没有重复添加过时为1
+    pub(crate) fn count_of(&self, value: &V, mwc: bool) -> usize {
+        let contains = if mwc { self.mwc_contains(value) } else { self.contains(value) };
+        if !contains {
+            return 0;
+        }
+        let extra = match (&self.duplicates, mwc) {
+            (None, _) => None,
+            (Some(duplicates), true) => duplicates.mwc.get(value),
+            (Some(duplicates), false) => duplicates.exact.get(value),
+        };
+        1 + extra.copied().unwrap_or(0)
+    }
+
+    /// 按照mode返回组中value被添加的次数，`DuplicateMode::Ignore`下最多为1
+    pub(crate) fn count_in_mode(&self, value: &V, mwc: bool, mode: DuplicateMode) -> usize {
+        match mode {
+            DuplicateMode::Count => self.count_of(value, mwc),
+            DuplicateMode::Ignore => self.count_of(value, mwc).min(1),
+        }
+    }
+
+    /// 移除并返回组中所有的值以及其被添加的次数
+    pub(crate) fn drain_counted(&mut self, mwc: bool) -> Vec<(V, usize)> {
+        let (set, counts) = self.group_counts_mut(mwc);
+        let counts = counts.map(std::mem::take).unwrap_or_default();
+        set.drain().map(|v| {
+            let extra = counts.get(&v).copied().unwrap_or(0);
+            (v, 1 + extra)
+        }).collect()
+    }
+
+    /// 只保留组中f返回true的值，f的参数为值以及其被添加的次数
+    pub(crate) fn retain_counted(&mut self, mwc: bool, mut f: impl FnMut(&V, usize) -> bool) {
+        let (set, counts) = self.group_counts_mut(mwc);
+        set.retain(|v| f(v, 1 + counts.as_ref().and_then(|counts| counts.get(v)).copied().unwrap_or(0)));
+        if let Some(counts) = counts {
+            counts.retain(|v, _| set.contains(v));
+        }
+    }
+
+    // 组以及组中的值的额外次数，没有重复添加过值时额外次数为None
+    fn group_counts_mut(&mut self, mwc: bool) -> (&mut ValueSet<V, S>, Option<&mut GroupCounts<V, S>>) {
+        let duplicates = self.duplicates.as_deref_mut();
+        if mwc {
+            (&mut self.m_value_set, duplicates.map(|d| &mut d.mwc))
+        } else {
+            (&mut self.value_set, duplicates.map(|d| &mut d.exact))
+        }
+    }
+
+    // 值从组中移除时也移除其额外次数，返回保存额外次数的值和额外次数
+    fn forget(&mut self, value: &V, mwc: bool) -> Option<(V, usize)> {
+        let duplicates = self.duplicates.as_mut()?;
+        if mwc { duplicates.mwc.remove_entry(value) } else { duplicates.exact.remove_entry(value) }
+    }
+
+    /// 获得一个token对应的子节点。如果不存在，则创建，有interner时子节点的键由interner驻留
+    pub(crate) fn get_child_node_mut_or_insert(&mut self, token: &K, interner: Option<&mut Interner<K>>) -> &mut Node<'a, V, S, K, M> {
+        if !self.children.contains_key(token.key()) {
+            let atom = match interner {
+                Some(interner) => interner.intern(token),
+                None => Atom::Plain(token.clone()),
+            };
+            self.children.insert(atom, Box::new(Node::new()));
+        }
+        self.children.get_mut(token.key()).unwrap()
     }
 
     /// 返回token对应的子节点的可变引用
-    pub(crate) fn get_child_node_mut(&mut self, token: &'a str) -> Option<&mut Node<'a, V>> {
+    pub(crate) fn get_child_node_mut(&mut self, token: &K::Key) -> Option<&mut Node<'a, V, S, K, M>> {
         self.children.get_mut(token).map(|n| (*n).as_mut())
     }
 
     /// 返回token对应的子节点的不可变引用
-    pub(crate) fn get_child_node(&self, token: &'a str) -> Option<&Node<'a, V>> {
+    pub(crate) fn get_child_node(&self, token: &K::Key) -> Option<&Node<'a, V, S, K, M>> {
         self.children.get(token).map(|n| (*n).as_ref())
     }
-}
\ No newline at end of file
+
+    /// 返回token对应的子节点的不可变引用，以及子结点中保存的token
+    pub(crate) fn get_child_entry(&self, token: &K::Key) -> Option<ChildEntry<'_, 'a, V, S, K, M>> {
+        self.children.get_key_value(token).map(|(k, n)| (k, n.as_ref()))
+    }
+
+    /// 返回备选token为alternatives的子结点的可变引用，如果不存在，则创建
+    pub(crate) fn alt_node_mut_or_insert(&mut self, alternatives: &[K]) -> &mut Node<'a, V, S, K, M> {
+        let alternatives = normalize_alternatives(alternatives);
+        let idx = match self.alt_nodes.iter().position(|(a, _)| *a == alternatives) {
+            Some(idx) => idx,
+            None => {
+                self.alt_nodes.push((alternatives, Box::new(Node::new())));
+                self.alt_nodes.len() - 1
+            }
+        };
+        &mut self.alt_nodes[idx].1
+    }
+
+    /// 返回备选token为alternatives的子结点的可变引用
+    pub(crate) fn alt_node_mut(&mut self, alternatives: &[K]) -> Option<&mut Node<'a, V, S, K, M>> {
+        let alternatives = normalize_alternatives(alternatives);
+        self.alt_nodes.iter_mut()
+            .find(|(a, _)| *a == alternatives)
+            .map(|(_, n)| n.as_mut())
+    }
+
+    /// 返回备选token为alternatives的子结点的不可变引用
+    pub(crate) fn alt_node(&self, alternatives: &[K]) -> Option<&Node<'a, V, S, K, M>> {
+        let alternatives = normalize_alternatives(alternatives);
+        self.alt_nodes.iter()
+            .find(|(a, _)| *a == alternatives)
+            .map(|(_, n)| n.as_ref())
+    }
+
+    /// 返回所有备选token中包含key的子结点，以及其备选token
+    pub(crate) fn alt_nodes_matching<'s, 'k>(&'s self, key: &'k K::Key) -> impl Iterator<Item = (&'s [K], &'s Node<'a, V, S, K, M>)> + 'k
+    where
+        's: 'k,
+    {
+        self.alt_nodes.iter()
+            .filter(move |(a, _)| a.binary_search_by(|alt| alt.key().cmp(key)).is_ok())
+            .map(|(a, n)| (a.as_slice(), n.as_ref()))
+    }
+
+    /// 返回所有备选token包含alternatives中每一项的子结点，以及其备选token
+    pub(crate) fn alt_nodes_covering<'s, 'c, Q>(&'s self, alternatives: &'c [Q]) -> impl Iterator<Item = (&'s [K], &'s Node<'a, V, S, K, M>)> + 'c
+    where
+        's: 'c,
+        Q: Borrow<K::Key>,
+    {
+        self.alt_nodes.iter()
+            .filter(move |(a, _)| alternatives.iter()
+                .all(|alt| a.binary_search_by(|x| x.key().cmp(alt.borrow())).is_ok()))
+            .map(|(a, n)| (a.as_slice(), n.as_ref()))
+    }
+
+    /// 返回范围为lo到hi的子结点的可变引用，如果不存在，则创建
+    pub(crate) fn range_node_mut_or_insert(&mut self, lo: u64, hi: u64) -> &mut Node<'a, V, S, K, M> {
+        let idx = match self.range_nodes.iter().position(|(r, _)| *r == (lo, hi)) {
+            Some(idx) => idx,
+            None => {
+                self.range_nodes.push(((lo, hi), Box::new(Node::new())));
+                self.range_nodes.len() - 1
+            }
+        };
+        &mut self.range_nodes[idx].1
+    }
+
+    /// 返回范围为lo到hi的子结点的可变引用
+    pub(crate) fn range_node_mut(&mut self, lo: u64, hi: u64) -> Option<&mut Node<'a, V, S, K, M>> {
+        self.range_nodes.iter_mut()
+            .find(|(r, _)| *r == (lo, hi))
+            .map(|(_, n)| n.as_mut())
+    }
+
+    /// 返回范围为lo到hi的子结点的不可变引用
+    pub(crate) fn range_node(&self, lo: u64, hi: u64) -> Option<&Node<'a, V, S, K, M>> {
+        self.range_nodes.iter()
+            .find(|(r, _)| *r == (lo, hi))
+            .map(|(_, n)| n.as_ref())
+    }
+
+    /// 返回所有范围包含key表示的数字的子结点，以及其范围。key不是数字时没有匹配的子结点
+    pub(crate) fn range_nodes_matching<'s>(&'s self, key: &K::Key) -> impl Iterator<Item = ((u64, u64), &'s Node<'a, V, S, K, M>)> {
+        let num = if self.range_nodes.is_empty() { None } else { K::key_to_num(key) };
+        self.range_nodes.iter()
+            .filter(move |((lo, hi), _)| num.is_some_and(|n| *lo <= n && n <= *hi))
+            .map(|(r, n)| (*r, n.as_ref()))
+    }
+
+    /// 返回所有范围包含lo到hi的子结点，以及其范围
+    pub(crate) fn range_nodes_covering(&self, lo: u64, hi: u64) -> impl Iterator<Item = ((u64, u64), &Node<'a, V, S, K, M>)> {
+        self.range_nodes.iter()
+            .filter(move |((l, h), _)| *l <= lo && hi <= *h)
+            .map(|(r, n)| (*r, n.as_ref()))
+    }
+
+    /// 返回token对应的子结点的不可变引用，多层wildcard没有对应的子结点
+    pub(crate) fn get_token_node(&self, token: &Token<'a, K>) -> Option<&Node<'a, V, S, K, M>> {
+        match token {
+            Token::Normal(s) => self.get_child_node(s.key()),
+            Token::OneWildcard(_) => self.owc_node(),
+            Token::OneOf(alternatives) => self.alt_node(alternatives),
+            Token::NumRange(lo, hi) => self.range_node(*lo, *hi),
+            Token::MultiWildcard => None,
+        }
+    }
+
+    /// 返回token对应的子结点的可变引用，多层wildcard没有对应的子结点
+    pub(crate) fn get_token_node_mut(&mut self, token: &Token<'a, K>) -> Option<&mut Node<'a, V, S, K, M>> {
+        match token {
+            Token::Normal(s) => self.get_child_node_mut(s.key()),
+            Token::OneWildcard(_) => self.o_node.as_deref_mut(),
+            Token::OneOf(alternatives) => self.alt_node_mut(alternatives),
+            Token::NumRange(lo, hi) => self.range_node_mut(*lo, *hi),
+            Token::MultiWildcard => None,
+        }
+    }
+
+    /// 返回token对应的子结点的可变引用，如果不存在，则创建。
+    /// 多层wildcard没有对应的子结点，返回自身
+    pub(crate) fn get_token_node_mut_or_insert(&mut self, token: &Token<'a, K>, interner: Option<&mut Interner<K>>) -> &mut Node<'a, V, S, K, M> {
+        match token {
+            Token::Normal(s) => self.get_child_node_mut_or_insert(s, interner),
+            Token::OneWildcard(_) => self.owc_node_mut(),
+            Token::OneOf(alternatives) => self.alt_node_mut_or_insert(alternatives),
+            Token::NumRange(lo, hi) => self.range_node_mut_or_insert(*lo, *hi),
+            Token::MultiWildcard => self,
+        }
+    }
+
+    /// 删除token对应的子结点并返回
+    pub(crate) fn remove_token_node(&mut self, token: &Token<'a, K>) -> Option<Box<Node<'a, V, S, K, M>>> {
+        match token {
+            Token::Normal(s) => self.children.remove(s.key()),
+            Token::OneWildcard(_) => self.o_node.take(),
+            Token::OneOf(alternatives) => {
+                let alternatives = normalize_alternatives(alternatives);
+                let idx = self.alt_nodes.iter().position(|(a, _)| *a == alternatives)?;
+                Some(self.alt_nodes.remove(idx).1)
+            },
+            Token::NumRange(lo, hi) => {
+                let idx = self.range_nodes.iter().position(|(r, _)| *r == (*lo, *hi))?;
+                Some(self.range_nodes.remove(idx).1)
+            },
+            Token::MultiWildcard => None,
+        }
+    }
+}
+
+// 需要复制值的方法
+impl<'a, V, S, K, M> Node<'a, V, S, K, M>
+where
+    K: TokenAtom,
+    M: ChildMap,
+    V: Eq + Hash + Clone,
+    S: BuildHasher + Default,
+{
+    /// 返回当前values的复制
+    pub(crate) fn values_owned(&self) -> impl Iterator<Item = V> + '_ {
+        self.value_set.iter().cloned()
+    }
+
+    /// 返回多层wildcard组中所有值的复制
+    pub(crate) fn mwc_values_owned(&self) -> impl Iterator<Item = V> + '_ {
+        self.m_value_set.iter().cloned()
+    }
+
+    /// 将当前结点及所有子结点中的值，包括各个wildcard组中的值，复制到out中
+    pub(crate) fn collect_subtree(&self, out: &mut Vec<V>) {
+        for node in self.descendants() {
+            out.extend(node.mwc_values_owned());
+            out.extend(node.values_owned());
+        }
+    }
+
+    /// 复制当前结点及所有子结点。与`prune`一样先逐层列出所有结点，
+    /// 再从最深的结点开始装回去，不会因为树太深而栈溢出
+    pub(crate) fn deep_clone(&self) -> Node<'a, V, S, K, M> {
+        // 原来的结点，以及其父结点在列表中的位置，None表示根结点。父结点总是在子结点之前
+        let mut sources: SourceNodes<'_, 'a, V, S, K, M> = self.sub_node_slots()
+            .into_iter()
+            .map(|(slot, child)| (None, slot, child))
+            .collect();
+        let mut i = 0;
+        while i < sources.len() {
+            let node = sources[i].2;
+            sources.extend(node.sub_node_slots().into_iter().map(|(slot, child)| (Some(i), slot, child)));
+            i += 1;
+        }
+        let mut nodes: DetachedNodes<'a, V, S, K, M> = sources.into_iter()
+            .map(|(parent, slot, node)| (parent, slot, Box::new(node.clone_groups())))
+            .collect();
+        let mut root = self.clone_groups();
+        while let Some((parent, slot, node)) = nodes.pop() {
+            match parent {
+                None => root.attach_sub_node(slot, node),
+                Some(p) => nodes[p].2.attach_sub_node(slot, node),
+            }
+        }
+        root
+    }
+
+    /// 只复制当前结点的两个组和捕获名，不包含子结点
+    fn clone_groups(&self) -> Node<'a, V, S, K, M> {
+        let mut node = Node::with_capacity(self.children.len());
+        node.value_set.extend(self.values_owned());
+        node.m_value_set.extend(self.mwc_values_owned());
+        node.captures = self.captures.clone();
+        if let Some(duplicates) = self.duplicates.as_ref() {
+            for (value, extra) in duplicates.exact.iter() {
+                node.add_extra(value.clone(), false, *extra);
+            }
+            for (value, extra) in duplicates.mwc.iter() {
+                node.add_extra(value.clone(), true, *extra);
+            }
+        }
+        node
+    }
+}
+
+// 用事先计算的hash向集合中插入值，已经存在时不插入并且返回该值。
+// 每个集合的hasher都是`S::default()`，只有其计算的hash对于所有实例都相同时，调用者才能事先计算
+#[cfg(feature = "hashbrown")]
+fn insert_prehashed<V, S>(set: &mut ValueSet<V, S>, value: V, hash: u64) -> Option<V>
+where
+    V: Eq + Hash,
+    S: BuildHasher + Default,
+{
+    debug_assert_eq!(hash, S::default().hash_one(&value), "the hash is not computed by S::default()");
+    let table = set.raw_table_mut();
+    if table.find(hash, |(v, _)| *v == value).is_some() {
+        return Some(value);
+    }
+    // 扩容时其它值的hash也由`S::default()`计算，与集合自身的hasher相同
+    table.insert(hash, (value, ()), |(v, _)| S::default().hash_one(v));
+    None
+}
+
+// 默认的析构会随着树的深度递归，这里先把子结点拆下来放到栈中，再逐个释放
+impl<'a, V, S, K: TokenAtom, M: ChildMap> Drop for Node<'a, V, S, K, M> {
+    fn drop(&mut self) {
+        let mut stack = Vec::new();
+        move_sub_nodes(self, &mut stack);
+        while let Some(mut node) = stack.pop() {
+            move_sub_nodes(&mut node, &mut stack);
+            // 此时node已经没有子结点了，释放它不会再递归
+        }
+    }
+}
+
+// 将node的所有子结点移动到stack中
+fn move_sub_nodes<'a, V, S, K: TokenAtom, M: ChildMap>(node: &mut Node<'a, V, S, K, M>, stack: &mut Vec<Box<Node<'a, V, S, K, M>>>) {
+    stack.extend(node.children.drain().map(|(_, n)| n));
+    stack.extend(node.o_node.take());
+    stack.extend(node.alt_nodes.drain(..).map(|(_, n)| n));
+    stack.extend(node.range_nodes.drain(..).map(|(_, n)| n));
+}
+
+impl<'a, V, S, K, M> Node<'a, V, S, K, M>
+where
+    K: TokenAtom,
+    M: ChildMap,
+    V: Eq + Hash + Debug,
+    S: BuildHasher + Default,
+{
+    /// 将以当前结点为根的子树渲染为缩进的文本，每个子结点占一行，缩进表示深度
+    pub(crate) fn write_tree(&self, out: &mut String, depth: usize) {
+        let mut stack = Vec::new();
+        self.push_labeled_sub_nodes(&mut stack, depth);
+        while let Some((node, depth, label)) = stack.pop() {
+            out.push_str(&"  ".repeat(depth));
+            out.push_str(&label);
+            node.write_groups(out);
+            out.push('\n');
+            node.push_labeled_sub_nodes(&mut stack, depth + 1);
+        }
+    }
+
+    /// 将子结点以及其显示的token放入栈中，出栈的顺序为排序后的普通token、多选一token、数字范围token、单层wildcard
+    fn push_labeled_sub_nodes<'s>(&'s self, stack: &mut LabeledNodes<'s, 'a, V, S, K, M>, depth: usize) {
+        let mut children: Vec<_> = self.children.iter().collect();
+        children.sort_by(|a, b| a.0.cmp(b.0));
+        let labeled: Vec<_> = children.into_iter()
+            .map(|(k, n)| (n.as_ref(), k.to_string()))
+            .chain(self.alt_nodes.iter().map(|(a, n)| (n.as_ref(), format!("({})", join_atoms(a)))))
+            .chain(self.range_nodes.iter().map(|((lo, hi), n)| (n.as_ref(), format!("[{}-{}]", lo, hi))))
+            .chain(self.o_node.as_ref().map(|n| (n.as_ref(), "*".to_string())))
+            .collect();
+        stack.extend(labeled.into_iter().rev().map(|(n, label)| (n, depth, label)));
+    }
+
+    /// 渲染结点上非空的组，普通组为`=> {1, 2}`，多层wildcard组为`=> >{3}`
+    pub(crate) fn write_groups(&self, out: &mut String) {
+        if !self.value_set.is_empty() {
+            let _ = write!(out, " => {}", format_values(self.values()));
+        }
+        if !self.m_value_set.is_empty() {
+            let _ = write!(out, " => >{}", format_values(self.mwc_values()));
+        }
+    }
+}
+
+// 渲染时待处理的结点，以及其深度和显示的token
+type LabeledNodes<'s, 'a, V, S, K, M> = Vec<(&'s Node<'a, V, S, K, M>, usize, String)>;
+
+// 将一组值渲染为`{1, 2}`，按照Debug输出排序以保证结果稳定
+fn format_values<'v, V: Debug + 'v>(values: impl Iterator<Item = &'v V>) -> String {
+    let mut values: Vec<String> = values.map(|v| format!("{:?}", v)).collect();
+    values.sort();
+    format!("{{{}}}", values.join(", "))
+}
+
+// 将多选一token的备选token渲染为`a|b`
+fn join_atoms<K: TokenAtom>(alternatives: &[K]) -> String {
+    let alternatives: Vec<String> = alternatives.iter().map(|a| a.key().to_string()).collect();
+    alternatives.join("|")
+}
@@ -0,0 +1,73 @@
+use crate::event::TrieEvent;
+use crate::token::{TokenAtom, Tokens};
+use std::borrow::Cow;
+
+/// 操作日志中的一项修改操作
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Op<V, K = Cow<'static, str>> {
+    /// 向pattern对应的组中添加了value
+    Insert { pattern: Tokens<'static, K>, value: V },
+    /// 从pattern对应的组中移除了value
+    Remove { pattern: Tokens<'static, K>, value: V },
+    /// 清空了整个trie树
+    Clear,
+}
+
+/// 按顺序记录trie树中每一次确实改变了内容的操作，可以在新的trie树上重放。
+/// 移除整个组或者子树会被记录为逐个值的移除，`DuplicateMode::Count`下值被添加了几次就记录几次移除
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OpLog<V, K = Cow<'static, str>> {
+    ops: Vec<Op<V, K>>,
+}
+
+impl<V, K> Default for OpLog<V, K> {
+    fn default() -> Self {
+        OpLog { ops: Vec::new() }
+    }
+}
+
+impl<V, K> OpLog<V, K> {
+    /// 生成一个空的操作日志
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 按顺序返回记录的所有操作
+    pub fn ops(&self) -> &[Op<V, K>] {
+        &self.ops
+    }
+
+    /// 记录的操作的数量
+    pub fn len(&self) -> usize {
+        self.ops.len()
+    }
+
+    /// 是否没有记录任何操作
+    pub fn is_empty(&self) -> bool {
+        self.ops.is_empty()
+    }
+
+    /// 记录事件对应的操作，事件中的值由clone复制
+    pub(crate) fn record<E>(&mut self, event: &TrieEvent<'_, V, E>, clone: fn(&V) -> V)
+    where
+        E: TokenAtom<Static = K>,
+    {
+        self.ops.push(match event {
+            TrieEvent::Inserted { pattern, value } => Op::Insert {
+                pattern: pattern.to_static(),
+                value: clone(value),
+            },
+            TrieEvent::Removed { pattern, value } => Op::Remove {
+                pattern: pattern.to_static(),
+                value: clone(value),
+            },
+            TrieEvent::Cleared => Op::Clear,
+        });
+    }
+}
+
+impl<V, K> From<Vec<Op<V, K>>> for OpLog<V, K> {
+    fn from(ops: Vec<Op<V, K>>) -> Self {
+        OpLog { ops }
+    }
+}
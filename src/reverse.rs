@@ -0,0 +1,102 @@
+use crate::children::{ChildMap, HashedChildren};
+use crate::error::Error;
+use crate::token::{Token, TokenAtom, Tokens};
+use crate::Trie;
+use std::borrow::Cow;
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hash};
+
+/// 从右向左索引token的trie树，适用于按照末尾的token匹配的键空间，例如域名。
+/// 模式和key都按照原本从左向右的顺序给出，插入和查询时再反转，因此模式中的多层wildcard
+/// 只能出现在最前面：`>.example.com`匹配`a.example.com`和`a.b.example.com`，但是不匹配
+/// `example.com`；`*.example.com`只匹配恰好多一个token的key。
+/// 这样的模式不能由要求多层wildcard在末尾的parser解析，可以直接构造`Tokens`
+pub struct ReverseTrie<'a, V, const N: usize, S = RandomState, K: TokenAtom = Cow<'a, str>, M: ChildMap = HashedChildren<S>> {
+    // 实际保存反转之后的模式的trie树
+    trie: Trie<'a, V, N, S, K, M>,
+}
+
+impl<'a, V, const N: usize, S, K, M> ReverseTrie<'a, V, N, S, K, M>
+where
+    K: TokenAtom,
+    M: ChildMap,
+    V: Eq + Hash + Clone,
+    S: BuildHasher + Default,
+{
+    /// 初始化
+    pub fn new() -> Self {
+        ReverseTrie { trie: Trie::new() }
+    }
+
+    /// 添加键值对，多层wildcard只能是tokens的第一个token
+    pub fn insert(&mut self, tokens: &Tokens<'a, K>, value: V) {
+        self.trie.insert(&reversed(tokens), value);
+    }
+
+    /// 检查反转之后的tokens的结构之后再添加键值对。错误中的位置是反转之前的位置，
+    /// 例如多层wildcard之前还有token时返回`Error::TokenAfterMwc`，其中是最后一个这样的token的位置
+    pub fn try_insert(&mut self, tokens: &Tokens<'a, K>, value: V) -> Result<(), Error> {
+        let last = tokens.len().saturating_sub(1);
+        self.trie.try_insert(&reversed(tokens), value).map_err(|e| match e {
+            Error::EmptyToken(i) => Error::EmptyToken(last - i),
+            Error::TokenAfterMwc(i) => Error::TokenAfterMwc(last - i),
+            e => e,
+        })
+    }
+
+    /// 移除tokens对应的组中的value值，如果确实移除了则返回true
+    pub fn remove(&mut self, tokens: &Tokens<'a, K>, value: &V) -> bool {
+        self.trie.remove(&reversed(tokens), value)
+    }
+
+    /// 返回能与keys匹配的所有值，经过缓存
+    pub fn find(&mut self, keys: impl AsRef<[&'a K::Key]>) -> Vec<V> {
+        self.trie.find(reversed_keys(keys.as_ref()))
+    }
+
+    /// 是否有与keys匹配的值存在
+    pub fn exist<'k>(&self, keys: impl AsRef<[&'k K::Key]>) -> bool {
+        self.trie.exist(reversed_keys(keys.as_ref()))
+    }
+
+    /// 返回与keys匹配的所有非空的组的tokens，按照从左向右的顺序，多层wildcard组以`MultiWildcard`开头
+    pub fn matching_patterns<'k>(&self, keys: impl AsRef<[&'k K::Key]>) -> Vec<Tokens<'a, K>> {
+        self.trie.matching_patterns(reversed_keys(keys.as_ref()))
+            .iter()
+            .map(reversed)
+            .collect()
+    }
+
+    /// 返回内部的trie树的引用，其中的模式和key都是反转之后的
+    pub fn as_trie(&self) -> &Trie<'a, V, N, S, K, M> {
+        &self.trie
+    }
+
+    /// 返回内部的trie树，其中的模式和key都是反转之后的
+    pub fn into_inner(self) -> Trie<'a, V, N, S, K, M> {
+        self.trie
+    }
+}
+
+// 手动实现，避免derive带来的`V: Default`约束
+impl<'a, V, const N: usize, S, K, M> Default for ReverseTrie<'a, V, N, S, K, M>
+where
+    K: TokenAtom,
+    M: ChildMap,
+    V: Eq + Hash + Clone,
+    S: BuildHasher + Default,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// 反转tokens的顺序
+fn reversed<'a, K: TokenAtom>(tokens: &Tokens<'a, K>) -> Tokens<'a, K> {
+    tokens.iter().rev().cloned().collect::<Vec<Token<'a, K>>>().into()
+}
+
+// 反转keys的顺序
+fn reversed_keys<'k, Q: ?Sized>(keys: &[&'k Q]) -> Vec<&'k Q> {
+    keys.iter().rev().copied().collect()
+}
@@ -0,0 +1,77 @@
+use crate::children::{ChildMap, HashedChildren};
+use crate::error::Error;
+use crate::token::{Token, TokenAtom, Tokens};
+use crate::Trie;
+use std::borrow::Cow;
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hash};
+
+/// 所有模式和key都固定为DEPTH个token的trie树，例如`region.service.instance`。
+/// 模式和key都以长度为DEPTH的数组传入，token数量不对的模式或者key在编译时就会被拒绝。
+/// 模式中仍然可以使用单层wildcard、多选一token和数字范围token
+pub struct SchemaTrie<'a, V, const DEPTH: usize, const N: usize, S = RandomState, K: TokenAtom = Cow<'a, str>, M: ChildMap = HashedChildren<S>> {
+    // 实际保存模式的trie树
+    trie: Trie<'a, V, N, S, K, M>,
+}
+
+impl<'a, V, const DEPTH: usize, const N: usize, S, K, M> SchemaTrie<'a, V, DEPTH, N, S, K, M>
+where
+    K: TokenAtom,
+    M: ChildMap,
+    V: Eq + Hash + Clone,
+    S: BuildHasher + Default,
+{
+    /// 初始化
+    pub fn new() -> Self {
+        SchemaTrie { trie: Trie::new() }
+    }
+
+    /// 添加键值对，pattern中的每一项可以是普通token的`&str`，也可以是任意的`Token`。
+    /// 与`Trie::try_insert`相同，多层wildcard不在最后、或者有永远不能匹配的token时返回错误并且不做任何修改
+    pub fn insert<T: Into<Token<'a, K>>>(&mut self, pattern: [T; DEPTH], value: V) -> Result<(), Error> {
+        self.trie.try_insert(&Self::tokens(pattern), value)
+    }
+
+    /// 移除pattern对应的组中的value值，如果确实移除了则返回true
+    pub fn remove<T: Into<Token<'a, K>>>(&mut self, pattern: [T; DEPTH], value: &V) -> bool {
+        self.trie.remove(&Self::tokens(pattern), value)
+    }
+
+    /// 返回能与keys匹配的所有值，经过缓存
+    pub fn find(&mut self, keys: [&'a K::Key; DEPTH]) -> Vec<V> {
+        self.trie.find(keys)
+    }
+
+    /// 是否有与keys匹配的值存在
+    pub fn exist(&self, keys: [&K::Key; DEPTH]) -> bool {
+        self.trie.exist(keys)
+    }
+
+    /// 返回内部的trie树的引用，用于其它只读的查询
+    pub fn as_trie(&self) -> &Trie<'a, V, N, S, K, M> {
+        &self.trie
+    }
+
+    /// 返回内部的trie树
+    pub fn into_inner(self) -> Trie<'a, V, N, S, K, M> {
+        self.trie
+    }
+
+    // 将数组形式的模式转换为tokens
+    fn tokens<T: Into<Token<'a, K>>>(pattern: [T; DEPTH]) -> Tokens<'a, K> {
+        Tokens::from(Vec::from(pattern).into_iter().map(Into::into).collect::<Vec<_>>())
+    }
+}
+
+// 手动实现，避免derive带来的`V: Default`约束
+impl<'a, V, const DEPTH: usize, const N: usize, S, K, M> Default for SchemaTrie<'a, V, DEPTH, N, S, K, M>
+where
+    K: TokenAtom,
+    M: ChildMap,
+    V: Eq + Hash + Clone,
+    S: BuildHasher + Default,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
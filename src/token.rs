@@ -1,28 +1,234 @@
+use crate::error::Error as TrieError;
+use std::borrow::{Borrow, Cow};
+use std::convert::TryFrom;
+use std::fmt::{Debug, Display};
+use std::hash::Hash;
+use std::ops::{Index, Range};
 use thiserror::Error;
 
-/// Token is the smallest unit of inserting subject
-#[derive(Debug, PartialEq, Eq, Hash)]
-pub enum Token<'a> {
-    /// normal one represented by str
-    Normal(&'a str),
-    /// wildcard which will always match a single token
-    OneWildcard,
+/// Atom of normal tokens, `Cow<str>` by default. Keys are given in the borrowed
+/// form `Key` of the atom, e.g. a trie of `Cow<str>` tokens is queried with `&str`
+/// keys. Integer atoms, e.g. `u32` IDs, are their own keys
+pub trait TokenAtom: Clone + Eq + Hash + Ord + Debug + Borrow<Self::Key> {
+    /// Borrowed form of the atom in which keys are given
+    type Key: ?Sized + Eq + Hash + Ord + Debug + Display + 'static;
+
+    /// The atom owning all its data, e.g. `Cow<'static, str>` for `Cow<'a, str>`
+    type Static: TokenAtom<Key = Self::Key, Static = Self::Static> + 'static;
+
+    /// Returns an owned atom equal to the key
+    fn from_key(key: &Self::Key) -> Self;
+
+    /// The key as a number for `NumRange` tokens, or None if it is not one
+    fn key_to_num(key: &Self::Key) -> Option<u64>;
+
+    /// Returns the atom owning all its data, which no longer borrows the source
+    fn to_static(&self) -> Self::Static;
+
+    /// Turns an owned atom back to the atom
+    fn from_static(atom: Self::Static) -> Self;
+
+    /// The atom in its borrowed form
+    fn key(&self) -> &Self::Key {
+        self.borrow()
+    }
+}
+
+impl<'a> TokenAtom for Cow<'a, str> {
+    type Key = str;
+    type Static = Cow<'static, str>;
+
+    fn from_key(key: &str) -> Self {
+        Cow::Owned(key.to_string())
+    }
+
+    fn key_to_num(key: &str) -> Option<u64> {
+        parse_num(key)
+    }
+
+    fn to_static(&self) -> Cow<'static, str> {
+        Cow::Owned(self.to_string())
+    }
+
+    fn from_static(atom: Cow<'static, str>) -> Self {
+        atom
+    }
+}
+
+macro_rules! int_atom {
+    ($($t:ty),*) => {
+        $(
+            impl TokenAtom for $t {
+                type Key = $t;
+                type Static = $t;
+
+                fn from_key(key: &$t) -> Self {
+                    *key
+                }
+
+                fn key_to_num(key: &$t) -> Option<u64> {
+                    u64::try_from(*key).ok()
+                }
+
+                fn to_static(&self) -> $t {
+                    *self
+                }
+
+                fn from_static(atom: $t) -> Self {
+                    atom
+                }
+            }
+        )*
+    };
+}
+
+int_atom!(u8, u16, u32, u64, usize);
+
+/// Token is the smallest unit of inserting subject. Normal tokens hold an atom `K`,
+/// which is a string by default, see [`TokenAtom`]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Token<'a, K = Cow<'a, str>> {
+    /// normal one represented by an atom, a str by default. It is borrowed from the source unless
+    /// the parser has to transform it, e.g. folding its case. It is always
+    /// literal, so `Normal("*")` only matches the key `*` and is never a wildcard
+    Normal(K),
+    /// wildcard which will always match a single token. It may carry a
+    /// capture name, e.g. `id` in `a.:id.c`, to bind the matched token to it
+    OneWildcard(Option<Cow<'a, str>>),
+    /// wildcard which will match a single token equal to any of the alternatives.
+    /// Alternatives are compared as a set
+    OneOf(Vec<K>),
+    /// wildcard which will match a single token that is a decimal number
+    /// within the inclusive range, e.g. `[1-100]` matches `42`
+    NumRange(u64, u64),
     /// wildcard which will always match one or more tokens
     /// but it can only appear at the end of subject
     MultiWildcard
 }
 
+impl<'a> From<&'a str> for Token<'a> {
+    fn from(s: &'a str) -> Token<'a> {
+        Token::Normal(Cow::Borrowed(s))
+    }
+}
+
 /// A Wrapper for a vector of Tokens
-#[derive(Debug, Default, PartialEq, Hash)]
-pub struct Tokens<'a>(pub(crate) Vec<Token<'a>>);
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Tokens<'a, K = Cow<'a, str>>(pub(crate) Vec<Token<'a, K>>);
+
+// implemented by hand, since deriving it would require `K: Default`
+impl<'a, K> Default for Tokens<'a, K> {
+    fn default() -> Self {
+        Tokens(Vec::new())
+    }
+}
 
-impl<'a> From<Vec<Token<'a>>> for Tokens<'a> {
-    fn from(v: Vec<Token<'a>>) -> Tokens<'a> {
+impl<'a, K> From<Vec<Token<'a, K>>> for Tokens<'a, K> {
+    fn from(v: Vec<Token<'a, K>>) -> Tokens<'a, K> {
         Tokens(v)
     }
 }
 
+impl<'a, K> Index<usize> for Tokens<'a, K> {
+    type Output = Token<'a, K>;
+
+    fn index(&self, i: usize) -> &Token<'a, K> {
+        &self.0[i]
+    }
+}
+
+impl<'a, K: TokenAtom> Token<'a, K> {
+    /// Returns a token owning all its strings, which no longer borrows the source
+    pub fn to_static(&self) -> Token<'static, K::Static> {
+        let own = |s: &Cow<'a, str>| Cow::Owned(s.to_string());
+        match self {
+            Token::Normal(s) => Token::Normal(s.to_static()),
+            Token::OneWildcard(name) => Token::OneWildcard(name.as_ref().map(own)),
+            Token::OneOf(alternatives) => Token::OneOf(alternatives.iter().map(K::to_static).collect()),
+            Token::NumRange(lo, hi) => Token::NumRange(*lo, *hi),
+            Token::MultiWildcard => Token::MultiWildcard,
+        }
+    }
+
+    /// Turns a token returned by `to_static` back to a token of atom `K`
+    pub(crate) fn from_static(token: &Token<'static, K::Static>) -> Self {
+        match token {
+            Token::Normal(s) => Token::Normal(K::from_static(s.clone())),
+            Token::OneWildcard(name) => Token::OneWildcard(name.clone()),
+            Token::OneOf(alternatives) => Token::OneOf(alternatives.iter().cloned().map(K::from_static).collect()),
+            Token::NumRange(lo, hi) => Token::NumRange(*lo, *hi),
+            Token::MultiWildcard => Token::MultiWildcard,
+        }
+    }
+}
+
+impl<'a, K: TokenAtom> Tokens<'a, K> {
+    /// Returns tokens owning all their strings, which no longer borrow the source
+    pub fn to_static(&self) -> Tokens<'static, K::Static> {
+        Tokens(self.0.iter().map(|t| t.to_static()).collect())
+    }
+
+    /// Turns tokens returned by `to_static` back to tokens of atom `K`
+    pub(crate) fn from_static(tokens: &Tokens<'static, K::Static>) -> Self {
+        Tokens(tokens.0.iter().map(Token::from_static).collect())
+    }
+}
+
 impl<'a> Tokens<'a> {
+
+    /// Returns a builder to build tokens one by one
+    pub fn builder() -> TokensBuilder<'a> {
+        TokensBuilder::new()
+    }
+
+    /// Renders the tokens back to the textual form seperated by `sep`, with `owc` for
+    /// one-token wildcards and `mwc` for the multi wildcard, which the `CommonTokenParser`
+    /// with the same configuration parses back to the same tokens. `OneOf` tokens are
+    /// rendered as `(b|c)` and `NumRange` tokens as `[1-100]`. Capture names are dropped,
+    /// since the prefix of named wildcards is not known here
+    pub fn render(&self, sep: char, owc: &str, mwc: &str) -> String {
+        let mut out = String::new();
+        for (i, token) in self.0.iter().enumerate() {
+            if i > 0 {
+                out.push(sep);
+            }
+            match token {
+                Token::Normal(s) => out.push_str(s),
+                Token::OneWildcard(_) => out.push_str(owc),
+                Token::OneOf(alternatives) => {
+                    out.push('(');
+                    out.push_str(&alternatives.join("|"));
+                    out.push(')');
+                },
+                Token::NumRange(lo, hi) => out.push_str(&format!("[{}-{}]", lo, hi)),
+                Token::MultiWildcard => out.push_str(mwc),
+            }
+        }
+        out
+    }
+}
+
+impl<'a, K: TokenAtom> Tokens<'a, K> {
+    /// Number of tokens
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    /// Whether there is no token
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// The token at index i, or None if out of bounds
+    pub fn get(&self, i: usize) -> Option<&Token<'a, K>> {
+        self.0.get(i)
+    }
+
+    /// Iterates over the tokens in order
+    pub fn iter(&self) -> std::slice::Iter<'_, Token<'a, K>> {
+        self.0.iter()
+    }
+
     /// Whether it contains wildcards 
     pub fn has_no_wildcard(&self) -> bool {
         self.0.iter()
@@ -37,8 +243,25 @@ impl<'a> Tokens<'a> {
         .is_some()
     }
 
-    /// Whether tokens is consistent with keys
-    pub fn match_keys(&self, keys: impl AsRef<[&'a str]>) -> bool {
+    /// Whether the last token is a multi wildcard
+    pub fn ends_with_multi_wildcard(&self) -> bool {
+        matches!(self.0.last(), Some(Token::MultiWildcard))
+    }
+
+    /// Numbers of single-token wildcards and multi wildcards.
+    /// `OneOf` and `NumRange` tokens are counted as single-token wildcards
+    pub fn wildcard_count(&self) -> (usize, usize) {
+        self.0.iter().fold((0, 0), |(owc, mwc), t| match t {
+            Token::Normal(_) => (owc, mwc),
+            Token::OneWildcard(_) | Token::OneOf(_) | Token::NumRange(..) => (owc + 1, mwc),
+            Token::MultiWildcard => (owc, mwc + 1),
+        })
+    }
+
+    /// Whether tokens is consistent with keys, exactly as `find` matches them.
+    /// The multi wildcard matches one or more keys, so `>` does not match
+    /// empty keys and `a.>` does not match `a`. Empty tokens only match empty keys
+    pub fn match_keys<'k>(&self, keys: impl AsRef<[&'k K::Key]>) -> bool {
         let keys = keys.as_ref();
         // If `tokens` is longer than `keys`, these two is inconsistent
         if self.0.len() > keys.len() { return false; }
@@ -55,13 +278,237 @@ impl<'a> Tokens<'a> {
             .try_for_each(|(t, k)| {
                 match t {
                     // Some(()) means true here
-                    Token::Normal(s) if s == k => Some(()),
-                    Token::OneWildcard | Token::MultiWildcard => Some(()),
+                    Token::Normal(s) if s.borrow() == *k => Some(()),
+                    Token::OneOf(alternatives) if alternatives.iter().any(|a| a.borrow() == *k) => Some(()),
+                    Token::NumRange(lo, hi) if in_range::<K>(*lo, *hi, k) => Some(()),
+                    Token::OneWildcard(_) | Token::MultiWildcard => Some(()),
                     // None means false here and will short-circurt
                     _ => None 
                 }
             }).is_some()
     }
+
+    /// Returns the remaining tokens if tokens start with `prefix`, e.g. stripping
+    /// `tenant.*` from `tenant.*.orders.>` gives `orders.>`. Tokens are compared as
+    /// patterns rather than matched: a wildcard in `prefix` only strips the same kind
+    /// of wildcard, capture names are ignored and `OneOf` alternatives are compared
+    /// as a set. Returns None if tokens do not start with `prefix`
+    pub fn strip_prefix(&self, prefix: &Tokens<K>) -> Option<Tokens<'a, K>> {
+        if prefix.0.len() > self.0.len() {
+            return None;
+        }
+        let (head, rest) = self.0.split_at(prefix.0.len());
+        if head.iter().zip(prefix.0.iter()).all(|(t, p)| same_token(t, p)) {
+            Some(rest.to_vec().into())
+        } else {
+            None
+        }
+    }
+
+    /// Returns the leading tokens if tokens end with `suffix`, e.g. stripping
+    /// `*.created` from `orders.*.created` gives `orders`. Tokens are compared
+    /// the same way as `strip_prefix`. Returns None if tokens do not end with `suffix`
+    pub fn strip_suffix(&self, suffix: &Tokens<K>) -> Option<Tokens<'a, K>> {
+        if suffix.0.len() > self.0.len() {
+            return None;
+        }
+        let (rest, tail) = self.0.split_at(self.0.len() - suffix.0.len());
+        if tail.iter().zip(suffix.0.iter()).all(|(t, s)| same_token(t, s)) {
+            Some(rest.to_vec().into())
+        } else {
+            None
+        }
+    }
+}
+
+/// Whether two tokens are the same pattern, ignoring capture names
+/// and comparing `OneOf` alternatives as a set
+fn same_token<K: TokenAtom>(t1: &Token<K>, t2: &Token<K>) -> bool {
+    match (t1, t2) {
+        (Token::Normal(s1), Token::Normal(s2)) => s1 == s2,
+        (Token::OneWildcard(_), Token::OneWildcard(_)) => true,
+        (Token::OneOf(a1), Token::OneOf(a2)) => normalize_alternatives(a1) == normalize_alternatives(a2),
+        (Token::NumRange(lo1, hi1), Token::NumRange(lo2, hi2)) => lo1 == lo2 && hi1 == hi2,
+        (Token::MultiWildcard, Token::MultiWildcard) => true,
+        _ => false,
+    }
+}
+
+impl<'a, K: TokenAtom> Tokens<'a, K> {
+    /// Checks whether tokens are well-formed: the multi wildcard can only appear
+    /// at the end, every `OneOf` token has at least one alternative and
+    /// every `NumRange` token has its lower bound not greater than its upper bound.
+    /// Tokens returned by a parser are always well-formed, but hand-built ones may not
+    pub fn validate(&self) -> Result<(), TrieError> {
+        self.0.iter().enumerate().try_fold(false, |has_mwc, (i, t)| {
+            if has_mwc {
+                return Err(TrieError::TokenAfterMwc(i));
+            }
+            match t {
+                Token::OneOf(alternatives) if alternatives.is_empty() => Err(TrieError::EmptyToken(i)),
+                Token::NumRange(lo, hi) if lo > hi => Err(TrieError::EmptyToken(i)),
+                Token::MultiWildcard => Ok(true),
+                _ => Ok(false),
+            }
+        })?;
+        Ok(())
+    }
+
+    /// Capture names of the one-token wildcards in order, None for unnamed ones
+    pub(crate) fn capture_names(&self) -> Vec<Option<Cow<'a, str>>> {
+        self.0.iter()
+            .filter_map(|t| match t {
+                Token::OneWildcard(name) => Some(name.clone()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Tokens without the trailing multi wildcard
+    pub(crate) fn strip_mwc(&self) -> &[Token<'a, K>] {
+        match self.0.split_last() {
+            Some((Token::MultiWildcard, rest)) => rest,
+            _ => &self.0,
+        }
+    }
+
+    /// Whether `keys` starts with a sequence consistent with tokens.
+    /// The trailing multi wildcard is ignored
+    pub(crate) fn match_keys_prefix<'k>(&self, keys: impl AsRef<[&'k K::Key]>) -> bool {
+        let prefix = self.strip_mwc();
+        let keys = keys.as_ref();
+        prefix.len() <= keys.len() && prefix.iter().zip(keys.iter())
+            .all(|(t, k)| match t {
+                Token::Normal(s) => s.borrow() == *k,
+                Token::OneOf(alternatives) => alternatives.iter().any(|a| a.borrow() == *k),
+                Token::NumRange(lo, hi) => in_range::<K>(*lo, *hi, k),
+                Token::OneWildcard(_) | Token::MultiWildcard => true,
+            })
+    }
+}
+
+/// Sorts alternatives and removes duplicates, so that alternatives can be
+/// compared as a set
+pub(crate) fn normalize_alternatives<K: Ord + Clone>(alternatives: &[K]) -> Vec<K> {
+    let mut alternatives = alternatives.to_vec();
+    alternatives.sort_unstable();
+    alternatives.dedup();
+    alternatives
+}
+
+/// Builds tokens one by one without parsing a string, e.g.
+/// `Tokens::builder().normal("a").one_wildcard().multi_wildcard().build()`.
+/// `build` checks the tokens like `Tokens::validate`, so a token added after
+/// the multi wildcard is reported instead of producing a pattern that never matches
+#[derive(Debug, Default, Clone)]
+pub struct TokensBuilder<'a> {
+    tokens: Vec<Token<'a>>,
+}
+
+impl<'a> TokensBuilder<'a> {
+    /// Returns an empty builder
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Appends a normal token, which is always literal
+    pub fn normal(self, s: &'a str) -> Self {
+        self.token(Token::from(s))
+    }
+
+    /// Appends a one-token wildcard without capture name
+    pub fn one_wildcard(self) -> Self {
+        self.token(Token::OneWildcard(None))
+    }
+
+    /// Appends the multi wildcard, which finalizes the tokens: nothing can follow it
+    pub fn multi_wildcard(self) -> Self {
+        self.token(Token::MultiWildcard)
+    }
+
+    /// Appends any token, e.g. a `OneOf` or a `NumRange`
+    pub fn token(mut self, token: Token<'a>) -> Self {
+        self.tokens.push(token);
+        self
+    }
+
+    /// Returns the tokens, or the error of `Tokens::validate` if they are not well-formed
+    pub fn build(self) -> Result<Tokens<'a>, TrieError> {
+        let tokens = Tokens(self.tokens);
+        tokens.validate()?;
+        Ok(tokens)
+    }
+}
+
+/// Whether the key is a decimal number within the inclusive range `lo..=hi`.
+/// Only plain ASCII digits are numbers, so `+42` and ` 42` are not
+pub(crate) fn in_range<K: TokenAtom>(lo: u64, hi: u64, key: &K::Key) -> bool {
+    K::key_to_num(key).is_some_and(|n| lo <= n && n <= hi)
+}
+
+/// Parses a key of only ASCII digits to a number, None if it is not one or overflows
+pub(crate) fn parse_num(key: &str) -> Option<u64> {
+    if key.is_empty() || !key.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    key.parse().ok()
+}
+
+/// Whether some subject matches both patterns, e.g. `a.*` and `*.b` overlap at
+/// `a.b` while `a.x` and `a.y` never do. The trailing multi wildcard matches one
+/// or more tokens on either side, so `a.>` overlaps `a.b.c` but not `a`, and two
+/// patterns both ending with it always overlap if their prefixes do
+pub fn patterns_overlap<K: TokenAtom>(p1: &Tokens<K>, p2: &Tokens<K>) -> bool {
+    let (a, b) = (p1.strip_mwc(), p2.strip_mwc());
+    let (a_mwc, b_mwc) = (p1.ends_with_multi_wildcard(), p2.ends_with_multi_wildcard());
+    let common = a.len().min(b.len());
+    let longer = if a.len() < b.len() { b } else { a };
+    // tokens beyond the shorter prefix can only be covered by its multi wildcard
+    let lengths_fit = match (a_mwc, b_mwc) {
+        (false, false) => a.len() == b.len(),
+        // the multi wildcard needs at least one token of the other pattern
+        (true, false) => b.len() > a.len(),
+        (false, true) => a.len() > b.len(),
+        (true, true) => true,
+    };
+    lengths_fit
+        && a.iter().zip(b.iter()).all(|(x, y)| tokens_overlap(x, y))
+        && longer[common..].iter().all(satisfiable)
+}
+
+/// Whether some single token matches both tokens. The tokens may hold different
+/// atoms with the same key, e.g. strings borrowed for different lifetimes
+pub(crate) fn tokens_overlap<K1, K2>(t1: &Token<K1>, t2: &Token<K2>) -> bool
+where
+    K1: TokenAtom,
+    K2: TokenAtom<Key = K1::Key>,
+{
+    match (t1, t2) {
+        (Token::OneWildcard(_) | Token::MultiWildcard, t) => satisfiable(t),
+        (t, Token::OneWildcard(_) | Token::MultiWildcard) => satisfiable(t),
+        (Token::Normal(s), Token::Normal(t)) => s.key() == t.key(),
+        (Token::Normal(s), Token::OneOf(alternatives)) => alternatives.iter().any(|a| a.key() == s.key()),
+        (Token::OneOf(alternatives), Token::Normal(s)) => alternatives.iter().any(|a| a.key() == s.key()),
+        (Token::Normal(s), Token::NumRange(lo, hi)) => in_range::<K1>(*lo, *hi, s.key()),
+        (Token::NumRange(lo, hi), Token::Normal(s)) => in_range::<K2>(*lo, *hi, s.key()),
+        (Token::OneOf(x), Token::OneOf(y)) => x.iter().any(|a| y.iter().any(|b| a.key() == b.key())),
+        (Token::OneOf(alternatives), Token::NumRange(lo, hi)) => {
+            alternatives.iter().any(|a| in_range::<K1>(*lo, *hi, a.key()))
+        },
+        (Token::NumRange(lo, hi), Token::OneOf(alternatives)) => {
+            alternatives.iter().any(|a| in_range::<K2>(*lo, *hi, a.key()))
+        },
+        (Token::NumRange(lo1, hi1), Token::NumRange(lo2, hi2)) => lo1.max(lo2) <= hi1.min(hi2),
+    }
+}
+
+/// Whether some single token matches the token
+fn satisfiable<K>(t: &Token<K>) -> bool {
+    match t {
+        Token::OneOf(alternatives) => !alternatives.is_empty(),
+        Token::NumRange(lo, hi) => lo <= hi,
+        _ => true,
+    }
 }
 
 /// Can parse bytes to token vector
@@ -72,6 +519,32 @@ pub trait TokenParser {
     fn parse_tokens<'a>(&self, source: &'a str) -> Result<Tokens<'a>, Self::Error>;
 }
 
+/// How to fold the case of tokens
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FoldMode {
+    /// Only folds ASCII letters, which is cheap
+    Ascii,
+    /// Folds with the full Unicode lowercase mapping of `str::to_lowercase`.
+    /// The mapping is locale-insensitive, e.g. the Turkish `İ` becomes
+    /// `i` followed by a combining dot above rather than a plain `i`
+    Unicode,
+}
+
+impl FoldMode {
+    /// Folds the case of `s`. It only allocates when some char changes
+    pub fn fold<'a>(&self, s: &'a str) -> Cow<'a, str> {
+        match self {
+            FoldMode::Ascii if s.bytes().any(|b| b.is_ascii_uppercase()) => {
+                Cow::Owned(s.to_ascii_lowercase())
+            },
+            FoldMode::Unicode if !s.chars().all(|c| c.to_lowercase().eq(std::iter::once(c))) => {
+                Cow::Owned(s.to_lowercase())
+            },
+            _ => Cow::Borrowed(s),
+        }
+    }
+}
+
 /// Common configurations to parse something to tokens
 pub struct CommonTokenParser<'b> {
     /// char to seperate tokens
@@ -80,46 +553,327 @@ pub struct CommonTokenParser<'b> {
     one_wildcard_chars: &'b str,
     /// chars to represent multi-token wildcard
     multi_wildcard_chars: &'b str,
+    /// whether to trim whitespaces around each token
+    trim: bool,
+    /// how to fold the case of tokens, None means case-sensitive
+    fold_mode: Option<FoldMode>,
+    /// whether wildcard chars inside a normal token are rejected
+    strict_wildcards: bool,
+    /// custom transformation applied to each normal token, None means no-op
+    token_transform: Option<TokenTransform<'b>>,
+    /// char to prefix a named one-token wildcard, None means no named wildcards
+    capture_prefix: Option<char>,
+    /// predicate of chars allowed in a normal token, None means any char is allowed
+    allowed_chars: Option<fn(char) -> bool>,
+    /// whether an empty source is parsed to no tokens instead of one empty token
+    treat_empty_as_no_tokens: bool,
+    /// whether `(b|c)` and `[1-5]` are parsed as alternatives and ranges
+    extended_syntax: bool,
 }
 
+/// Custom transformation of a normal token, which borrows the token if it is unchanged
+pub type TokenTransform<'b> = Box<dyn for<'x> Fn(&'x str) -> Cow<'x, str> + 'b>;
+
 impl<'b> CommonTokenParser<'b> {
     /// Returns a CommonTokenParser instance
     pub fn new(sc: char, owc: &'b str, mwc: &'b str) -> Self {
         Self {
             seperate_char: sc,
             one_wildcard_chars: owc,
-            multi_wildcard_chars: mwc
+            multi_wildcard_chars: mwc,
+            trim: false,
+            fold_mode: None,
+            strict_wildcards: false,
+            token_transform: None,
+            capture_prefix: None,
+            allowed_chars: None,
+            treat_empty_as_no_tokens: false,
+            extended_syntax: true,
         }
     }
+
+    /// Returns a parser for MQTT topic filters: `/` seperates levels, `+` is the
+    /// single-level wildcard and `#` is the multi-level wildcard. Wildcards are
+    /// strict, so `sport#` and `sp+ort` are rejected as MQTT requires. The extended
+    /// syntax is off, so `(a|b)` and `[1-5]` are literal topic levels
+    pub fn mqtt() -> Self {
+        Self::new('/', "+", "#").strict_wildcards(true).extended_syntax(false)
+    }
+
+    /// Returns a parser for NATS subjects: `.` seperates tokens, `*` matches a single
+    /// token and `>` matches one or more trailing tokens. This is the model the crate
+    /// is primarily designed for. Use `parse_nats_subject` to also validate the subject.
+    /// The extended syntax is off, so `(a|b)` and `[1-5]` are literal tokens
+    pub fn nats() -> Self {
+        Self::new('.', "*", ">").extended_syntax(false)
+    }
+
+    /// Parses a NATS subject to tokens, rejecting what NATS disallows: empty subjects,
+    /// leading or trailing seperators, empty tokens and `>` not at end
+    pub fn parse_nats_subject<'a>(&self, source: &'a str) -> Result<Tokens<'a>, NatsSubjectError> {
+        if source.is_empty() {
+            return Err(NatsSubjectError::EmptySubject);
+        }
+        if source.starts_with(self.seperate_char) {
+            return Err(NatsSubjectError::LeadingSeparator);
+        }
+        if source.ends_with(self.seperate_char) {
+            return Err(NatsSubjectError::TrailingSeparator);
+        }
+        let tokens = self.parse_tokens_spanned(source)?;
+        if let Some((_, span)) = tokens.iter().find(|(t, _)| matches!(t, Token::Normal(s) if s.is_empty())) {
+            return Err(NatsSubjectError::EmptyToken { span: span.clone() });
+        }
+        Ok(tokens.into_iter().map(|(token, _)| token).collect::<Vec<_>>().into())
+    }
+
+    /// Parses `:id` to the capture name `id`, returns None if named wildcards
+    /// are disabled, the token does not start with the prefix or the name is empty
+    fn parse_capture<'a>(&self, token: &'a str) -> Option<&'a str> {
+        let name = token.strip_prefix(self.capture_prefix?)?;
+        if name.is_empty() { None } else { Some(name) }
+    }
+
+    /// Parses `(b|c)` to the alternatives `b` and `c`, returns None if the extended
+    /// syntax is off or the token is not surrounded by parentheses, and an error
+    /// if an alternative contains a char which is not allowed
+    fn parse_alternatives<'a>(&self, token: &'a str) -> Option<Result<Vec<Cow<'a, str>>, CommonTokenError>> {
+        if !self.extended_syntax {
+            return None;
+        }
+        let inner = token.strip_prefix('(')?.strip_suffix(')')?;
+        let alternatives = inner
+            .split('|')
+            .map(|s| {
+                let s = if self.trim { s.trim() } else { s };
+                match self.invalid_char(s) {
+                    Some(ch) => Err(CommonTokenError::InvalidChar { token: token.to_string(), ch }),
+                    None => Ok(self.normalize_key(s)),
+                }
+            })
+            .collect::<Result<Vec<_>, _>>();
+        Some(alternatives.map(|alternatives| normalize_alternatives(&alternatives)))
+    }
+
+    /// Parses `[1-100]` to the inclusive range from 1 to 100, returns None if the
+    /// extended syntax is off, the token is not surrounded by brackets, either bound
+    /// is not a number or the lower bound is greater than the upper bound
+    fn parse_range(&self, token: &str) -> Option<(u64, u64)> {
+        if !self.extended_syntax {
+            return None;
+        }
+        let inner = token.strip_prefix('[')?.strip_suffix(']')?;
+        let (lo, hi) = inner.split_once('-')?;
+        let (lo, hi) = (parse_num(lo)?, parse_num(hi)?);
+        if lo <= hi { Some((lo, hi)) } else { None }
+    }
+
+    /// Returns the first char of the normal token, alternative or capture name which is not allowed, if any
+    fn invalid_char(&self, token: &str) -> Option<char> {
+        let allowed = self.allowed_chars?;
+        token.chars().find(|&c| !allowed(c))
+    }
+
+    /// Parses str to token sequence, and returns each token with its byte range in the source.
+    /// The range of a trimmed token excludes the trimmed whitespaces
+    pub fn parse_tokens_spanned<'a>(&self, source: &'a str) -> Result<Vec<(Token<'a>, Range<usize>)>, CommonTokenError> {
+        self.parse_spanned_with(source, self.seperate_char)
+    }
+
+    /// Parses str to token sequence like `parse_tokens`, but seperates tokens by `sep`
+    /// instead of the configured char for this call only. Useful when the seperator
+    /// varies per message, e.g. some subjects use `.` and others use `/`
+    pub fn parse_tokens_with<'a>(&self, source: &'a str, sep: char) -> Result<Tokens<'a>, CommonTokenError> {
+        Ok(self.parse_spanned_with(source, sep)?
+            .into_iter()
+            .map(|(token, _)| token)
+            .collect::<Vec<_>>()
+            .into())
+    }
+
+    /// Parses str to token sequence seperated by `sep`, with the byte range of each token
+    fn parse_spanned_with<'a>(&self, source: &'a str, sep: char) -> Result<Vec<(Token<'a>, Range<usize>)>, CommonTokenError> {
+        if self.treat_empty_as_no_tokens && source.is_empty() {
+            return Ok(vec![]);
+        }
+        Ok(source
+            .split(sep)
+            .map(|s| if self.trim { s.trim() } else { s })
+            .try_fold((vec![], None), |(mut vec, mwc_span), s| {
+                // every token is a sub-slice of the source
+                let start = s.as_ptr() as usize - source.as_ptr() as usize;
+                let span = start..start + s.len();
+                if let Some(mwc_span) = mwc_span {
+                    // token after mwc
+                    Err(CommonTokenError::MultiWildcardNotAtEnd { input: source.to_string(), span: mwc_span })
+                } else if s == self.one_wildcard_chars {
+                    vec.push((Token::OneWildcard(None), span));
+                    Ok((vec, None))
+                } else if s == self.multi_wildcard_chars {
+                    vec.push((Token::MultiWildcard, span.clone()));
+                    Ok((vec, Some(span)))
+                } else if let Some(name) = self.parse_capture(s) {
+                    if let Some(ch) = self.invalid_char(name) {
+                        return Err(CommonTokenError::InvalidChar { token: s.to_string(), ch });
+                    }
+                    vec.push((Token::OneWildcard(Some(Cow::Borrowed(name))), span));
+                    Ok((vec, None))
+                } else if self.strict_wildcards && contains_marker(s, self.one_wildcard_chars) {
+                    Err(CommonTokenError::OneWildcardInsideToken { span })
+                } else if self.strict_wildcards && contains_marker(s, self.multi_wildcard_chars) {
+                    Err(CommonTokenError::MultiWildcardInsideToken { span })
+                } else if let Some(alternatives) = self.parse_alternatives(s) {
+                    vec.push((Token::OneOf(alternatives?), span));
+                    Ok((vec, None))
+                } else if let Some((lo, hi)) = self.parse_range(s) {
+                    vec.push((Token::NumRange(lo, hi), span));
+                    Ok((vec, None))
+                } else if let Some(ch) = self.invalid_char(s) {
+                    Err(CommonTokenError::InvalidChar { token: s.to_string(), ch })
+                } else {
+                    vec.push((Token::Normal(self.normalize_key(s)), span));
+                    Ok((vec, None))
+                }
+            })?.0)
+    }
+
+    /// Normalizes a key the same way as normal tokens are normalized when parsing,
+    /// i.e. trims, transforms and folds its case according to the configuration.
+    /// Keys used to query a trie should be normalized by this method,
+    /// otherwise they may silently fail to match
+    pub fn normalize_key<'a>(&self, key: &'a str) -> Cow<'a, str> {
+        let key = if self.trim { key.trim() } else { key };
+        let key = match &self.token_transform {
+            Some(transform) => transform(key),
+            None => Cow::Borrowed(key),
+        };
+        match (self.fold_mode, key) {
+            (None, key) => key,
+            (Some(mode), Cow::Borrowed(key)) => mode.fold(key),
+            (Some(mode), Cow::Owned(key)) => Cow::Owned(mode.fold(&key).into_owned()),
+        }
+    }
+
+    /// Sets how to fold the case of normal tokens, so that matching becomes
+    /// case-insensitive. Wildcard markers are not folded. It is case-sensitive by default.
+    pub fn fold_case(mut self, mode: FoldMode) -> Self {
+        self.fold_mode = Some(mode);
+        self
+    }
+
+    /// Sets a transformation applied to each normal token and each alternative
+    /// after trimming and before folding, e.g. stripping a prefix or normalizing
+    /// synonyms. Wildcard markers are not transformed. It is a no-op by default.
+    pub fn token_transform(mut self, transform: impl for<'x> Fn(&'x str) -> Cow<'x, str> + 'b) -> Self {
+        self.token_transform = Some(Box::new(transform));
+        self
+    }
+
+    /// Sets the char to prefix a named one-token wildcard, e.g. with `:` the token
+    /// `:id` matches any single token like `*` and captures it as `id`, see
+    /// `Trie::find_captures`. The name is kept as is, without trimming or folding.
+    /// A token of only the prefix is a normal token. It is disabled by default.
+    pub fn capture_prefix(mut self, prefix: char) -> Self {
+        self.capture_prefix = Some(prefix);
+        self
+    }
+
+    /// Sets the predicate of chars allowed in a normal token, e.g. rejecting
+    /// spaces and control chars, so that invalid subjects are rejected when
+    /// parsing with `CommonTokenError::InvalidChar`. Each alternative of `(b|c)` and
+    /// each capture name are checked like a normal token, while wildcard markers,
+    /// the capture prefix and ranges are not. Any char is allowed by default.
+    pub fn allowed_chars(mut self, pred: fn(char) -> bool) -> Self {
+        self.allowed_chars = Some(pred);
+        self
+    }
+
+    /// Sets whether an empty source is parsed to no tokens, which corresponds to
+    /// the query `find(&[])` and the values stored at the root. Otherwise it is
+    /// parsed to a single empty normal token like any empty segment, which
+    /// matches the key `[""]`. It is off by default.
+    pub fn treat_empty_as_no_tokens(mut self, enabled: bool) -> Self {
+        self.treat_empty_as_no_tokens = enabled;
+        self
+    }
+
+    /// Sets whether a normal token containing the wildcard chars, e.g. `sp+ort`
+    /// or `sport#` in MQTT, is rejected. Such a token is parsed as a normal
+    /// token otherwise. It is off by default.
+    pub fn strict_wildcards(mut self, strict: bool) -> Self {
+        self.strict_wildcards = strict;
+        self
+    }
+
+    /// Sets whether the extended syntax is parsed: `(b|c)` as a token matching
+    /// either `b` or `c`, and `[1-5]` as a token matching a number from 1 to 5.
+    /// Such tokens are normal tokens otherwise, as in MQTT and NATS where they are
+    /// valid literal levels. It is on by default, and off in `mqtt` and `nats`.
+    pub fn extended_syntax(mut self, enabled: bool) -> Self {
+        self.extended_syntax = enabled;
+        self
+    }
+
+    /// Sets whether leading and trailing whitespaces of each token are trimmed
+    /// before parsing. A token consisting of only whitespaces becomes an empty
+    /// token. It is off by default.
+    pub fn trim(mut self, trim: bool) -> Self {
+        self.trim = trim;
+        self
+    }
 }
 
 #[derive(Error, Debug, PartialEq, Eq)]
 pub enum CommonTokenError {
-    #[error("multi wildcard not at end")]
-    MultiWildcardNotAtEnd,
+    /// The multi wildcard at the span of the input is followed by other tokens
+    #[error("multi wildcard not at end of {input:?} (at {span:?})")]
+    MultiWildcardNotAtEnd { input: String, span: Range<usize> },
+    /// The token at the span mixes the one wildcard with other chars, only in strict mode
+    #[error("one wildcard inside a token (at {span:?})")]
+    OneWildcardInsideToken { span: Range<usize> },
+    /// The token at the span mixes the multi wildcard with other chars, only in strict mode
+    #[error("multi wildcard inside a token (at {span:?})")]
+    MultiWildcardInsideToken { span: Range<usize> },
+    /// The normal token, one of its alternatives or its capture name contains a char
+    /// which is not allowed, see `CommonTokenParser::allowed_chars`
+    #[error("invalid char {ch:?} in token {token:?}")]
+    InvalidChar { token: String, ch: char },
+}
+
+/// Errors of an invalid NATS subject
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum NatsSubjectError {
+    /// The subject is empty
+    #[error("empty subject")]
+    EmptySubject,
+    /// The subject starts with a seperator
+    #[error("subject starts with a seperator")]
+    LeadingSeparator,
+    /// The subject ends with a seperator
+    #[error("subject ends with a seperator")]
+    TrailingSeparator,
+    /// The token at the span of the subject is empty
+    #[error("empty token (at {span:?})")]
+    EmptyToken { span: Range<usize> },
+    /// The subject can not be parsed, e.g. `>` is not at end
+    #[error("invalid subject: {0}")]
+    Parse(#[from] CommonTokenError),
+}
+
+/// Whether the non-empty wildcard marker appears in the token
+fn contains_marker(token: &str, marker: &str) -> bool {
+    !marker.is_empty() && token.contains(marker)
 }
 
 impl<'b> TokenParser for CommonTokenParser<'b> {
     type Error = CommonTokenError;
     
     fn parse_tokens<'a>(&self, source: &'a str) -> Result<Tokens<'a>, Self::Error> {
-        Ok(source
-            .split(self.seperate_char)
-            .try_fold((vec![], false), |(mut vec, has_mwc), s|
-                if has_mwc {
-                    // token after mwc
-                    Err(CommonTokenError::MultiWildcardNotAtEnd)
-                } else if s == self.one_wildcard_chars {
-                    vec.push(Token::OneWildcard);
-                    Ok((vec, false))
-                } else if s == self.multi_wildcard_chars {
-                    vec.push(Token::MultiWildcard);
-                    Ok((vec, true))
-                } else {
-                    vec.push(Token::Normal(s));
-                    Ok((vec, false))
-                }
-            )?.0.into())
+        Ok(self.parse_tokens_spanned(source)?
+            .into_iter()
+            .map(|(token, _)| token)
+            .collect::<Vec<_>>()
+            .into())
     }
 }
 
@@ -130,13 +884,13 @@ mod test {
     // macro to generate token conveniently
     macro_rules! token {
         (o) => {
-            Token::OneWildcard
+            Token::OneWildcard(None)
         };
         (m) => {
             Token::MultiWildcard
         };
         ($a:literal) => {
-            Token::Normal($a)
+            Token::from($a)
         }
     }
 
@@ -174,11 +928,252 @@ mod test {
             parser.parse_tokens(">")?,
             Tokens(vec![token!(m)])
         );
-        assert_eq!(parser.parse_tokens(">.a").unwrap_err(), CommonTokenError::MultiWildcardNotAtEnd);
+        assert_eq!(parser.parse_tokens(">.a").unwrap_err(), CommonTokenError::MultiWildcardNotAtEnd { input: ">.a".into(), span: 0..1 });
+        Ok(())
+    }
+
+    #[test]
+    fn test_common_token_parser_trim() -> Result<(), CommonTokenError> {
+        let parser = CommonTokenParser::new('.', "*", ">");
+        assert_eq!(
+            parser.parse_tokens("a . *")?,
+            Tokens(vec![token!("a "), token!(" *")])
+        );
+        let parser = parser.trim(true);
+        assert_eq!(
+            parser.parse_tokens(" a . b\t. c ")?,
+            Tokens(vec![token!("a"), token!("b"), token!("c")])
+        );
+        assert_eq!(
+            parser.parse_tokens("a . * . >")?,
+            Tokens(vec![token!("a"), token!(o), token!(m)])
+        );
+        assert_eq!(
+            parser.parse_tokens("a.  .b")?,
+            Tokens(vec![token!("a"), token!(""), token!("b")])
+        );
+        assert_eq!(parser.parse_tokens(" > . a").unwrap_err(), CommonTokenError::MultiWildcardNotAtEnd { input: " > . a".into(), span: 1..2 });
+        Ok(())
+    }
+
+    #[test]
+    fn test_common_token_parser_alternatives() -> Result<(), CommonTokenError> {
+        let parser = CommonTokenParser::new('.', "*", ">");
+        assert_eq!(
+            parser.parse_tokens("a.(c|b).d")?,
+            Tokens(vec![token!("a"), Token::OneOf(vec!["b".into(), "c".into()]), token!("d")])
+        );
+        assert_eq!(
+            parser.parse_tokens("(x|x)")?,
+            Tokens(vec![Token::OneOf(vec!["x".into()])])
+        );
+        assert_eq!(
+            parser.parse_tokens("(a.b")?,
+            Tokens(vec![token!("(a"), token!("b")])
+        );
+        assert_eq!(
+            CommonTokenParser::new('.', "*", ">").trim(true).parse_tokens("( b | c )")?,
+            Tokens(vec![Token::OneOf(vec!["b".into(), "c".into()])])
+        );
+        let tokens = parser.parse_tokens("a.(b|c).>")?;
+        assert!(!tokens.has_no_wildcard());
+        assert!(tokens.match_keys(vec!["a", "b", "x"]));
+        assert!(tokens.match_keys(vec!["a", "c", "x", "y"]));
+        assert!(!tokens.match_keys(vec!["a", "d", "x"]));
         Ok(())
     }
 
     #[test]
+    fn test_common_token_parser_num_range() -> Result<(), CommonTokenError> {
+        let parser = CommonTokenParser::new('.', "*", ">");
+        assert_eq!(
+            parser.parse_tokens("sensor.[1-100].temp")?,
+            Tokens(vec![token!("sensor"), Token::NumRange(1, 100), token!("temp")])
+        );
+        assert_eq!(parser.parse_tokens("[7-7]")?, Tokens(vec![Token::NumRange(7, 7)]));
+        // not a range: reversed bounds, missing bounds, signs or missing brackets
+        for source in ["[9-1]", "[1-]", "[-1-5]", "[+1-5]", "[1-5", "1-5"].iter() {
+            assert_eq!(parser.parse_tokens(source)?, Tokens(vec![Token::from(*source)]));
+        }
+        let tokens = parser.parse_tokens("sensor.[1-100].>")?;
+        assert_eq!(tokens.wildcard_count(), (1, 1));
+        assert!(tokens.match_keys(vec!["sensor", "42", "temp"]));
+        assert!(tokens.match_keys(vec!["sensor", "100", "temp"]));
+        assert!(tokens.match_keys(vec!["sensor", "007", "temp"]));
+        assert!(!tokens.match_keys(vec!["sensor", "0", "temp"]));
+        assert!(!tokens.match_keys(vec!["sensor", "101", "temp"]));
+        assert!(!tokens.match_keys(vec!["sensor", "+42", "temp"]));
+        assert!(!tokens.match_keys(vec!["sensor", "x", "temp"]));
+        assert!(!tokens.match_keys(vec!["sensor", "99999999999999999999999", "temp"]));
+        Ok(())
+    }
+
+    #[test]
+    fn test_common_token_parser_fold_case() -> Result<(), CommonTokenError> {
+        let parser = CommonTokenParser::new('.', "*", ">").fold_case(FoldMode::Ascii);
+        assert_eq!(
+            parser.parse_tokens("Foo.BAR.(X|y).*")?,
+            Tokens(vec![token!("foo"), token!("bar"), Token::OneOf(vec!["x".into(), "y".into()]), token!(o)])
+        );
+        // only allocates when the case changes
+        assert!(matches!(parser.normalize_key("foo"), Cow::Borrowed("foo")));
+        assert_eq!(parser.normalize_key("ÄBC"), "Äbc");
+        // the Turkish dotted capital I is not an ASCII letter
+        assert_eq!(parser.normalize_key("İSTANBUL"), "İstanbul");
+
+        let parser = CommonTokenParser::new('.', "*", ">").fold_case(FoldMode::Unicode);
+        assert_eq!(parser.normalize_key("ÄBC"), "äbc");
+        assert_eq!(parser.normalize_key("ΣΟΦΟΣ"), "σοφος");
+        // locale-insensitive: `İ` becomes `i` with a combining dot above, not a plain `i`
+        assert_eq!(parser.normalize_key("İSTANBUL"), "i\u{307}stanbul");
+        assert_ne!(parser.normalize_key("İSTANBUL"), parser.normalize_key("ISTANBUL"));
+        assert_eq!(parser.normalize_key("ıstanbul"), "ıstanbul");
+        assert_eq!(
+            parser.parse_tokens("İSTANBUL.>")?,
+            Tokens(vec![token!("i\u{307}stanbul"), token!(m)])
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_common_token_parser_spanned() -> Result<(), CommonTokenError> {
+        let parser = CommonTokenParser::new('.', "*", ">");
+        assert_eq!(
+            parser.parse_tokens_spanned("ab.*.(c|d).>")?,
+            vec![(token!("ab"), 0..2), (token!(o), 3..4), (Token::OneOf(vec!["c".into(), "d".into()]), 5..10), (token!(m), 11..12)]
+        );
+        assert_eq!(parser.parse_tokens_spanned("")?, vec![(token!(""), 0..0)]);
+        assert_eq!(parser.parse_tokens_spanned("é.b")?, vec![(token!("é"), 0..2), (token!("b"), 3..4)]);
+        assert_eq!(
+            parser.parse_tokens_spanned("a.>.b").unwrap_err(),
+            CommonTokenError::MultiWildcardNotAtEnd { input: "a.>.b".into(), span: 2..3 }
+        );
+        let parser = parser.trim(true);
+        assert_eq!(
+            parser.parse_tokens_spanned(" a .  b")?,
+            vec![(token!("a"), 1..2), (token!("b"), 6..7)]
+        );
+        assert_eq!(
+            parser.parse_tokens_spanned("a.  > .b").unwrap_err().to_string(),
+            "multi wildcard not at end of \"a.  > .b\" (at 4..5)"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_tokens_as_map_key() -> Result<(), CommonTokenError> {
+        use std::collections::HashMap;
+
+        let parser = CommonTokenParser::new('.', "*", ">");
+        let mut metadata = HashMap::new();
+        let tokens = parser.parse_tokens("a.*.(c|b)")?;
+        metadata.insert(tokens.clone(), "first");
+        metadata.insert(parser.parse_tokens("a.>")?, "second");
+        // tokens parsed from equivalent patterns are the same key
+        assert_eq!(metadata.get(&parser.parse_tokens("a.*.(b|c)")?), Some(&"first"));
+        assert_eq!(metadata.get(&tokens), Some(&"first"));
+        assert_eq!(metadata.get(&parser.parse_tokens("a.*")?), None);
+        Ok(())
+    }
+
+    #[test]
+    fn test_patterns_overlap() -> Result<(), CommonTokenError> {
+        let parser = CommonTokenParser::new('.', "*", ">");
+        let overlap = |p1: &str, p2: &str| -> Result<bool, CommonTokenError> {
+            let (t1, t2) = (parser.parse_tokens(p1)?, parser.parse_tokens(p2)?);
+            let result = patterns_overlap(&t1, &t2);
+            assert_eq!(result, patterns_overlap(&t2, &t1), "{} and {} is not symmetric", p1, p2);
+            Ok(result)
+        };
+        assert!(overlap("a.*", "*.b")?);
+        assert!(!overlap("a.x", "a.y")?);
+        assert!(!overlap("a.b", "a.b.c")?);
+        assert!(overlap("a.>", "a.b.c")?);
+        assert!(!overlap("a.>", "a")?);
+        assert!(overlap("a.>", "*.b")?);
+        assert!(overlap("a.>", "*.b.>")?);
+        assert!(!overlap("a.>", "b.>")?);
+        assert!(overlap(">", "a.b.c")?);
+        assert!(overlap(">", ">")?);
+        assert!(overlap("a.(b|c)", "a.(c|d)")?);
+        assert!(!overlap("a.(b|c)", "a.(d|e)")?);
+        assert!(overlap("a.(b|c)", "a.c")?);
+        assert!(overlap("[1-10]", "[10-20]")?);
+        assert!(!overlap("[1-9]", "[10-20]")?);
+        assert!(overlap("[1-10]", "(5|x)")?);
+        assert!(!overlap("[1-10]", "11")?);
+        // an empty alternative list matches nothing, even under the other's multi wildcard
+        let empty = Tokens(vec![token!("a"), Token::OneOf(vec![])]);
+        assert!(!patterns_overlap(&empty, &parser.parse_tokens("a.>")?));
+        let no_tokens: Tokens = Tokens(vec![]);
+        assert!(patterns_overlap(&no_tokens, &no_tokens));
+        assert!(!patterns_overlap(&Tokens(vec![]), &parser.parse_tokens(">")?));
+        Ok(())
+    }
+
+    #[test]
+    fn test_tokens_builder() -> Result<(), CommonTokenError> {
+        let parser = CommonTokenParser::new('.', "*", ">");
+        assert_eq!(
+            Tokens::builder().normal("a").one_wildcard().token(Token::NumRange(1, 9)).multi_wildcard().build(),
+            Ok(parser.parse_tokens("a.*.[1-9].>")?)
+        );
+        assert_eq!(TokensBuilder::new().build(), Ok(Tokens(vec![])));
+        assert_eq!(
+            Tokens::builder().normal("a").multi_wildcard().normal("b").build(),
+            Err(TrieError::TokenAfterMwc(2))
+        );
+        assert_eq!(
+            Tokens::builder().token(Token::OneOf(vec![])).build(),
+            Err(TrieError::EmptyToken(0))
+        );
+        // normal tokens are literal, even if they look like wildcards
+        let tokens = Tokens::builder().normal("*").normal(">").build().unwrap();
+        assert!(tokens.has_no_wildcard());
+        Ok(())
+    }
+
+    #[test]
+    fn test_validate() {
+        assert_eq!(Tokens(vec![token!("a"), token!(o), token!(m)]).validate(), Ok(()));
+        assert_eq!(Tokens::<Cow<str>>(vec![]).validate(), Ok(()));
+        assert_eq!(
+            Tokens(vec![token!("a"), token!(m), token!("b")]).validate(),
+            Err(TrieError::TokenAfterMwc(2))
+        );
+        assert_eq!(
+            Tokens::<Cow<str>>(vec![token!(m), token!(m)]).validate(),
+            Err(TrieError::TokenAfterMwc(1))
+        );
+        assert_eq!(
+            Tokens(vec![token!("a"), Token::OneOf(vec![])]).validate(),
+            Err(TrieError::EmptyToken(1))
+        );
+        assert_eq!(
+            Tokens::<Cow<str>>(vec![Token::NumRange(5, 1)]).validate(),
+            Err(TrieError::EmptyToken(0))
+        );
+        let parser = CommonTokenParser::new('.', "*", ">");
+        let err: TrieError = parser.parse_tokens(">.a").unwrap_err().into();
+        assert_eq!(err, TrieError::Parse(CommonTokenError::MultiWildcardNotAtEnd { input: ">.a".into(), span: 0..1 }));
+    }
+
+    #[test]
+    fn test_wildcard_introspection() {
+        let tokens = Tokens(vec![token!("a"), token!(o), Token::OneOf(vec!["b".into()]), token!(m)]);
+        assert!(tokens.ends_with_multi_wildcard());
+        assert_eq!(tokens.wildcard_count(), (2, 1));
+        let tokens = Tokens(vec![token!(o), token!("a"), token!(o)]);
+        assert!(!tokens.ends_with_multi_wildcard());
+        assert_eq!(tokens.wildcard_count(), (2, 0));
+        let tokens = Tokens(vec![token!("a")]);
+        assert!(!tokens.ends_with_multi_wildcard());
+        assert_eq!(tokens.wildcard_count(), (0, 0));
+        assert!(!Tokens::<Cow<str>>(vec![]).ends_with_multi_wildcard());
+    }
+
+    #[test]
+    #[allow(clippy::bool_assert_comparison)]
     fn test_matcher() {
         assert_eq!(Tokens(vec![token!("a"), token!("b"), token!("c")]).has_no_wildcard(), true);
         assert_eq!(Tokens(vec![token!("a"), token!(o), token!("c")]).has_no_wildcard(), false);
@@ -205,4 +1200,253 @@ mod test {
         assert_eq!(tokens.match_keys(vec!["b", "c"]), false);
         assert_eq!(tokens.match_keys(vec!["a", "b", "c"]), true);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_allowed_chars() -> Result<(), CommonTokenError> {
+        let parser = CommonTokenParser::new('.', "*", ">")
+            .allowed_chars(|c| c.is_ascii_alphanumeric() || c == '_');
+        assert_eq!(
+            parser.parse_tokens("a.*.b_1.>")?,
+            Tokens(vec![token!("a"), token!(o), token!("b_1"), token!(m)])
+        );
+        assert_eq!(
+            parser.parse_tokens("a.b c"),
+            Err(CommonTokenError::InvalidChar { token: "b c".into(), ch: ' ' })
+        );
+        assert_eq!(
+            parser.parse_tokens("a\tb"),
+            Err(CommonTokenError::InvalidChar { token: "a\tb".into(), ch: '\t' })
+        );
+        // ranges are not normal tokens
+        assert_eq!(parser.parse_tokens("[1-5]")?, Tokens(vec![Token::NumRange(1, 5)]));
+        // each alternative is checked like a normal token
+        assert_eq!(parser.parse_tokens("a.(b|c_1)")?, Tokens(vec![token!("a"), Token::OneOf(vec!["b".into(), "c_1".into()])]));
+        assert_eq!(
+            parser.parse_tokens("a.(b c|d)"),
+            Err(CommonTokenError::InvalidChar { token: "(b c|d)".into(), ch: ' ' })
+        );
+        // so is the capture name, but not the prefix
+        let parser = parser.capture_prefix(':');
+        assert_eq!(parser.parse_tokens("a.:id_1")?, Tokens(vec![token!("a"), Token::OneWildcard(Some("id_1".into()))]));
+        assert_eq!(
+            parser.parse_tokens("a.:i-d"),
+            Err(CommonTokenError::InvalidChar { token: ":i-d".into(), ch: '-' })
+        );
+        // any char is allowed by default
+        let parser = CommonTokenParser::new('.', "*", ">");
+        assert_eq!(parser.parse_tokens("b c")?, Tokens(vec![token!("b c")]));
+        Ok(())
+    }
+
+    #[test]
+    fn test_treat_empty_as_no_tokens() -> Result<(), CommonTokenError> {
+        // an empty source is one empty token by default
+        let parser = CommonTokenParser::new('.', "*", ">");
+        assert_eq!(parser.parse_tokens("")?, Tokens(vec![token!("")]));
+        let parser = parser.treat_empty_as_no_tokens(true);
+        assert_eq!(parser.parse_tokens("")?, Tokens(vec![]));
+        assert_eq!(parser.parse_tokens_with("", '/')?, Tokens(vec![]));
+        // other empty segments are still empty tokens
+        assert_eq!(parser.parse_tokens(".")?, Tokens(vec![token!(""), token!("")]));
+        assert_eq!(parser.parse_tokens("a")?, Tokens(vec![token!("a")]));
+        Ok(())
+    }
+
+    #[test]
+    fn test_tokens_render() -> Result<(), CommonTokenError> {
+        let parser = CommonTokenParser::new('.', "*", ">");
+        for source in ["a.b", "a.*.c", "a.(b|c).[1-5].>", ">", ""].iter() {
+            let tokens = parser.parse_tokens(source)?;
+            assert_eq!(tokens.render('.', "*", ">"), *source);
+        }
+        let tokens = parser.parse_tokens("a.*.>")?;
+        assert_eq!(tokens.render('/', "+", "#"), "a/+/#");
+        assert_eq!(Tokens(vec![Token::OneWildcard(Some("id".into()))]).render('.', "*", ">"), "*");
+        Ok(())
+    }
+
+    #[test]
+    fn test_mqtt_strict() -> Result<(), CommonTokenError> {
+        let parser = CommonTokenParser::mqtt();
+        assert_eq!(
+            parser.parse_tokens("sport/+/player1")?,
+            Tokens(vec![token!("sport"), token!(o), token!("player1")])
+        );
+        assert_eq!(
+            parser.parse_tokens("sport/#")?,
+            Tokens(vec![token!("sport"), token!(m)])
+        );
+        assert_eq!(parser.parse_tokens("#")?, Tokens(vec![token!(m)]));
+        assert_eq!(
+            parser.parse_tokens("sport/#/ranking"),
+            Err(CommonTokenError::MultiWildcardNotAtEnd { input: "sport/#/ranking".into(), span: 6..7 })
+        );
+        assert_eq!(
+            parser.parse_tokens("sport#"),
+            Err(CommonTokenError::MultiWildcardInsideToken { span: 0..6 })
+        );
+        assert_eq!(
+            parser.parse_tokens("a/sp+ort"),
+            Err(CommonTokenError::OneWildcardInsideToken { span: 2..8 })
+        );
+        // alternatives and ranges are literal levels in MQTT
+        assert_eq!(
+            parser.parse_tokens("sensor/[1-5]/(a|b)")?,
+            Tokens(vec![token!("sensor"), token!("[1-5]"), token!("(a|b)")])
+        );
+        let extended = CommonTokenParser::mqtt().extended_syntax(true);
+        assert_eq!(
+            extended.parse_tokens("sensor/[1-5]")?,
+            Tokens(vec![token!("sensor"), Token::NumRange(1, 5)])
+        );
+        // not strict by default
+        let parser = CommonTokenParser::new('/', "+", "#");
+        assert_eq!(parser.parse_tokens("sport#")?, Tokens(vec![token!("sport#")]));
+        Ok(())
+    }
+
+    #[test]
+    fn test_nats_subject() -> Result<(), NatsSubjectError> {
+        let parser = CommonTokenParser::nats();
+        assert_eq!(
+            parser.parse_nats_subject("foo.*.bar")?,
+            Tokens(vec![token!("foo"), token!(o), token!("bar")])
+        );
+        assert_eq!(
+            parser.parse_nats_subject("foo.>")?,
+            Tokens(vec![token!("foo"), token!(m)])
+        );
+        assert_eq!(parser.parse_nats_subject(""), Err(NatsSubjectError::EmptySubject));
+        assert_eq!(parser.parse_nats_subject(".foo"), Err(NatsSubjectError::LeadingSeparator));
+        assert_eq!(parser.parse_nats_subject("foo."), Err(NatsSubjectError::TrailingSeparator));
+        assert_eq!(
+            parser.parse_nats_subject("foo..bar"),
+            Err(NatsSubjectError::EmptyToken { span: 4..4 })
+        );
+        assert_eq!(
+            parser.parse_nats_subject("foo.>.bar"),
+            Err(NatsSubjectError::Parse(CommonTokenError::MultiWildcardNotAtEnd { input: "foo.>.bar".into(), span: 4..5 }))
+        );
+        assert_eq!(
+            parser.parse_nats_subject("foo.(a|b).[1-5]")?,
+            Tokens(vec![token!("foo"), token!("(a|b)"), token!("[1-5]")])
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_common_token_parser_transform() -> Result<(), CommonTokenError> {
+        let parser = CommonTokenParser::new('.', "*", ">")
+            .token_transform(|s| match s {
+                "colour" => Cow::Borrowed("color"),
+                s => s.strip_prefix("x-").map_or(Cow::Borrowed(s), |s| Cow::Owned(s.to_string())),
+            })
+            .fold_case(FoldMode::Ascii);
+        assert_eq!(
+            parser.parse_tokens("X-A.colour.*.(x-b|c).>")?,
+            Tokens(vec![
+                token!("x-a"),
+                token!("color"),
+                token!(o),
+                Token::OneOf(vec!["b".into(), "c".into()]),
+                token!(m),
+            ])
+        );
+        assert_eq!(parser.normalize_key("x-B"), "b");
+        Ok(())
+    }
+
+    #[test]
+    fn test_capture_prefix() -> Result<(), CommonTokenError> {
+        let parser = CommonTokenParser::new('.', "*", ">").capture_prefix(':');
+        let tokens = parser.parse_tokens("a.:id.*.:.>")?;
+        assert_eq!(
+            tokens,
+            Tokens(vec![token!("a"), Token::OneWildcard(Some("id".into())), token!(o), token!(":"), token!(m)])
+        );
+        assert_eq!(tokens.capture_names(), vec![Some("id".into()), None]);
+        assert_eq!(tokens.wildcard_count(), (2, 1));
+        assert!(tokens.match_keys(["a", "1", "2", ":", "x"]));
+        assert_eq!(tokens.to_static(), tokens);
+        // disabled by default
+        let parser = CommonTokenParser::new('.', "*", ">");
+        assert_eq!(parser.parse_tokens(":id")?, Tokens(vec![token!(":id")]));
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_tokens_with() -> Result<(), CommonTokenError> {
+        let parser = CommonTokenParser::new('.', "*", ">");
+        let expected = Tokens(vec![token!("a"), token!(o), token!(m)]);
+        assert_eq!(parser.parse_tokens_with("a/*/>", '/')?, expected);
+        assert_eq!(parser.parse_tokens_with("a.*.>", '.')?, parser.parse_tokens("a.*.>")?);
+        // the configured seperator is not used
+        assert_eq!(parser.parse_tokens_with("a.b", '/')?, Tokens(vec![token!("a.b")]));
+        assert_eq!(
+            parser.parse_tokens_with("a/>/b", '/'),
+            Err(CommonTokenError::MultiWildcardNotAtEnd { input: "a/>/b".into(), span: 2..3 })
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_tokens_accessors() -> Result<(), CommonTokenError> {
+        let parser = CommonTokenParser::new('.', "*", ">");
+        let tokens = parser.parse_tokens("a.*.>")?;
+        assert_eq!(tokens.len(), 3);
+        assert!(!tokens.is_empty());
+        assert_eq!(tokens.get(0), Some(&token!("a")));
+        assert_eq!(tokens.get(3), None);
+        assert_eq!(tokens[1], token!(o));
+        assert_eq!(tokens.iter().cloned().collect::<Vec<_>>(), vec![token!("a"), token!(o), token!(m)]);
+        assert!(Tokens::<Cow<str>>::default().is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_match_keys_boundaries() {
+        let cases: Vec<(Tokens, Vec<&str>, bool)> = vec![
+            (Tokens(vec![token!(m)]), vec!["a", "b", "c"], true),
+            (Tokens(vec![token!(m)]), vec!["a"], true),
+            (Tokens(vec![token!(m)]), vec![], false),
+            (Tokens(vec![token!("a"), token!(m)]), vec!["a"], false),
+            (Tokens(vec![token!("a"), token!(m)]), vec!["a", "b"], true),
+            (Tokens(vec![token!("a"), token!(m)]), vec!["b", "b"], false),
+            (Tokens(vec![token!(o)]), vec![], false),
+            (Tokens(vec![token!(o)]), vec!["a"], true),
+            (Tokens(vec![token!(o)]), vec!["a", "b"], false),
+            (Tokens(vec![token!(o), token!(m)]), vec!["a"], false),
+            (Tokens(vec![]), vec![], true),
+            (Tokens(vec![]), vec!["a"], false),
+            (Tokens(vec![token!("")]), vec![""], true),
+            (Tokens(vec![token!("")]), vec![], false),
+            (Tokens(vec![Token::OneOf(vec!["a".into(), "b".into()])]), vec!["b"], true),
+            (Tokens(vec![Token::OneOf(vec![])]), vec!["b"], false),
+        ];
+        for (tokens, keys, expected) in cases.into_iter() {
+            assert_eq!(tokens.match_keys(&keys), expected, "{:?} {:?}", tokens, keys);
+        }
+    }
+
+    #[test]
+    fn test_strip_prefix_suffix() -> Result<(), CommonTokenError> {
+        let parser = CommonTokenParser::new('.', "*", ">").capture_prefix(':');
+        let tokens = parser.parse_tokens("tenant.:id.(b|c).[1-5].>")?;
+        assert_eq!(tokens.strip_prefix(&parser.parse_tokens("tenant.*")?), Some(parser.parse_tokens("(b|c).[1-5].>")?));
+        assert_eq!(tokens.strip_prefix(&parser.parse_tokens("tenant.*.(c|b)")?), Some(parser.parse_tokens("[1-5].>")?));
+        assert_eq!(tokens.strip_prefix(&tokens), Some(Tokens::default()));
+        assert_eq!(tokens.strip_prefix(&Tokens::default()), Some(tokens.clone()));
+        // wildcards are not matched against normal tokens
+        assert_eq!(tokens.strip_prefix(&parser.parse_tokens("*")?), None);
+        assert_eq!(tokens.strip_prefix(&parser.parse_tokens("tenant.x")?), None);
+        assert_eq!(tokens.strip_prefix(&parser.parse_tokens("tenant.*.b")?), None);
+        assert_eq!(tokens.strip_prefix(&parser.parse_tokens("tenant.*.(b|c).[1-5].x.y")?), None);
+
+        assert_eq!(tokens.strip_suffix(&parser.parse_tokens("[1-5].>")?), Some(parser.parse_tokens("tenant.:id.(b|c)")?));
+        assert_eq!(tokens.strip_suffix(&tokens), Some(Tokens::default()));
+        assert_eq!(tokens.strip_suffix(&Tokens::default()), Some(tokens.clone()));
+        assert_eq!(tokens.strip_suffix(&parser.parse_tokens("[1-4].>")?), None);
+        assert_eq!(tokens.strip_suffix(&parser.parse_tokens("x.tenant.*.(b|c).[1-5].>")?), None);
+        Ok(())
+    }
+}
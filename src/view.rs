@@ -0,0 +1,1049 @@
+use crate::children::{ChildMap, HashedChildren};
+use crate::matcher::Matcher;
+use crate::node::Node;
+use crate::token::{tokens_overlap, Token, TokenAtom, Tokens};
+use std::borrow::Cow;
+use std::cmp::{Ordering, Reverse};
+use std::collections::{HashMap, HashSet};
+use std::collections::hash_map::RandomState;
+use std::fmt::Debug;
+use std::hash::{BuildHasher, Hash};
+
+// 每一层待处理的node的集合。大多数查询每一层只会匹配到少数几个node，
+// 开启smallvec特性时不超过4个的node保存在栈上，不需要分配内存
+#[cfg(feature = "smallvec")]
+type Frontier<T> = smallvec::SmallVec<[T; 4]>;
+#[cfg(not(feature = "smallvec"))]
+type Frontier<T> = Vec<T>;
+// 查询过程中待处理的node
+type Nodes<'t, 'a, V, S, K, M> = Frontier<&'t Node<'a, V, S, K, M>>;
+// 查询过程中待处理的node，以及到达该node的路径上是否经过了单层wildcard
+type TracedNodes<'t, 'a, V, S, K, M> = Frontier<(&'t Node<'a, V, S, K, M>, bool)>;
+// 与keys匹配的node：多层wildcard组匹配的node，以及自身的组匹配的node
+type MatchedNodes<'t, 'a, V, S, K, M> = (Nodes<'t, 'a, V, S, K, M>, TracedNodes<'t, 'a, V, S, K, M>);
+// exist_batch中每个首个key的第一步匹配的结果，Err表示已经可以确定结果
+type FirstSteps<'k, 't, 'a, V, S, K, M> = HashMap<&'k <K as TokenAtom>::Key, Result<Nodes<'t, 'a, V, S, K, M>, bool>>;
+
+// 与key匹配的一个非空的组
+struct MatchedGroup<'t, 'a, V, S, K: TokenAtom, M: ChildMap> {
+    // 组对应的tokens
+    pattern: Vec<Token<'a, K>>,
+    // 组所在的node
+    node: &'t Node<'a, V, S, K, M>,
+    // 是否是多层wildcard组
+    mwc: bool,
+}
+
+impl<'t, 'a, V, S, K, M> MatchedGroup<'t, 'a, V, S, K, M>
+where
+    K: TokenAtom,
+    M: ChildMap,
+    V: Eq + Hash,
+    S: BuildHasher + Default,
+{
+    // 组中是否存在value
+    fn contains(&self, value: &V) -> bool {
+        if self.mwc {
+            self.node.mwc_contains(value)
+        } else {
+            self.node.contains(value)
+        }
+    }
+
+    // 组中所有值的引用
+    fn values(&self) -> Vec<&'t V> {
+        if self.mwc {
+            self.node.mwc_values().collect()
+        } else {
+            self.node.values().collect()
+        }
+    }
+
+    // 组中所有值的复制
+    fn values_owned(&self) -> Vec<V>
+    where
+        V: Clone,
+    {
+        if self.mwc {
+            self.node.mwc_values_owned().collect()
+        } else {
+            self.node.values_owned().collect()
+        }
+    }
+}
+
+/// 与某个key匹配的值按照来源分类的统计
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct MatchBreakdown {
+    /// 来自不含wildcard的组
+    pub exact: usize,
+    /// 来自含有单层wildcard或者多选一token的组
+    pub one_wildcard: usize,
+    /// 来自多层wildcard组
+    pub multi_wildcard: usize,
+}
+
+/// 整棵trie树中的值按照所在的组的模式分类的统计
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct SubStats {
+    /// 不含wildcard的组中的值
+    pub exact: usize,
+    /// 含有单层wildcard、多选一token或者数字范围token，但不以多层wildcard结尾的组中的值
+    pub one_wildcard: usize,
+    /// 多层wildcard组中的值
+    pub multi_wildcard: usize,
+}
+
+/// tokens对应的结点的概要，用于检查trie树的结构
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct NodeInfo {
+    /// 结点对应的组中的值的数量
+    pub value_count: usize,
+    /// 结点上的多层wildcard组中的值的数量
+    pub mwc_count: usize,
+    /// 普通token对应的直接子结点的数量，与`child_count`相同
+    pub child_count: usize,
+    /// 是否有单层wildcard对应的子结点
+    pub has_owc: bool,
+    /// 单层wildcard对应的子结点的组中的值的数量，没有该子结点时为0
+    pub owc_value_count: usize,
+}
+
+/// `try_find`的结果，区分没有匹配到结点和匹配到的结点中没有值
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FindResult<V> {
+    /// 没有与keys匹配的结点，匹配过程中待处理的结点已经为空
+    NoPath,
+    /// 有与keys匹配的结点，但是其中的组都没有值。参数表示keys本身对应的、只经过普通token的结点是否存在
+    NoValues(bool),
+    /// 匹配到的所有值，与`find`的结果相同
+    Matched(Vec<V>),
+}
+
+/// `find_ranked`中来自不同组的值的排列顺序
+#[derive(Default)]
+pub enum MatchPriority<K = Cow<'static, str>> {
+    /// 按照具体程度的分数从高到低排列：不含wildcard的组，含有单层wildcard或者多选一token的组，多层wildcard组
+    #[default]
+    ExactFirst,
+    /// 从左向右逐个比较token，先出现更具体的token的组排在前面，
+    /// 例如对于`a.b`，`a.*`排在`*.b`前面
+    LeftmostSpecific,
+    /// 自定义的比较，参数是两个组的tokens，返回`Ordering::Less`的组排在前面
+    Custom(PriorityFn<K>),
+}
+
+/// 自定义的组的比较，K是token的原子
+pub type PriorityFn<K = Cow<'static, str>> = Box<dyn Fn(&Tokens<K>, &Tokens<K>) -> Ordering + Send + Sync>;
+
+/// trie树的只读视图，所有的查询都不经过缓存，因此只需要不可变引用
+pub struct TrieView<'t, 'a, V, S = RandomState, K: TokenAtom = Cow<'a, str>, M: ChildMap = HashedChildren<S>> {
+    // 根结点
+    root: &'t Node<'a, V, S, K, M>,
+    // 多层wildcard组是否也匹配其前缀本身，例如`a.>`匹配`a`
+    mwc_includes_prefix: bool,
+    // 是否可能存在wildcard，为false时只需要沿着普通token对应的子结点向下查询
+    has_wildcard: bool,
+}
+
+impl<'t, 'a, V, S, K, M> TrieView<'t, 'a, V, S, K, M>
+where
+    K: TokenAtom,
+    M: ChildMap,
+    V: Eq + Hash,
+    S: BuildHasher + Default,
+{
+    /// 以root为根结点生成视图
+    pub(crate) fn new(root: &'t Node<'a, V, S, K, M>, mwc_includes_prefix: bool, has_wildcard: bool) -> Self {
+        TrieView { root, mwc_includes_prefix, has_wildcard }
+    }
+
+    /// 返回整棵树中所有多层wildcard组中的值，以及组对应的以`MultiWildcard`结尾的tokens
+    pub fn multi_wildcard_values(&self) -> Vec<(Tokens<'a, K>, &'t V)> {
+        let mut values = Vec::new();
+        // 待处理的node，以及到达它的token和其父结点的tokens的长度
+        let mut stack = vec![(0, None, self.root)];
+        let mut path: Vec<Token<'a, K>> = Vec::new();
+        while let Some((depth, token, node)) = stack.pop() {
+            path.truncate(depth);
+            path.extend(token);
+            if !node.is_mwc_empty() {
+                let mut mwc_path = path.clone();
+                mwc_path.push(Token::MultiWildcard);
+                let tokens: Tokens<'a, K> = mwc_path.into();
+                values.extend(node.mwc_values().map(|v| (tokens.clone(), v)));
+            }
+            let depth = path.len();
+            stack.extend(node.token_child_nodes().map(|(t, n)| (depth, Some(t), n)));
+        }
+        values
+    }
+
+    /// 返回tokens对应的结点的直接子结点数量，路径不存在时返回None
+    pub fn child_count(&self, tokens: &Tokens<'a, K>) -> Option<usize> {
+        self.find_node(tokens).0.map(|n| n.child_count())
+    }
+
+    /// 返回tokens对应的结点的概要，路径不存在时返回None。tokens末尾的多层wildcard被忽略，
+    /// 即`a.>`与`a`对应同一个结点
+    pub fn node_info(&self, tokens: &Tokens<'a, K>) -> Option<NodeInfo> {
+        let node = self.find_node(tokens).0?;
+        let owc = node.owc_node();
+        Some(NodeInfo {
+            value_count: node.len(),
+            mwc_count: node.mwc_len(),
+            child_count: node.child_count(),
+            has_owc: owc.is_some(),
+            owc_value_count: owc.map_or(0, |n| n.len()),
+        })
+    }
+
+    /// 返回整棵树中最大的直接子结点数量
+    pub fn max_fanout(&self) -> usize {
+        self.root.max_fanout()
+    }
+
+    /// 返回能与keys匹配的值的数量，与`find`返回的结果数量相同
+    pub fn count<'k>(&self, keys: impl AsRef<[&'k K::Key]>) -> usize {
+        let (mwc_nodes, nodes) = self.matched_nodes(keys.as_ref().iter().copied());
+        mwc_nodes.into_iter().map(|n| n.mwc_len()).sum::<usize>()
+            + nodes.into_iter().map(|(n, _)| n.len()).sum::<usize>()
+    }
+
+    /// 返回能与keys匹配的值按照来源分类的数量
+    pub fn match_breakdown<'k>(&self, keys: impl AsRef<[&'k K::Key]>) -> MatchBreakdown {
+        let (mwc_nodes, nodes) = self.matched_nodes(keys.as_ref().iter().copied());
+        let mut breakdown = MatchBreakdown {
+            multi_wildcard: mwc_nodes.into_iter().map(|n| n.mwc_len()).sum(),
+            ..Default::default()
+        };
+        for (node, via_owc) in nodes.into_iter() {
+            if via_owc {
+                breakdown.one_wildcard += node.len();
+            } else {
+                breakdown.exact += node.len();
+            }
+        }
+        breakdown
+    }
+
+    /// 深度优先地遍历整棵树，对每个值调用f，参数是值所在的组的tokens，包括wildcard。
+    /// 所有的调用共用同一个tokens，不会为每个值分配内存
+    pub fn visit<F: FnMut(&Tokens<'a, K>, &'t V)>(&self, mut f: F) {
+        // 待处理的node，以及到达它的token和其父结点的tokens的长度
+        let mut stack = vec![(0, None, self.root)];
+        let mut path: Tokens<'a, K> = Tokens::default();
+        while let Some((depth, token, node)) = stack.pop() {
+            path.0.truncate(depth);
+            path.0.extend(token);
+            for value in node.values() {
+                f(&path, value);
+            }
+            if !node.is_mwc_empty() {
+                path.0.push(Token::MultiWildcard);
+                for value in node.mwc_values() {
+                    f(&path, value);
+                }
+                path.0.pop();
+            }
+            let depth = path.len();
+            // 倒序入栈，子结点按照`token_child_nodes`的顺序出栈
+            let start = stack.len();
+            stack.extend(node.token_child_nodes().map(|(t, n)| (depth, Some(t), n)));
+            stack[start..].reverse();
+        }
+    }
+
+    /// 遍历整棵树，按照组的模式统计值的数量。一个值在多个组中时分别统计
+    pub fn subscription_stats(&self) -> SubStats {
+        let mut stats = SubStats::default();
+        // 待处理的结点，以及到达该结点是否经过了单层wildcard、多选一token或者数字范围token
+        let mut stack = vec![(self.root, false)];
+        while let Some((node, via_owc)) = stack.pop() {
+            stats.multi_wildcard += node.mwc_len();
+            if via_owc {
+                stats.one_wildcard += node.len();
+            } else {
+                stats.exact += node.len();
+            }
+            stack.extend(node.token_child_nodes()
+                .map(|(token, n)| (n, via_owc || !matches!(token, Token::Normal(_)))));
+        }
+        stats
+    }
+
+    /// 返回tokens对应的组中唯一的值，不进行wildcard匹配。组不存在、为空或者有多个值时返回None
+    pub fn get_single(&self, tokens: &Tokens<'a, K>) -> Option<&'t V> {
+        let (node, hasmwc) = self.find_node(tokens);
+        let node = node?;
+        let mut values = if hasmwc { node.mwc_values() } else { node.values() };
+        match (values.next(), values.next()) {
+            (Some(value), None) => Some(value),
+            _ => None,
+        }
+    }
+
+    /// 返回逐个接收token进行匹配的匹配器
+    pub fn matcher(&self) -> Matcher<'t, 'a, V, S, K, M> {
+        Matcher::new(self.root, self.mwc_includes_prefix)
+    }
+
+    /// 是否有与keys匹配的值存在，包含带有wildcard的
+    pub fn exist<'k>(&self, keys: impl AsRef<[&'k K::Key]>) -> bool {
+        if !self.has_wildcard {
+            return self.exist_exact(keys);
+        }
+        self.exist_from(self.root_nodes(), keys.as_ref())
+    }
+
+    /// 对每个subject分别判断是否有与其匹配的值存在，结果与逐个调用`exist`相同。
+    /// 首个key相同的subject共用从根结点出发的第一步匹配
+    pub fn exist_batch(&self, subjects: &[&[&K::Key]]) -> Vec<bool> {
+        let mut first_steps: FirstSteps<'_, 't, 'a, V, S, K, M> = HashMap::new();
+        subjects.iter()
+            .map(|keys| match keys.split_first() {
+                None => self.exist_from(self.root_nodes(), keys),
+                Some((first, rest)) => {
+                    let step = first_steps.entry(first)
+                        .or_insert_with(|| Self::exist_step(self.root_nodes(), first));
+                    match step {
+                        Err(v) => *v,
+                        Ok(nodes) => self.exist_from(nodes.clone(), rest),
+                    }
+                },
+            })
+            .collect()
+    }
+
+    /// 从nodes出发，是否有与keys匹配的值存在
+    fn exist_from(&self, nodes: Nodes<'t, 'a, V, S, K, M>, keys: &[&K::Key]) -> bool {
+        // 迭代key来获得最终node
+        // 其中try_fold里面的Result没有错误的含义，只是用来使用Err来短路迭代
+        let nodes = keys.iter().try_fold(nodes, |nodes, token| Self::exist_step(nodes, token));
+        match nodes {
+            // 短路，直接输出内部包含值
+            Err(v) => v,
+            // 没有短路，查找匹配的nodes中是否有值
+            Ok(ns) => ns.into_iter()
+                .any(|n| !n.is_empty() || (self.mwc_includes_prefix && !n.is_mwc_empty())),
+        }
+    }
+
+    /// 用一个key匹配nodes，返回匹配到的下一层nodes。已经可以确定结果时以Err短路
+    fn exist_step(nodes: Nodes<'t, 'a, V, S, K, M>, token: &K::Key) -> Result<Nodes<'t, 'a, V, S, K, M>, bool> {
+        // 如果是空node，那就不用查找了
+        if nodes.is_empty() {
+            return Err(false);
+        }
+        let mut next_nodes: Nodes<'t, 'a, V, S, K, M> = Frontier::new();
+        for node in nodes.into_iter() {
+            // 存在mwc的结果则肯定有匹配值
+            if !node.is_mwc_empty() { return Err(true); }
+            // 符合当前token的node可以是token对应的，也可以是owc、多选一token或者数字范围token对应的
+            next_nodes.extend(node.owc_node());
+            next_nodes.extend(node.alt_nodes_matching(token).map(|(_, n)| n));
+            next_nodes.extend(node.range_nodes_matching(token).map(|(_, n)| n));
+            if let Some(n) = node.get_child_node(token) {
+                next_nodes.push(n);
+            }
+        }
+        Ok(next_nodes)
+    }
+
+    /// 返回任意一个与keys匹配的值，找到第一个值之后立即返回，不存在时返回None
+    pub fn any_match<'k>(&self, keys: impl AsRef<[&'k K::Key]>) -> Option<&'t V> {
+        // 其中try_fold里面的Result没有错误的含义，只是用来使用Err来短路迭代
+        let nodes = keys.as_ref().iter()
+            .try_fold(self.root_nodes(),
+                |nodes, token| {
+                    // 如果是空node，那就不用查找了
+                    if nodes.is_empty() {
+                        return Err(None);
+                    }
+                    let mut next_nodes: Nodes<'t, 'a, V, S, K, M> = Frontier::new();
+                    for node in nodes.into_iter() {
+                        // 存在mwc的结果则直接返回
+                        if let Some(v) = node.mwc_values().next() { return Err(Some(v)); }
+                        // 符合当前token的node可以是token对应的，也可以是owc、多选一token或者数字范围token对应的
+                        next_nodes.extend(node.owc_node());
+                        next_nodes.extend(node.alt_nodes_matching(token).map(|(_, n)| n));
+                        next_nodes.extend(node.range_nodes_matching(token).map(|(_, n)| n));
+                        if let Some(n) = node.get_child_node(token) {
+                            next_nodes.push(n);
+                        }
+                    }
+                    Ok(next_nodes)
+                }
+            );
+        match nodes {
+            Err(v) => v,
+            Ok(ns) => ns.into_iter().find_map(|n| {
+                let prefix = if self.mwc_includes_prefix { n.mwc_values().next() } else { None };
+                prefix.or_else(|| n.values().next())
+            }),
+        }
+    }
+
+    /// 是否存在与keys完全相同、不含任何wildcard的组，并且组中有值
+    pub fn exist_exact<'k>(&self, keys: impl AsRef<[&'k K::Key]>) -> bool {
+        self.exact_node(keys.as_ref()).is_some_and(|n| !n.is_empty())
+    }
+
+    /// 沿着普通token对应的子结点找到keys对应的node，不进行wildcard匹配
+    fn exact_node(&self, keys: &[&K::Key]) -> Option<&'t Node<'a, V, S, K, M>> {
+        keys.iter().try_fold(self.root, |n, key| n.get_child_node(key))
+    }
+
+    /// 找到tokens对应的node，返回其引用，如果没有，则返回None。
+    /// 第二个返回值表示tokens中是否有多层wildcard
+    pub(crate) fn find_node(&self, tokens: &Tokens<'a, K>) -> (Option<&'t Node<'a, V, S, K, M>>, bool) {
+        let mut hasmwc = false;
+        let value = tokens.0.iter()
+            // 查找token对应的node，如果没有token就返回None
+            .try_fold(self.root,
+                |n, token| {
+                    match token {
+                        Token::MultiWildcard => {
+                            hasmwc = true;
+                            Some(n)
+                        },
+                        _ => n.get_token_node(token),
+                    }
+                });
+        (value, hasmwc)
+    }
+
+    /// 返回与keys匹配的所有非空的组的tokens，多层wildcard组以`MultiWildcard`结尾
+    pub fn matching_patterns<'k>(&self, keys: impl AsRef<[&'k K::Key]>) -> Vec<Tokens<'a, K>> {
+        self.matched_groups(keys.as_ref())
+            .into_iter()
+            .map(|g| g.pattern.into())
+            .collect()
+    }
+
+    /// 返回与keys匹配的所有非空的组的tokens以及组中所有值的引用，每个组只出现一次
+    pub fn find_grouped<'k>(&self, keys: impl AsRef<[&'k K::Key]>) -> Vec<(Tokens<'a, K>, Vec<&'t V>)> {
+        self.matched_groups(keys.as_ref())
+            .into_iter()
+            .map(|g| {
+                let values = g.values();
+                (g.pattern.into(), values)
+            })
+            .collect()
+    }
+
+    /// 返回一个覆盖candidate的非空的组的tokens，即能与candidate匹配的key都能与之匹配。
+    /// 单层wildcard覆盖任意单个token，多选一token覆盖其中的普通token以及备选token是其子集的多选一token，
+    /// 数字范围token覆盖其中的数字、范围是其子集的数字范围token以及备选token都是其中的数字的多选一token，
+    /// 多层wildcard覆盖之后的一个或者多个任意token。只有一个备选token的多选一token与普通token相同，
+    /// 也被该token的普通子结点覆盖。不存在时返回None
+    pub fn covers<Q>(&self, candidate: &Tokens<Q>) -> Option<Tokens<'a, K>>
+    where
+        Q: TokenAtom<Key = K::Key>,
+    {
+        let mut nodes = vec![(self.root, vec![])];
+        for token in candidate.0.iter() {
+            let mut next_nodes = Vec::new();
+            for (node, pattern) in nodes.into_iter() {
+                // 多层wildcard组覆盖之后的所有token
+                if !node.is_mwc_empty() {
+                    let mut mwc_pattern: Vec<Token<'a, K>> = pattern;
+                    mwc_pattern.push(Token::MultiWildcard);
+                    return Some(mwc_pattern.into());
+                }
+                // 只有多层wildcard组能够覆盖多层wildcard
+                if let Token::MultiWildcard = token {
+                    continue;
+                }
+                if let Some(n) = node.owc_node() {
+                    let mut owc_pattern = pattern.clone();
+                    owc_pattern.push(Token::OneWildcard(None));
+                    next_nodes.push((n, owc_pattern));
+                }
+                // 普通token，或者只有一个备选token的多选一token，只匹配这一个key
+                let single_key = match token {
+                    Token::Normal(key) => Some(key.key()),
+                    Token::OneOf(alternatives) => match alternatives.split_first() {
+                        Some((first, rest)) if rest.iter().all(|alt| alt.key() == first.key()) => Some(first.key()),
+                        _ => None,
+                    },
+                    _ => None,
+                };
+                let alt_nodes: Vec<_> = match (single_key, token) {
+                    (Some(key), _) => node.alt_nodes_matching(key).collect(),
+                    (None, Token::OneOf(alternatives)) => node.alt_nodes_covering(alternatives).collect(),
+                    _ => vec![],
+                };
+                for (alternatives, n) in alt_nodes.into_iter() {
+                    let mut alt_pattern = pattern.clone();
+                    alt_pattern.push(Token::OneOf(alternatives.to_vec()));
+                    next_nodes.push((n, alt_pattern));
+                }
+                let range_nodes: Vec<_> = match token {
+                    Token::Normal(key) => node.range_nodes_matching(key.key()).collect(),
+                    Token::NumRange(lo, hi) => node.range_nodes_covering(*lo, *hi).collect(),
+                    Token::OneOf(alternatives) => match num_bounds(alternatives) {
+                        Some((lo, hi)) => node.range_nodes_covering(lo, hi).collect(),
+                        None => vec![],
+                    },
+                    _ => vec![],
+                };
+                for ((lo, hi), n) in range_nodes.into_iter() {
+                    let mut range_pattern = pattern.clone();
+                    range_pattern.push(Token::NumRange(lo, hi));
+                    next_nodes.push((n, range_pattern));
+                }
+                if let Some(key) = single_key {
+                    if let Some((k, n)) = node.get_child_entry(key) {
+                        let mut child_pattern = pattern;
+                        child_pattern.push(Token::Normal(k.to_atom()));
+                        next_nodes.push((n, child_pattern));
+                    }
+                }
+            }
+            nodes = next_nodes;
+        }
+        nodes.into_iter().find_map(|(node, mut pattern)| {
+            if !node.is_empty() {
+                Some(pattern.into())
+            } else if self.mwc_includes_prefix && !node.is_mwc_empty() {
+                pattern.push(Token::MultiWildcard);
+                Some(pattern.into())
+            } else {
+                None
+            }
+        })
+    }
+
+    /// prefix之下的所有subject是否都能匹配到值，即在prefix之后还有一个或者多个任意token的key都能匹配，
+    /// prefix末尾的多层wildcard会被忽略。prefix处或者其上的多层wildcard组覆盖其下的所有subject，
+    /// 逐层的单层wildcard组加上最后的多层wildcard组（例如`a.*`和`a.*.>`）也能覆盖。
+    /// prefix中的wildcard、数字范围和多选一token要求其匹配的每一个token之下都被覆盖
+    pub fn has_catchall<Q>(&self, prefix: &Tokens<Q>) -> bool
+    where
+        Q: TokenAtom<Key = K::Key>,
+    {
+        let prefix = prefix.strip_mwc();
+        let mut states = vec![(vec![self.root], 0)];
+        while let Some((nodes, depth)) = states.pop() {
+            // 多层wildcard组覆盖之后的所有token
+            if nodes.iter().any(|n| !n.is_mwc_empty()) {
+                continue;
+            }
+            let token = match prefix.get(depth) {
+                Some(token) => token,
+                None if self.covers_suffixes(nodes) => continue,
+                None => return false,
+            };
+            match token {
+                Token::Normal(key) => states.push((Self::nodes_matching(&nodes, key.key()), depth + 1)),
+                Token::OneOf(alternatives) => {
+                    for alternative in alternatives.iter() {
+                        states.push((Self::nodes_matching(&nodes, alternative.key()), depth + 1));
+                    }
+                }
+                // 范围中的每一个数字都能到达单层wildcard结点以及包含整个范围的数字范围结点
+                Token::NumRange(lo, hi) => {
+                    let mut next_nodes: Vec<_> = nodes.iter().filter_map(|n| n.owc_node()).collect();
+                    next_nodes.extend(nodes.iter().flat_map(|n| n.range_nodes_covering(*lo, *hi).map(|(_, n)| n)));
+                    states.push((next_nodes, depth + 1));
+                }
+                Token::OneWildcard(_) | Token::MultiWildcard => {
+                    states.push((nodes.iter().filter_map(|n| n.owc_node()).collect(), depth + 1));
+                }
+            }
+        }
+        true
+    }
+
+    /// 与key匹配的nodes的所有子结点
+    fn nodes_matching(nodes: &[&'t Node<'a, V, S, K, M>], key: &K::Key) -> Vec<&'t Node<'a, V, S, K, M>> {
+        let mut next_nodes = Vec::new();
+        for node in nodes.iter() {
+            next_nodes.extend(node.owc_node());
+            next_nodes.extend(node.get_child_node(key));
+            next_nodes.extend(node.alt_nodes_matching(key).map(|(_, n)| n));
+            next_nodes.extend(node.range_nodes_matching(key).map(|((_, _), n)| n));
+        }
+        next_nodes
+    }
+
+    /// 从nodes出发，之后一个或者多个任意token组成的key是否都能匹配。
+    /// 总有不等于任何子结点的token，它只能到达单层wildcard结点，而其他token能到达的结点都包含这些结点，
+    /// 因此只需要沿着单层wildcard结点检查
+    fn covers_suffixes(&self, mut nodes: Vec<&'t Node<'a, V, S, K, M>>) -> bool {
+        loop {
+            if nodes.iter().any(|n| !n.is_mwc_empty()) {
+                return true;
+            }
+            let owc_nodes: Vec<_> = nodes.iter().filter_map(|n| n.owc_node()).collect();
+            // 只多一个token的key需要由单层wildcard结点中的组匹配
+            let matched = owc_nodes
+                .iter()
+                .any(|n| !n.is_empty() || (self.mwc_includes_prefix && !n.is_mwc_empty()));
+            if !matched {
+                return false;
+            }
+            nodes = owc_nodes;
+        }
+    }
+
+    /// 返回与keys匹配并且包含value的所有组的tokens
+    pub(crate) fn patterns_containing(&self, keys: &[&K::Key], value: &V) -> Vec<Tokens<'a, K>> {
+        self.matched_groups(keys)
+            .into_iter()
+            .filter(|g| g.contains(value))
+            .map(|g| g.pattern.into())
+            .collect()
+    }
+
+    /// 返回与keys匹配的所有非空的组，以及每个组对应的tokens
+    fn matched_groups(&self, keys: &[&K::Key]) -> Vec<MatchedGroup<'t, 'a, V, S, K, M>> {
+        let mut groups = Vec::new();
+        let mut nodes = vec![(self.root, vec![])];
+        for key in keys.iter() {
+            let mut next_nodes = Vec::new();
+            for (node, pattern) in nodes.into_iter() {
+                // 多层wildcard必然满足tokens的需求
+                if !node.is_mwc_empty() {
+                    let mut mwc_pattern = pattern.clone();
+                    mwc_pattern.push(Token::MultiWildcard);
+                    groups.push(MatchedGroup { pattern: mwc_pattern, node, mwc: true });
+                }
+                // 符合当前token的node可以是token对应的，也可以是owc或者多选一token对应的
+                if let Some(n) = node.owc_node() {
+                    let mut owc_pattern = pattern.clone();
+                    owc_pattern.push(Token::OneWildcard(None));
+                    next_nodes.push((n, owc_pattern));
+                }
+                for (alternatives, n) in node.alt_nodes_matching(key) {
+                    let mut alt_pattern = pattern.clone();
+                    alt_pattern.push(Token::OneOf(alternatives.to_vec()));
+                    next_nodes.push((n, alt_pattern));
+                }
+                for ((lo, hi), n) in node.range_nodes_matching(key) {
+                    let mut range_pattern = pattern.clone();
+                    range_pattern.push(Token::NumRange(lo, hi));
+                    next_nodes.push((n, range_pattern));
+                }
+                if let Some((k, n)) = node.get_child_entry(key) {
+                    let mut child_pattern = pattern;
+                    child_pattern.push(Token::Normal(k.to_atom()));
+                    next_nodes.push((n, child_pattern));
+                }
+            }
+            // 如果是空node，那就不用查找了
+            if next_nodes.is_empty() {
+                return groups;
+            }
+            nodes = next_nodes;
+        }
+        for (node, pattern) in nodes.into_iter() {
+            // 多层wildcard组也匹配其前缀本身
+            if self.mwc_includes_prefix && !node.is_mwc_empty() {
+                let mut mwc_pattern = pattern.clone();
+                mwc_pattern.push(Token::MultiWildcard);
+                groups.push(MatchedGroup { pattern: mwc_pattern, node, mwc: true });
+            }
+            if !node.is_empty() {
+                groups.push(MatchedGroup { pattern, node, mwc: false });
+            }
+        }
+        groups
+    }
+
+    /// 只包含根结点的待处理node
+    fn root_nodes(&self) -> Nodes<'t, 'a, V, S, K, M> {
+        std::iter::once(self.root).collect()
+    }
+
+    /// 返回与keys匹配的所有node。第一个是多层wildcard组匹配的node，第二个是自身的组匹配的node，
+    /// 同时标记了到达该node是否经过了单层wildcard
+    fn matched_nodes<'k>(&self, keys: impl IntoIterator<Item = &'k K::Key>)
+        -> MatchedNodes<'t, 'a, V, S, K, M> {
+        let mut mwc_nodes = Frontier::new();
+        // 迭代key来获得最终node
+        let nodes = keys.into_iter()
+            // 待处理的nodes
+            .try_fold(std::iter::once((self.root, false)).collect(),
+                |nodes: TracedNodes<'t, 'a, V, S, K, M>, token| {
+                    // 如果是空node，那就不用查找了
+                    if nodes.is_empty() {
+                        return Err(());
+                    }
+
+                    let mut next_nodes: TracedNodes<'t, 'a, V, S, K, M> = Frontier::new();
+                    for (node, via_owc) in nodes.into_iter() {
+                        // 多层wildcard必然满足tokens的需求
+                        if !node.is_mwc_empty() {
+                            mwc_nodes.push(node);
+                        }
+                        // 符合当前token的node可以是token对应的，也可以是owc、多选一token或者数字范围token对应的
+                        next_nodes.extend(node.owc_node().map(|n| (n, true)));
+                        next_nodes.extend(node.alt_nodes_matching(token).map(|(_, n)| (n, true)));
+                        next_nodes.extend(node.range_nodes_matching(token).map(|(_, n)| (n, true)));
+                        if let Some(n) = node.get_child_node(token) {
+                            next_nodes.push((n, via_owc));
+                        }
+                    }
+                    Ok(next_nodes)
+                }).unwrap_or_default();
+        // 多层wildcard组也匹配其前缀本身
+        if self.mwc_includes_prefix {
+            mwc_nodes.extend(nodes.iter().map(|(n, _)| *n).filter(|n| !n.is_mwc_empty()));
+        }
+        (mwc_nodes, nodes)
+    }
+}
+
+// 返回值的复制的查询
+impl<'t, 'a, V, S, K, M> TrieView<'t, 'a, V, S, K, M>
+where
+    K: TokenAtom,
+    M: ChildMap,
+    V: Eq + Hash + Clone,
+    S: BuildHasher + Default,
+{
+    /// 返回以keys为字面前缀的子树中的所有值，keys中的每一项只与普通token对应的子结点比较
+    pub fn subtree_values<'k>(&self, keys: impl AsRef<[&'k K::Key]>) -> Vec<V> {
+        let mut values = Vec::new();
+        let node = keys.as_ref().iter()
+            .try_fold(self.root, |n, key| n.get_child_node(key));
+        if let Some(node) = node {
+            node.collect_subtree(&mut values);
+        }
+        values
+    }
+
+    /// 返回能与keys匹配的所有值，如果不存在键，返回空的Vec
+    pub fn find<'k>(&self, keys: impl AsRef<[&'k K::Key]>) -> Vec<V> {
+        // 没有wildcard时只有keys对应的结点可能匹配
+        if !self.has_wildcard {
+            return self.exact_node(keys.as_ref())
+                .map(|n| n.values_owned().collect())
+                .unwrap_or_default();
+        }
+        let (mwc_nodes, nodes) = self.matched_nodes(keys.as_ref().iter().copied());
+        // 先迭代mwc中的结果
+        mwc_nodes.into_iter().flat_map(|n| n.mwc_values_owned())
+            .chain(nodes.into_iter().flat_map(|(n, _)| n.values_owned()))
+            .collect()
+    }
+
+    /// 与`find`相同，但是没有匹配的值时区分是没有与keys匹配的结点，还是匹配到的结点中没有值
+    pub fn try_find<'k>(&self, keys: impl AsRef<[&'k K::Key]>) -> FindResult<V> {
+        let keys = keys.as_ref();
+        let (mwc_nodes, nodes) = self.matched_nodes(keys.iter().copied());
+        let values: Vec<V> = mwc_nodes.into_iter().flat_map(|n| n.mwc_values_owned())
+            .chain(nodes.iter().flat_map(|(n, _)| n.values_owned()))
+            .collect();
+        if !values.is_empty() {
+            FindResult::Matched(values)
+        } else if nodes.is_empty() {
+            FindResult::NoPath
+        } else {
+            FindResult::NoValues(self.exact_node(keys).is_some())
+        }
+    }
+
+    /// 返回能与keys匹配的所有值，按照从小到大的顺序排列，结果与`find`的相同但是顺序是确定的。
+    /// 需要对结果额外进行O(n log n)的排序，只关心匹配了哪些值时应当使用`find`
+    pub fn find_sorted<'k>(&self, keys: impl AsRef<[&'k K::Key]>) -> Vec<V>
+    where
+        V: Ord,
+    {
+        let mut values = self.find(keys);
+        values.sort_unstable();
+        values
+    }
+
+    /// 返回能与keys匹配的所有值组成的集合，同一个值出现在多个匹配的组中时只出现一次
+    pub fn find_set<'k>(&self, keys: impl AsRef<[&'k K::Key]>) -> HashSet<V> {
+        let (mwc_nodes, nodes) = self.matched_nodes(keys.as_ref().iter().copied());
+        mwc_nodes.into_iter().flat_map(|n| n.mwc_values_owned())
+            .chain(nodes.into_iter().flat_map(|(n, _)| n.values_owned()))
+            .collect()
+    }
+
+    /// 返回最多limit个能与keys匹配的值，收集到limit个之后立即停止匹配。
+    /// 返回哪些值没有特别的含义，但是对于同一棵没有修改过的树，结果总是相同的
+    pub fn find_limited<'k>(&self, keys: impl AsRef<[&'k K::Key]>, limit: usize) -> Vec<V> {
+        let mut values = Vec::new();
+        if limit == 0 {
+            return values;
+        }
+        let mut nodes = self.root_nodes();
+        for key in keys.as_ref().iter() {
+            let mut next_nodes: Nodes<'t, 'a, V, S, K, M> = Frontier::new();
+            for node in nodes.into_iter() {
+                // 多层wildcard必然满足tokens的需求
+                let room = limit - values.len();
+                values.extend(node.mwc_values_owned().take(room));
+                if values.len() == limit {
+                    return values;
+                }
+                // 符合当前token的node可以是token对应的，也可以是owc、多选一token或者数字范围token对应的
+                next_nodes.extend(node.owc_node());
+                next_nodes.extend(node.alt_nodes_matching(key).map(|(_, n)| n));
+                next_nodes.extend(node.range_nodes_matching(key).map(|(_, n)| n));
+                if let Some(n) = node.get_child_node(key) {
+                    next_nodes.push(n);
+                }
+            }
+            // 如果是空node，那就不用查找了
+            if next_nodes.is_empty() {
+                return values;
+            }
+            nodes = next_nodes;
+        }
+        for node in nodes.into_iter() {
+            // 多层wildcard组也匹配其前缀本身
+            if self.mwc_includes_prefix {
+                let room = limit - values.len();
+                values.extend(node.mwc_values_owned().take(room));
+            }
+            let room = limit - values.len();
+            values.extend(node.values_owned().take(room));
+            if values.len() == limit {
+                break;
+            }
+        }
+        values
+    }
+
+    /// 返回能与keys匹配的所有值，以及收集到该值时已经匹配的key的数量。
+    /// 多层wildcard组中的值为多层wildcard所在的位置，其它组中的值为keys的长度
+    pub fn find_with_depth<'k>(&self, keys: impl AsRef<[&'k K::Key]>) -> Vec<(V, usize)> {
+        let keys = keys.as_ref();
+        let mut values = Vec::new();
+        let mut nodes = self.root_nodes();
+        for (depth, key) in keys.iter().enumerate() {
+            let mut next_nodes: Nodes<'t, 'a, V, S, K, M> = Frontier::new();
+            for node in nodes.into_iter() {
+                // 多层wildcard必然满足tokens的需求，之后的key都不需要再匹配
+                values.extend(node.mwc_values_owned().map(|v| (v, depth)));
+                // 符合当前token的node可以是token对应的，也可以是owc、多选一token或者数字范围token对应的
+                next_nodes.extend(node.owc_node());
+                next_nodes.extend(node.alt_nodes_matching(key).map(|(_, n)| n));
+                next_nodes.extend(node.range_nodes_matching(key).map(|(_, n)| n));
+                if let Some(n) = node.get_child_node(key) {
+                    next_nodes.push(n);
+                }
+            }
+            // 如果是空node，那就不用查找了
+            if next_nodes.is_empty() {
+                return values;
+            }
+            nodes = next_nodes;
+        }
+        let depth = keys.len();
+        for node in nodes.into_iter() {
+            // 多层wildcard组也匹配其前缀本身
+            if self.mwc_includes_prefix {
+                values.extend(node.mwc_values_owned().map(|v| (v, depth)));
+            }
+            values.extend(node.values_owned().map(|v| (v, depth)));
+        }
+        values
+    }
+
+    /// 与`find`相同，但是keys由迭代器逐个给出，匹配过程中按需消耗，不需要先收集为slice
+    pub fn find_iter_keys<'k>(&self, keys: impl IntoIterator<Item = &'k K::Key>) -> Vec<V> {
+        let (mwc_nodes, nodes) = self.matched_nodes(keys);
+        mwc_nodes.into_iter().flat_map(|n| n.mwc_values_owned())
+            .chain(nodes.into_iter().flat_map(|(n, _)| n.values_owned()))
+            .collect()
+    }
+
+    /// 返回能与keys匹配的所有值，以及每个值所在的组中带名字的单层wildcard匹配到的key
+    pub fn find_captures<'k>(&self, keys: impl AsRef<[&'k K::Key]>) -> Vec<(V, HashMap<&'t str, &'k K::Key>)> {
+        let mut matches = Vec::new();
+        let mut push_group = |node: &'t Node<'a, V, S, K, M>, captured: &[&'k K::Key], mwc: bool| {
+            let bound = node.bind_captures(captured, mwc);
+            let values = if mwc { node.mwc_values() } else { node.values() };
+            matches.extend(values.map(|v| (v.clone(), bound.clone())));
+        };
+        // 每个待处理的node带有到达它的路径上各个单层wildcard匹配的key
+        let mut nodes = vec![(self.root, Vec::new())];
+        for &key in keys.as_ref().iter() {
+            let mut next_nodes = Vec::new();
+            for (node, captured) in nodes.into_iter() {
+                // 多层wildcard必然满足tokens的需求
+                if !node.is_mwc_empty() {
+                    push_group(node, &captured, true);
+                }
+                if let Some(n) = node.owc_node() {
+                    let mut owc_captured = captured.clone();
+                    owc_captured.push(key);
+                    next_nodes.push((n, owc_captured));
+                }
+                next_nodes.extend(node.alt_nodes_matching(key).map(|(_, n)| (n, captured.clone())));
+                next_nodes.extend(node.range_nodes_matching(key).map(|(_, n)| (n, captured.clone())));
+                if let Some(n) = node.get_child_node(key) {
+                    next_nodes.push((n, captured));
+                }
+            }
+            nodes = next_nodes;
+        }
+        for (node, captured) in nodes.into_iter() {
+            if self.mwc_includes_prefix && !node.is_mwc_empty() {
+                push_group(node, &captured, true);
+            }
+            push_group(node, &captured, false);
+        }
+        matches
+    }
+
+    /// 返回能与keys匹配的所有值以及其来源组的具体程度，按照具体程度从高到低排列。
+    /// 不含wildcard的组为2，含有单层wildcard或者多选一token的组为1，多层wildcard组为0
+    pub fn find_ranked<'k>(&self, keys: impl AsRef<[&'k K::Key]>) -> Vec<(u32, V)> {
+        self.find_ranked_by(keys, &MatchPriority::ExactFirst)
+    }
+
+    /// 与`find_ranked`相同，但是来自不同组的值按照priority排列
+    pub fn find_ranked_by<'k>(&self, keys: impl AsRef<[&'k K::Key]>, priority: &MatchPriority<K>) -> Vec<(u32, V)> {
+        let mut groups: Vec<(u32, Tokens<'a, K>, Vec<V>)> = self.matched_groups(keys.as_ref())
+            .into_iter()
+            .map(|g| {
+                let score = if g.mwc {
+                    0
+                } else if g.pattern.iter().any(|t| !matches!(t, Token::Normal(_))) {
+                    1
+                } else {
+                    2
+                };
+                let values = g.values_owned();
+                (score, g.pattern.into(), values)
+            })
+            .collect();
+        match priority {
+            MatchPriority::ExactFirst => groups.sort_by_key(|(score, _, _)| Reverse(*score)),
+            MatchPriority::LeftmostSpecific => groups.sort_by(|(_, a, _), (_, b, _)| {
+                b.0.iter().map(token_rank).cmp(a.0.iter().map(token_rank))
+            }),
+            MatchPriority::Custom(cmp) => groups.sort_by(|(_, a, _), (_, b, _)| cmp(a, b)),
+        }
+        groups.into_iter()
+            .flat_map(|(score, _, values)| values.into_iter().map(move |v| (score, v)))
+            .collect()
+    }
+
+    /// 在所有与keys匹配的组中，返回最具体的一个组的tokens和其中的值，不存在时返回None。
+    /// 普通token越多越具体；数量相同时wildcard越少越具体；仍然相同时从左向右逐个比较，
+    /// 普通token比多选一token和数字范围token具体，它们比单层wildcard具体，单层wildcard比多层wildcard具体
+    pub fn longest_match<'k>(&self, keys: impl AsRef<[&'k K::Key]>) -> Option<(Tokens<'a, K>, Vec<V>)> {
+        self.matched_groups(keys.as_ref())
+            .into_iter()
+            .max_by(|a, b| compare_specificity(&a.pattern, &b.pattern))
+            .map(|g| {
+                let values = g.values_owned();
+                (g.pattern.into(), values)
+            })
+    }
+    /// 返回tokens对应的组中的所有值，不进行wildcard匹配
+    pub fn values_at(&self, tokens: &Tokens<'a, K>) -> Vec<V> {
+        match self.find_node(tokens) {
+            (None, _) => vec![],
+            (Some(node), true) => node.mwc_values_owned().collect(),
+            (Some(node), false) => node.values_owned().collect(),
+        }
+    }
+
+    /// 返回与pattern有交集的所有组中的值，即存在某个key同时与pattern和组的tokens匹配，
+    /// 与`patterns_overlap`的判断一致。pattern中的单层wildcard恰好对应一个token，
+    /// 因此`a.*`与`a.b`、`a.*`和`a.>`有交集，与`a`和`a.b.c`没有交集；
+    /// pattern中的多层wildcard对应一个或者多个token，`a.>`与`a.*`、`a.b.c`和`a.>`有交集，与`a`没有交集。
+    /// 组的多层wildcard是否也匹配其前缀本身遵循`set_mwc_includes_prefix`，pattern中的多层wildcard则总是至少对应一个token。
+    /// 同一个值在多个组中时会重复出现
+    pub fn find_pattern<Q>(&self, pattern: &Tokens<Q>) -> Vec<V>
+    where
+        Q: TokenAtom<Key = K::Key>,
+    {
+        let mut values = Vec::new();
+        let mut nodes = vec![(self.root, 0)];
+        while let Some((node, i)) = nodes.pop() {
+            let token = match pattern.0.get(i) {
+                Some(token) => token,
+                None => {
+                    values.extend(node.values_owned());
+                    if self.mwc_includes_prefix {
+                        values.extend(node.mwc_values_owned());
+                    }
+                    continue;
+                },
+            };
+            // 多层wildcard组对应pattern剩下的一个或者多个token
+            values.extend(node.mwc_values_owned());
+            match token {
+                // pattern的多层wildcard对应子结点之下的所有组
+                Token::MultiWildcard => {
+                    for (_, child) in node.token_child_nodes() {
+                        child.collect_subtree(&mut values);
+                    }
+                },
+                Token::Normal(key) => {
+                    let key = key.key();
+                    nodes.extend(node.owc_node().map(|n| (n, i + 1)));
+                    nodes.extend(node.alt_nodes_matching(key).map(|(_, n)| (n, i + 1)));
+                    nodes.extend(node.range_nodes_matching(key).map(|(_, n)| (n, i + 1)));
+                    nodes.extend(node.get_child_node(key).map(|n| (n, i + 1)));
+                },
+                _ => nodes.extend(node.token_child_nodes()
+                    .filter(|(t, _)| tokens_overlap(token, t))
+                    .map(|(_, n)| (n, i + 1))),
+            }
+        }
+        values
+    }
+}
+
+impl<'t, 'a, V, S, K, M> TrieView<'t, 'a, V, S, K, M>
+where
+    K: TokenAtom,
+    M: ChildMap,
+    V: Eq + Hash + Debug,
+    S: BuildHasher + Default,
+{
+    /// 将trie树的结构渲染为缩进的文本，用于调试。每个子结点按照其token占一行，
+    /// 单层wildcard显示为`*`，多选一token显示为`(b|c)`，数字范围token显示为`[1-100]`，
+    /// 有值的结点标注为`c => {1, 2}`，多层wildcard组标注为`=> >{3}`
+    pub fn debug_tree(&self) -> String {
+        let mut out = String::new();
+        // 根结点上的组对应空的tokens
+        let mut root = String::new();
+        self.root.write_groups(&mut root);
+        if !root.is_empty() {
+            out.push_str("<root>");
+            out.push_str(&root);
+            out.push('\n');
+        }
+        self.root.write_tree(&mut out, 0);
+        out
+    }
+}
+
+// 比较两个tokens的具体程度
+fn compare_specificity<K>(a: &[Token<K>], b: &[Token<K>]) -> Ordering {
+    let normals = |p: &[Token<K>]| p.iter().filter(|t| matches!(t, Token::Normal(_))).count();
+    normals(a).cmp(&normals(b))
+        .then_with(|| (b.len() - normals(b)).cmp(&(a.len() - normals(a))))
+        .then_with(|| a.iter().map(token_rank).cmp(b.iter().map(token_rank)))
+}
+
+// 备选token都是数字时，返回其中最小和最大的数字
+fn num_bounds<K: TokenAtom>(alternatives: &[K]) -> Option<(u64, u64)> {
+    alternatives.iter().try_fold(None, |bounds: Option<(u64, u64)>, a| {
+        let n = K::key_to_num(a.key())?;
+        Some(Some(bounds.map_or((n, n), |(lo, hi)| (lo.min(n), hi.max(n)))))
+    })?
+}
+
+// 每个token的具体程度
+fn token_rank<K>(t: &Token<K>) -> u8 {
+    match t {
+        Token::Normal(_) => 3,
+        Token::OneOf(_) | Token::NumRange(..) => 2,
+        Token::OneWildcard(_) => 1,
+        Token::MultiWildcard => 0,
+    }
+}